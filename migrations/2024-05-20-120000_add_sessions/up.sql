@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE sessions (
+    id SERIAL PRIMARY KEY,
+    jti TEXT NOT NULL,
+    user_id INT NOT NULL,
+    refresh_token_hash TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    UNIQUE (jti),
+    UNIQUE (refresh_token_hash)
+);
+
+SELECT diesel_manage_updated_at('sessions');
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE login_attempts;
+ALTER TABLE users DROP COLUMN locked_until;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE user_identities;
+DROP TABLE oidc_login_states;
@@ -0,0 +1,17 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE classes_students
+    DROP CONSTRAINT classes_students_student_id_fkey,
+    ADD CONSTRAINT classes_students_student_id_fkey
+        FOREIGN KEY (student_id) REFERENCES users(id);
+
+ALTER TABLE avatars
+    DROP CONSTRAINT avatars_user_id_fkey,
+    ADD CONSTRAINT avatars_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id);
+
+DELETE FROM student_answers WHERE user_id IS NULL;
+ALTER TABLE student_answers
+    DROP CONSTRAINT student_answers_user_id_fkey,
+    ADD CONSTRAINT student_answers_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id);
+ALTER TABLE student_answers ALTER COLUMN user_id SET NOT NULL;
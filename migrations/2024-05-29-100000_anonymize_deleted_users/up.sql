@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- Answers of deleted users are kept for the exam statistics, without the user.
+ALTER TABLE student_answers ALTER COLUMN user_id DROP NOT NULL;
+ALTER TABLE student_answers
+    DROP CONSTRAINT student_answers_user_id_fkey,
+    ADD CONSTRAINT student_answers_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE SET NULL;
+
+ALTER TABLE avatars
+    DROP CONSTRAINT avatars_user_id_fkey,
+    ADD CONSTRAINT avatars_user_id_fkey
+        FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
+
+ALTER TABLE classes_students
+    DROP CONSTRAINT classes_students_student_id_fkey,
+    ADD CONSTRAINT classes_students_student_id_fkey
+        FOREIGN KEY (student_id) REFERENCES users(id) ON DELETE CASCADE;
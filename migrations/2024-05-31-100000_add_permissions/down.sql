@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE role_permissions;
+DROP TABLE permissions;
+DELETE FROM roles WHERE name NOT IN ('ADMIN', 'STUDENT', 'TEACHER', 'MONITOR');
+ALTER TABLE roles DROP COLUMN built_in;
+ALTER TABLE roles DROP COLUMN description;
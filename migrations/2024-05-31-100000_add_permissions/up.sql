@@ -0,0 +1,41 @@
+-- Your SQL goes here
+ALTER TABLE roles ADD COLUMN description TEXT NOT NULL DEFAULT '';
+ALTER TABLE roles ADD COLUMN built_in BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE roles SET built_in = TRUE, description = 'Manages the whole platform' WHERE name = 'ADMIN';
+UPDATE roles SET built_in = TRUE, description = 'Attends classes and takes exams' WHERE name = 'STUDENT';
+UPDATE roles SET built_in = TRUE, description = 'Runs classes, questions and exams' WHERE name = 'TEACHER';
+UPDATE roles SET built_in = TRUE, description = 'Watches over the exams they are assigned to' WHERE name = 'MONITOR';
+
+CREATE TABLE permissions (
+    "name" TEXT NOT NULL PRIMARY KEY,
+    description TEXT NOT NULL
+);
+
+INSERT INTO permissions (name, description) VALUES
+    ('all', 'Every action on every resource'),
+    ('users:manage', 'List, provision, deactivate and unlock users and set their roles'),
+    ('roles:manage', 'Create, change and delete roles'),
+    ('classes:teach', 'Create classes and manage the ones they teach'),
+    ('classes:attend', 'Enroll in classes'),
+    ('questions:manage', 'Create questions and edit the ones only their classes use'),
+    ('exams:manage', 'Create and manage the exams of the classes they teach'),
+    ('exams:monitor', 'Follow the exams they are assigned to'),
+    ('exams:take', 'Take the exams of the classes they are enrolled in');
+
+CREATE TABLE role_permissions (
+    role_name TEXT NOT NULL,
+    permission_name TEXT NOT NULL,
+    PRIMARY KEY (role_name, permission_name),
+    FOREIGN KEY (role_name) REFERENCES roles (name) ON DELETE CASCADE,
+    FOREIGN KEY (permission_name) REFERENCES permissions (name) ON DELETE CASCADE
+);
+
+INSERT INTO role_permissions (role_name, permission_name) VALUES
+    ('ADMIN', 'all'),
+    ('TEACHER', 'classes:teach'),
+    ('TEACHER', 'questions:manage'),
+    ('TEACHER', 'exams:manage'),
+    ('STUDENT', 'classes:attend'),
+    ('STUDENT', 'exams:take'),
+    ('MONITOR', 'exams:monitor');
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE class_staff (
+    class_id INT NOT NULL,
+    user_id INT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'co_teacher', 'monitor')),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (class_id) REFERENCES classes(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    PRIMARY KEY (class_id, user_id)
+);
+
+CREATE UNIQUE INDEX class_staff_one_owner ON class_staff (class_id) WHERE role = 'owner';
+
+INSERT INTO class_staff (class_id, user_id, role)
+SELECT id, user_id, 'owner' FROM classes;
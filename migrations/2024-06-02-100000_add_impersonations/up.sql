@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE impersonations (
+    id SERIAL PRIMARY KEY,
+    jti TEXT NOT NULL,
+    actor_id INT NOT NULL,
+    user_id INT NOT NULL,
+    reason TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (actor_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    UNIQUE (jti)
+);
+
+CREATE INDEX impersonations_actor_id_idx ON impersonations (actor_id);
+CREATE INDEX impersonations_user_id_idx ON impersonations (user_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM permissions WHERE name = 'audit:read';
+DROP TABLE audit_events;
+DROP FUNCTION audit_events_append_only;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Set when the avatar is an uploaded image kept in the blob store, NULL for external urls.
+ALTER TABLE avatars ADD COLUMN image_etag TEXT;
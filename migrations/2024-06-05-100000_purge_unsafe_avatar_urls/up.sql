@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- Urls are now checked when set. The ones stored before that and no client should load
+-- are flagged, and kept out of responses until the user sets a new avatar.
+-- Uploaded avatars point at our own relative url and are left alone.
+ALTER TABLE avatars ADD COLUMN url_blocked BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE avatars
+SET url_blocked = TRUE
+WHERE image_etag IS NULL
+    AND ("url" !~* '^https?://[^/]' OR "url" ~* '^https?://(localhost|127\.|10\.|172\.(1[6-9]|2[0-9]|3[01])\.|192\.168\.|169\.254\.|\[)');
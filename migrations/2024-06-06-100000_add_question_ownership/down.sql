@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+UPDATE permissions SET description = 'Create questions and edit the ones only their classes use'
+WHERE name = 'questions:manage';
+
+ALTER TABLE questions DROP COLUMN visibility, DROP COLUMN author_id;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+ALTER TABLE questions
+    ADD COLUMN author_id INT REFERENCES users(id) ON DELETE SET NULL,
+    ADD COLUMN visibility TEXT NOT NULL DEFAULT 'private'
+        CHECK (visibility IN ('private', 'shared', 'public'));
+
+-- Questions written before authors were recorded were open to every teacher, they stay
+-- in the public bank.
+UPDATE questions SET visibility = 'public';
+
+CREATE INDEX questions_author_id_idx ON questions (author_id);
+
+UPDATE permissions SET description = 'Create questions, edit their own and use the ones shared with them'
+WHERE name = 'questions:manage';
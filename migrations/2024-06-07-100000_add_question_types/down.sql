@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM student_answers WHERE answer_id IS NULL;
+ALTER TABLE student_answers
+    DROP CONSTRAINT student_answers_answered,
+    DROP COLUMN response,
+    ALTER COLUMN answer_id SET NOT NULL;
+
+DELETE FROM exam_questions WHERE question_id IN (SELECT id FROM questions WHERE question_type <> 'single_choice');
+DELETE FROM answers WHERE question_id IN (SELECT id FROM questions WHERE question_type <> 'single_choice');
+DELETE FROM questions WHERE question_type <> 'single_choice';
+ALTER TABLE questions DROP COLUMN settings, DROP COLUMN question_type;
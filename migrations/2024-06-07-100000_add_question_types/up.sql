@@ -0,0 +1,13 @@
+-- Your SQL goes here
+-- Settings hold what each type needs to be graded, see `question::dto::QuestionSettings`.
+ALTER TABLE questions
+    ADD COLUMN question_type TEXT NOT NULL DEFAULT 'single_choice'
+        CHECK (question_type IN ('single_choice', 'multi_select', 'true_false', 'numeric', 'short_text', 'essay')),
+    ADD COLUMN settings JSONB NOT NULL DEFAULT '{}';
+
+-- Single choice answers keep pointing at the chosen answer, every other type keeps what
+-- the student answered in `response`.
+ALTER TABLE student_answers
+    ALTER COLUMN answer_id DROP NOT NULL,
+    ADD COLUMN response JSONB,
+    ADD CONSTRAINT student_answers_answered CHECK (answer_id IS NOT NULL OR response IS NOT NULL);
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE exams DROP COLUMN grades_released_at;
+
+ALTER TABLE student_answers
+    DROP COLUMN graded_at,
+    DROP COLUMN graded_by,
+    DROP COLUMN feedback,
+    DROP COLUMN manual_score;
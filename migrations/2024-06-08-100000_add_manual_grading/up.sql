@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- Answers that need a person to grade them get a score between 0 and 1 and feedback.
+ALTER TABLE student_answers
+    ADD COLUMN manual_score REAL CHECK (manual_score BETWEEN 0 AND 1),
+    ADD COLUMN feedback TEXT,
+    ADD COLUMN graded_by INT REFERENCES users (id) ON DELETE SET NULL,
+    ADD COLUMN graded_at TIMESTAMP;
+
+-- Students only see manual scores once the grades are released.
+ALTER TABLE exams ADD COLUMN grades_released_at TIMESTAMP;
@@ -0,0 +1,28 @@
+-- This file should undo anything in `up.sql`
+-- Only the latest version of each question survives.
+UPDATE questions SET "question" = question_versions."question",
+    question_type = question_versions.question_type,
+    settings = question_versions.settings
+FROM question_versions
+WHERE question_versions.question_id = questions.id AND question_versions."version" = questions."version";
+
+ALTER TABLE student_answers DROP COLUMN question_version_id;
+ALTER TABLE exam_questions DROP COLUMN question_version_id;
+
+UPDATE student_answers SET answer_id = NULL
+WHERE answer_id IN (
+    SELECT answers.id FROM answers
+    JOIN question_versions ON question_versions.id = answers.question_version_id
+    JOIN questions ON questions.id = question_versions.question_id
+    WHERE question_versions."version" <> questions."version"
+);
+DELETE FROM student_answers WHERE answer_id IS NULL AND response IS NULL;
+DELETE FROM answers
+USING question_versions, questions
+WHERE question_versions.id = answers.question_version_id
+    AND questions.id = question_versions.question_id
+    AND question_versions."version" <> questions."version";
+ALTER TABLE answers DROP COLUMN question_version_id;
+
+ALTER TABLE questions DROP COLUMN "version";
+DROP TABLE question_versions;
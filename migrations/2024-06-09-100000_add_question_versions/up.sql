@@ -0,0 +1,35 @@
+-- Your SQL goes here
+-- Questions are never edited in place: each edit adds a version, with its own answers. The
+-- questions row keeps the latest one for listing and searching.
+CREATE TABLE question_versions (
+    id SERIAL PRIMARY KEY,
+    question_id INT NOT NULL REFERENCES questions (id) ON DELETE CASCADE,
+    "version" INT NOT NULL,
+    "question" VARCHAR NOT NULL,
+    question_type TEXT NOT NULL,
+    settings JSONB NOT NULL,
+    created_by INT REFERENCES users (id) ON DELETE SET NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (question_id, "version")
+);
+
+INSERT INTO question_versions (question_id, "version", "question", question_type, settings, created_by, created_at)
+SELECT id, 1, "question", question_type, settings, author_id, created_at FROM questions;
+
+ALTER TABLE questions ADD COLUMN "version" INT NOT NULL DEFAULT 1;
+
+ALTER TABLE answers ADD COLUMN question_version_id INT REFERENCES question_versions (id) ON DELETE CASCADE;
+UPDATE answers SET question_version_id = question_versions.id
+FROM question_versions WHERE question_versions.question_id = answers.question_id;
+ALTER TABLE answers ALTER COLUMN question_version_id SET NOT NULL;
+
+-- Exams pin the version they ask, answers remember the version the student saw.
+ALTER TABLE exam_questions ADD COLUMN question_version_id INT REFERENCES question_versions (id);
+UPDATE exam_questions SET question_version_id = question_versions.id
+FROM question_versions WHERE question_versions.question_id = exam_questions.question_id;
+ALTER TABLE exam_questions ALTER COLUMN question_version_id SET NOT NULL;
+
+ALTER TABLE student_answers ADD COLUMN question_version_id INT REFERENCES question_versions (id);
+UPDATE student_answers SET question_version_id = question_versions.id
+FROM question_versions WHERE question_versions.question_id = student_answers.question_id;
+ALTER TABLE student_answers ALTER COLUMN question_version_id SET NOT NULL;
@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE question_search;
+
+ALTER TABLE questions
+    DROP COLUMN metadata,
+    DROP COLUMN difficulty,
+    DROP COLUMN topic_id,
+    DROP COLUMN tags;
+
+DROP TABLE topics;
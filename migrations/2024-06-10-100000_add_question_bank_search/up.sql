@@ -0,0 +1,39 @@
+-- Your SQL goes here
+CREATE TABLE topics (
+    id SERIAL PRIMARY KEY,
+    "name" TEXT NOT NULL,
+    parent_id INT REFERENCES topics (id),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX topics_parent_id_name_idx ON topics (COALESCE(parent_id, 0), LOWER("name"));
+
+-- Tags, topic, difficulty and metadata organize the bank, they aren't versioned.
+ALTER TABLE questions
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN topic_id INT REFERENCES topics (id) ON DELETE SET NULL,
+    ADD COLUMN difficulty TEXT CHECK (difficulty IN ('easy', 'medium', 'hard')),
+    ADD COLUMN metadata JSONB NOT NULL DEFAULT '{}';
+
+CREATE INDEX questions_tags_idx ON questions USING GIN (tags);
+CREATE INDEX questions_topic_id_idx ON questions (topic_id);
+
+-- Search document over the latest version of each question and its answers, kept up to
+-- date by `question::repository`.
+CREATE TABLE question_search (
+    question_id INT PRIMARY KEY REFERENCES questions (id) ON DELETE CASCADE,
+    document TSVECTOR NOT NULL
+);
+
+CREATE INDEX question_search_document_idx ON question_search USING GIN (document);
+
+INSERT INTO question_search (question_id, document)
+SELECT questions.id,
+    setweight(to_tsvector('simple', questions."question"), 'A')
+        || setweight(to_tsvector('simple', array_to_string(questions.tags, ' ')), 'B')
+        || setweight(to_tsvector('simple', COALESCE(string_agg(answers."answer", ' '), '')), 'C')
+FROM questions
+LEFT JOIN question_versions
+    ON question_versions.question_id = questions.id AND question_versions."version" = questions."version"
+LEFT JOIN answers ON answers.question_version_id = question_versions.id
+GROUP BY questions.id;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE oidc_login_states
+    ADD COLUMN link_user_id INT REFERENCES users(id) ON DELETE CASCADE;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX sessions_previous_refresh_token_hash_idx;
+
+ALTER TABLE sessions DROP COLUMN previous_refresh_token_hash;
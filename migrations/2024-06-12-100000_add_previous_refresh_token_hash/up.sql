@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE sessions ADD COLUMN previous_refresh_token_hash TEXT;
+
+CREATE INDEX sessions_previous_refresh_token_hash_idx ON sessions (previous_refresh_token_hash);
@@ -0,0 +1,42 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{auth::models::LoggedUser, errors::ServiceError};
+
+use super::{dto::CreateApiTokenInputDto, service};
+
+pub async fn create_api_token(
+    req: HttpRequest,
+    input: web::Json<CreateApiTokenInputDto>,
+) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::create_api_token(user, input.into_inner()) {
+        Ok(token) => HttpResponse::Created().json(token),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn list_api_tokens(req: HttpRequest) -> impl Responder {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::list_api_tokens(user.id) {
+        Ok(tokens) => HttpResponse::Ok().json(tokens),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn revoke_api_token(req: HttpRequest, path: web::Path<i32>) -> impl Responder {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::revoke_api_token(user.id, path.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
@@ -0,0 +1,72 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::policy::SCOPES;
+
+use super::model::ApiToken;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiTokenInputDto {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl CreateApiTokenInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("Name is required".to_string());
+        }
+
+        if self.scopes.is_empty() {
+            return Err("At least one scope is required".to_string());
+        }
+
+        if let Some(scope) = self.scopes.iter().find(|s| !SCOPES.contains(&s.as_str())) {
+            return Err(format!("Unknown scope {}", scope));
+        }
+
+        if let Some(expires_at) = self.expires_at {
+            if expires_at <= chrono::Utc::now().naive_utc() {
+                return Err("Expiration must be in the future".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiTokenOutputDto {
+    pub id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<ApiToken> for ApiTokenOutputDto {
+    fn from(token: ApiToken) -> Self {
+        ApiTokenOutputDto {
+            id: token.id,
+            name: token.name,
+            prefix: token.prefix,
+            scopes: token.scopes,
+            expires_at: token.expires_at,
+            last_used_at: token.last_used_at,
+            revoked_at: token.revoked_at,
+            created_at: token.created_at,
+        }
+    }
+}
+
+/// Only returned once, right after creation: the plain token is never stored.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiTokenOutputDto {
+    pub token: String,
+    #[serde(flatten)]
+    pub api_token: ApiTokenOutputDto,
+}
@@ -0,0 +1,5 @@
+pub mod controller;
+mod dto;
+mod model;
+mod repository;
+pub mod service;
@@ -0,0 +1,75 @@
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+use crate::{db::DB_MANAGER, errors::ServiceError, schema::api_tokens};
+
+use super::model::{ApiToken, NewApiToken};
+
+pub fn create_api_token(new_token: NewApiToken) -> Result<ApiToken, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let token = diesel::insert_into(api_tokens::table)
+        .values(&new_token)
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(token)
+}
+
+pub fn get_api_token_by_id(token_id: i32) -> Result<Option<ApiToken>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let token = api_tokens::table
+        .find(token_id)
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(token)
+}
+
+pub fn get_api_token_by_prefix(prefix: &str) -> Result<Option<ApiToken>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let token = api_tokens::table
+        .filter(api_tokens::prefix.eq(prefix))
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(token)
+}
+
+pub fn list_api_tokens_by_user_id(user_id: i32) -> Result<Vec<ApiToken>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let tokens = api_tokens::table
+        .filter(api_tokens::user_id.eq(user_id))
+        .order(api_tokens::created_at.desc())
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(tokens)
+}
+
+pub fn revoke_api_token(token_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(api_tokens::table.find(token_id))
+        .filter(api_tokens::revoked_at.is_null())
+        .set(api_tokens::revoked_at.eq(chrono::Utc::now().naive_utc()))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn touch_api_token(token_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(api_tokens::table.find(token_id))
+        .set(api_tokens::last_used_at.eq(chrono::Utc::now().naive_utc()))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
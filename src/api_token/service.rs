@@ -0,0 +1,143 @@
+use crate::{
+    auth::{crypto, mfa, models::LoggedUser},
+    errors::ServiceError,
+    role, user,
+};
+
+use super::{
+    dto::{ApiTokenOutputDto, CreateApiTokenInputDto, CreateApiTokenOutputDto},
+    model::NewApiToken,
+    repository,
+};
+
+/// Personal access tokens look like `mp_<prefix>_<secret>`. The prefix is stored in clear
+/// to find the token, the whole token is only kept hashed.
+pub const TOKEN_PREFIX: &str = "mp_";
+
+const PREFIX_LEN: usize = 8;
+const SECRET_LEN: usize = 32;
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// Tokens don't carry a second factor, users whose role requires one must have enrolled it.
+fn check_mfa(user_id: i32, roles: &[String]) -> Result<(), ServiceError> {
+    if mfa::is_required_for(roles) && !mfa::is_enrolled(user_id)? {
+        return Err(ServiceError::Forbidden);
+    }
+
+    Ok(())
+}
+
+pub fn create_api_token(
+    user: &LoggedUser,
+    input: CreateApiTokenInputDto,
+) -> Result<CreateApiTokenOutputDto, ServiceError> {
+    check_mfa(user.id, &user.roles)?;
+
+    let prefix = crypto::generate_opaque_token(PREFIX_LEN);
+    let token = format!(
+        "{}{}_{}",
+        TOKEN_PREFIX,
+        prefix,
+        crypto::generate_opaque_token(SECRET_LEN)
+    );
+
+    let mut scopes = input.scopes;
+    scopes.sort();
+    scopes.dedup();
+
+    let api_token = repository::create_api_token(NewApiToken {
+        user_id: user.id,
+        name: input.name.trim(),
+        prefix: &prefix,
+        token_hash: &crypto::hash_token(&token),
+        scopes: &scopes,
+        expires_at: input.expires_at,
+    })?;
+
+    Ok(CreateApiTokenOutputDto {
+        token,
+        api_token: api_token.into(),
+    })
+}
+
+pub fn list_api_tokens(user_id: i32) -> Result<Vec<ApiTokenOutputDto>, ServiceError> {
+    let tokens = repository::list_api_tokens_by_user_id(user_id)?;
+
+    Ok(tokens.into_iter().map(ApiTokenOutputDto::from).collect())
+}
+
+pub fn revoke_api_token(user_id: i32, token_id: i32) -> Result<(), ServiceError> {
+    match repository::get_api_token_by_id(token_id)? {
+        Some(token) if token.user_id == user_id => repository::revoke_api_token(token_id),
+        _ => Err(ServiceError::BadRequest("Token not found".to_string())),
+    }
+}
+
+/// Resolves a personal access token into the user it acts for, limited to its scopes.
+/// Tokens can only be created from sessions that met the account policies, so only the
+/// email verification is checked again here, along with the second factor in case the
+/// user's roles started requiring one since.
+pub fn authenticate(
+    token: &str,
+    enforce_account_policies: bool,
+) -> Result<LoggedUser, ServiceError> {
+    let prefix = token
+        .strip_prefix(TOKEN_PREFIX)
+        .and_then(|rest| rest.split_once('_'))
+        .map(|(prefix, _)| prefix)
+        .ok_or(ServiceError::Unauthorized)?;
+
+    let api_token = match repository::get_api_token_by_prefix(prefix)? {
+        Some(api_token) => api_token,
+        None => return Err(ServiceError::Unauthorized),
+    };
+
+    let now = chrono::Utc::now().naive_utc();
+
+    if api_token.token_hash != crypto::hash_token(token)
+        || api_token.revoked_at.is_some()
+        || api_token
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= now)
+    {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    let user = match user::service::get_user_with_roles_by_id(api_token.user_id)? {
+        Some(user) => user,
+        None => return Err(ServiceError::Unauthorized),
+    };
+
+    if !user.active {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    if enforce_account_policies
+        && user::service::is_email_verification_required()
+        && user.email_verified_at.is_none()
+    {
+        return Err(ServiceError::Forbidden);
+    }
+
+    check_mfa(user.id, &user.roles)?;
+
+    // Scripts may hit the api many times a second, a minute is precise enough here.
+    if api_token
+        .last_used_at
+        .is_none_or(|last_used_at| now - last_used_at > chrono::Duration::minutes(1))
+    {
+        repository::touch_api_token(api_token.id)?;
+    }
+
+    Ok(LoggedUser {
+        id: user.id,
+        permissions: role::service::permissions_of(&user.roles)?,
+        roles: user.roles,
+        jti: String::new(),
+        scopes: Some(api_token.scopes),
+        impersonator_id: None,
+    })
+}
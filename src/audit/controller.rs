@@ -0,0 +1,33 @@
+use actix_web::{http::header, web, HttpResponse, Responder};
+
+use crate::errors::ServiceError;
+
+use super::{dto::AuditEventsQueryDto, service};
+
+pub async fn list_events(query: web::Query<AuditEventsQueryDto>) -> impl Responder {
+    if let Err(e) = query.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    match service::list_events(query.into_inner()) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn export_events(query: web::Query<AuditEventsQueryDto>) -> impl Responder {
+    if let Err(e) = query.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    match service::export_events(query.into_inner()) {
+        Ok(lines) => HttpResponse::Ok()
+            .content_type("application/x-ndjson")
+            .insert_header((
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"audit-events.jsonl\"",
+            ))
+            .body(lines),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use super::model::AuditEvent;
+
+#[derive(Debug, Deserialize)]
+pub struct AuditEventsQueryDto {
+    pub actor_id: Option<i32>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub from: Option<chrono::NaiveDateTime>,
+    pub to: Option<chrono::NaiveDateTime>,
+    pub cursor: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl AuditEventsQueryDto {
+    pub const DEFAULT_LIMIT: i64 = 50;
+    pub const MAX_LIMIT: i64 = 500;
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                return Err("from must be before to".to_string());
+            }
+        }
+
+        if self.entity_id.is_some() && self.entity_type.is_none() {
+            return Err("entity_id needs an entity_type".to_string());
+        }
+
+        if self
+            .limit
+            .is_some_and(|limit| !(1..=Self::MAX_LIMIT).contains(&limit))
+        {
+            return Err(format!("Limit must be between 1 and {}", Self::MAX_LIMIT));
+        }
+
+        Ok(())
+    }
+}
+
+/// A page of events, newest first. `next_cursor` is passed back as `cursor` to get the
+/// next page and is missing on the last one.
+#[derive(Debug, Serialize)]
+pub struct AuditEventPageOutputDto {
+    pub events: Vec<AuditEvent>,
+    pub next_cursor: Option<i64>,
+}
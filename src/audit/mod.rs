@@ -0,0 +1,5 @@
+pub mod controller;
+pub mod dto;
+pub mod model;
+pub mod repository;
+pub mod service;
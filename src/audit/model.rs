@@ -0,0 +1,120 @@
+use diesel::{deserialize::Queryable, prelude::Insertable, Selectable};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::schema::audit_events;
+
+#[derive(Debug, Serialize, Queryable, Selectable)]
+#[diesel(table_name = audit_events)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub actor_id: Option<i32>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = audit_events)]
+pub struct NewAuditEvent {
+    pub actor_id: Option<i32>,
+    pub action: &'static str,
+    pub entity_type: &'static str,
+    pub entity_id: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl NewAuditEvent {
+    /// `actor_id` did `action` on the entity, which went from `before` to `after`. Missing
+    /// on creation and deletion respectively.
+    pub fn new(
+        actor_id: i32,
+        action: &'static str,
+        entity_type: &'static str,
+        entity_id: impl ToString,
+        before: Option<Value>,
+        after: Option<Value>,
+    ) -> Self {
+        let (before, after) = match (before, after) {
+            (Some(before), Some(after)) => {
+                let (before, after) = diff(before, after);
+                (Some(before), Some(after))
+            }
+            unchanged => unchanged,
+        };
+
+        NewAuditEvent {
+            actor_id: Some(actor_id),
+            action,
+            entity_type,
+            entity_id: entity_id.to_string(),
+            before,
+            after,
+        }
+    }
+}
+
+/// JSON snapshot of an entity for an event.
+pub fn snapshot(entity: &impl Serialize) -> Option<Value> {
+    serde_json::to_value(entity).ok()
+}
+
+/// Drops the fields both objects have the same value for, leaving what changed. Anything
+/// but two objects is kept whole.
+pub fn diff(before: Value, after: Value) -> (Value, Value) {
+    match (before, after) {
+        (Value::Object(mut before), Value::Object(mut after)) => {
+            let unchanged: Vec<String> = before
+                .iter()
+                .filter(|(key, value)| after.get(*key) == Some(value))
+                .map(|(key, _)| key.clone())
+                .collect();
+
+            for key in unchanged {
+                before.remove(&key);
+                after.remove(&key);
+            }
+
+            (Value::Object(before), Value::Object(after))
+        }
+        (before, after) => (before, after),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn diff_keeps_only_changed_fields() {
+        let (before, after) = diff(
+            json!({"name": "Exam 1", "start_date": "2024-06-01T10:00:00", "class_id": 3}),
+            json!({"name": "Exam 1", "start_date": "2024-06-08T10:00:00", "class_id": 3}),
+        );
+
+        assert_eq!(before, json!({"start_date": "2024-06-01T10:00:00"}));
+        assert_eq!(after, json!({"start_date": "2024-06-08T10:00:00"}));
+    }
+
+    #[test]
+    fn diff_keeps_added_and_removed_fields() {
+        let (before, after) = diff(json!({"a": 1, "b": 2}), json!({"b": 2, "c": 3}));
+
+        assert_eq!(before, json!({"a": 1}));
+        assert_eq!(after, json!({"c": 3}));
+    }
+
+    #[test]
+    fn diff_keeps_other_values_whole() {
+        let (before, after) = diff(json!(["TEACHER"]), json!(["TEACHER", "MONITOR"]));
+
+        assert_eq!(before, json!(["TEACHER"]));
+        assert_eq!(after, json!(["TEACHER", "MONITOR"]));
+    }
+}
@@ -0,0 +1,59 @@
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl, SelectableHelper};
+
+use crate::{db::DB_MANAGER, errors::ServiceError, schema::audit_events};
+
+use super::{
+    dto::AuditEventsQueryDto,
+    model::{AuditEvent, NewAuditEvent},
+};
+
+/// Meant to run in the transaction of the change it records.
+pub fn record(tx: &mut PgConnection, event: NewAuditEvent) -> diesel::QueryResult<()> {
+    diesel::insert_into(audit_events::table)
+        .values(&event)
+        .execute(tx)?;
+
+    Ok(())
+}
+
+pub fn list_events(
+    filter: &AuditEventsQueryDto,
+    cursor: Option<i64>,
+    limit: i64,
+) -> Result<Vec<AuditEvent>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let mut query = audit_events::table
+        .select(AuditEvent::as_select())
+        .into_boxed();
+
+    if let Some(actor_id) = filter.actor_id {
+        query = query.filter(audit_events::actor_id.eq(actor_id));
+    }
+
+    if let Some(entity_type) = &filter.entity_type {
+        query = query.filter(audit_events::entity_type.eq(entity_type));
+    }
+
+    if let Some(entity_id) = &filter.entity_id {
+        query = query.filter(audit_events::entity_id.eq(entity_id));
+    }
+
+    if let Some(from) = filter.from {
+        query = query.filter(audit_events::created_at.ge(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(audit_events::created_at.lt(to));
+    }
+
+    if let Some(cursor) = cursor {
+        query = query.filter(audit_events::id.lt(cursor));
+    }
+
+    query
+        .order(audit_events::id.desc())
+        .limit(limit)
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)
+}
@@ -0,0 +1,49 @@
+use crate::errors::ServiceError;
+
+use super::{
+    dto::{AuditEventPageOutputDto, AuditEventsQueryDto},
+    repository,
+};
+
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+pub fn list_events(filter: AuditEventsQueryDto) -> Result<AuditEventPageOutputDto, ServiceError> {
+    let limit = filter.limit.unwrap_or(AuditEventsQueryDto::DEFAULT_LIMIT);
+
+    // One extra row tells whether there is a next page.
+    let mut events = repository::list_events(&filter, filter.cursor, limit + 1)?;
+    let next_cursor = if events.len() as i64 > limit {
+        events.truncate(limit as usize);
+        events.last().map(|event| event.id)
+    } else {
+        None
+    };
+
+    Ok(AuditEventPageOutputDto {
+        events,
+        next_cursor,
+    })
+}
+
+/// Every event matching the filters, newest first, as JSON Lines. `limit` is ignored.
+pub fn export_events(filter: AuditEventsQueryDto) -> Result<String, ServiceError> {
+    let mut lines = String::new();
+    let mut cursor = filter.cursor;
+
+    loop {
+        let events = repository::list_events(&filter, cursor, EXPORT_BATCH_SIZE)?;
+
+        for event in &events {
+            let line =
+                serde_json::to_string(event).map_err(|_| ServiceError::InternalServerError)?;
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+
+        if (events.len() as i64) < EXPORT_BATCH_SIZE {
+            return Ok(lines);
+        }
+
+        cursor = events.last().map(|event| event.id);
+    }
+}
@@ -0,0 +1,293 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+
+use crate::errors::ServiceError;
+
+use super::{models::LoginAttempt, repository};
+
+/// Keeps track of failed logins per key, where a key is either an account (`email:...`),
+/// a client address (`ip:...`) or the second factor of a user (`mfa:...`).
+pub trait LoginAttemptStore: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<LoginAttempt>, ServiceError>;
+    /// Counts one more failure at `now` in a single step, so concurrent failures all
+    /// count. Failures from before `window_start` are forgotten, and so is their block.
+    fn increment(
+        &self,
+        key: &str,
+        now: NaiveDateTime,
+        window_start: NaiveDateTime,
+    ) -> Result<LoginAttempt, ServiceError>;
+    /// Blocks the key until `until`, unless it is already blocked for longer.
+    fn block(&self, key: &str, until: NaiveDateTime) -> Result<(), ServiceError>;
+    fn clear(&self, key: &str) -> Result<(), ServiceError>;
+
+    /// Registers one more failure for `key`. Every failure past the free ones doubles the
+    /// time the key stays blocked, up to `LOGIN_MAX_BACKOFF_SECONDS`.
+    fn record_failure(&self, key: &str, now: NaiveDateTime) -> Result<LoginAttempt, ServiceError> {
+        let mut attempt = self.increment(key, now, now - attempt_window())?;
+
+        if let Some(until) = backoff_until(attempt.failures, now) {
+            self.block(key, until)?;
+            attempt.blocked_until = attempt.blocked_until.max(Some(until));
+        }
+
+        Ok(attempt)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryLoginAttemptStore {
+    attempts: Mutex<HashMap<String, LoginAttempt>>,
+}
+
+impl LoginAttemptStore for MemoryLoginAttemptStore {
+    fn get(&self, key: &str) -> Result<Option<LoginAttempt>, ServiceError> {
+        Ok(self.attempts.lock().unwrap().get(key).cloned())
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        now: NaiveDateTime,
+        window_start: NaiveDateTime,
+    ) -> Result<LoginAttempt, ServiceError> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let attempt = attempts
+            .entry(key.to_string())
+            .and_modify(|attempt| {
+                if attempt.last_failure_at > window_start {
+                    attempt.failures += 1;
+                } else {
+                    attempt.failures = 1;
+                    attempt.blocked_until = None;
+                }
+                attempt.last_failure_at = now;
+            })
+            .or_insert_with(|| LoginAttempt {
+                key: key.to_string(),
+                failures: 1,
+                last_failure_at: now,
+                blocked_until: None,
+            });
+
+        Ok(attempt.clone())
+    }
+
+    fn block(&self, key: &str, until: NaiveDateTime) -> Result<(), ServiceError> {
+        if let Some(attempt) = self.attempts.lock().unwrap().get_mut(key) {
+            attempt.blocked_until = attempt.blocked_until.max(Some(until));
+        }
+        Ok(())
+    }
+
+    fn clear(&self, key: &str) -> Result<(), ServiceError> {
+        self.attempts.lock().unwrap().remove(key);
+        Ok(())
+    }
+}
+
+pub struct PostgresLoginAttemptStore;
+
+impl LoginAttemptStore for PostgresLoginAttemptStore {
+    fn get(&self, key: &str) -> Result<Option<LoginAttempt>, ServiceError> {
+        repository::get_login_attempt(key)
+    }
+
+    fn increment(
+        &self,
+        key: &str,
+        now: NaiveDateTime,
+        window_start: NaiveDateTime,
+    ) -> Result<LoginAttempt, ServiceError> {
+        repository::increment_login_attempt(key, now, window_start)
+    }
+
+    fn block(&self, key: &str, until: NaiveDateTime) -> Result<(), ServiceError> {
+        repository::block_login_attempt(key, until)
+    }
+
+    fn clear(&self, key: &str) -> Result<(), ServiceError> {
+        repository::delete_login_attempt(key)
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Failures allowed before any backoff kicks in.
+fn free_attempts() -> i32 {
+    env_or("LOGIN_FREE_ATTEMPTS", 3)
+}
+
+fn max_backoff_seconds() -> i64 {
+    env_or("LOGIN_MAX_BACKOFF_SECONDS", 900)
+}
+
+/// Failures older than this window don't count anymore.
+fn attempt_window() -> chrono::Duration {
+    chrono::Duration::minutes(env_or("LOGIN_ATTEMPT_WINDOW_MINUTES", 60))
+}
+
+pub fn account_lockout_threshold() -> i32 {
+    env_or("ACCOUNT_LOCKOUT_THRESHOLD", 10)
+}
+
+pub fn account_lockout_duration() -> chrono::Duration {
+    chrono::Duration::minutes(env_or("ACCOUNT_LOCKOUT_MINUTES", 15))
+}
+
+/// Whether the failures of an account key are enough to lock the account itself.
+pub fn reaches_lockout(attempt: &LoginAttempt) -> bool {
+    attempt.failures >= account_lockout_threshold()
+}
+
+pub fn email_key(email: &str) -> String {
+    format!("email:{}", email.trim().to_lowercase())
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+/// Failed second factor codes of a user, across every login challenge.
+pub fn mfa_key(user_id: i32) -> String {
+    format!("mfa:{}", user_id)
+}
+
+/// Seconds the caller still has to wait, if any.
+pub fn retry_after(attempt: &LoginAttempt, now: NaiveDateTime) -> Option<i64> {
+    match attempt.blocked_until {
+        Some(blocked_until) if blocked_until > now => {
+            Some((blocked_until - now).num_seconds().max(1))
+        }
+        _ => None,
+    }
+}
+
+/// When a key with that many failures is free again, if it is blocked at all.
+fn backoff_until(failures: i32, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    match failures - free_attempts() {
+        over if over > 0 => {
+            let backoff = 2_i64
+                .saturating_pow(over.min(32) as u32)
+                .min(max_backoff_seconds());
+            Some(now + chrono::Duration::seconds(backoff))
+        }
+        _ => None,
+    }
+}
+
+fn store_from_env() -> Box<dyn LoginAttemptStore> {
+    match std::env::var("LOGIN_ATTEMPT_STORE").as_deref() {
+        Ok("memory") => Box::<MemoryLoginAttemptStore>::default(),
+        _ => Box::new(PostgresLoginAttemptStore),
+    }
+}
+
+lazy_static! {
+    pub static ref LOGIN_ATTEMPTS: Box<dyn LoginAttemptStore> = store_from_env();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "email:student@school.test";
+
+    fn now() -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn free_attempts_are_not_blocked() {
+        let store = MemoryLoginAttemptStore::default();
+
+        for failures in 1..=free_attempts() {
+            let attempt = store.record_failure(KEY, now()).unwrap();
+
+            assert_eq!(attempt.failures, failures);
+            assert_eq!(retry_after(&attempt, now()), None);
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max() {
+        let store = MemoryLoginAttemptStore::default();
+        for _ in 0..free_attempts() {
+            store.record_failure(KEY, now()).unwrap();
+        }
+
+        let first = store.record_failure(KEY, now()).unwrap();
+        let second = store.record_failure(KEY, now()).unwrap();
+        assert_eq!(retry_after(&first, now()), Some(2));
+        assert_eq!(retry_after(&second, now()), Some(4));
+
+        for _ in 0..40 {
+            store.record_failure(KEY, now()).unwrap();
+        }
+        let attempt = store.get(KEY).unwrap().unwrap();
+        assert_eq!(retry_after(&attempt, now()), Some(max_backoff_seconds()));
+    }
+
+    #[test]
+    fn failures_outside_the_window_start_over() {
+        let store = MemoryLoginAttemptStore::default();
+        for _ in 0..5 {
+            store.record_failure(KEY, now()).unwrap();
+        }
+
+        let later = now() + attempt_window() + chrono::Duration::seconds(1);
+        let attempt = store.record_failure(KEY, later).unwrap();
+
+        assert_eq!(attempt.failures, 1);
+        assert_eq!(retry_after(&attempt, later), None);
+    }
+
+    #[test]
+    fn accounts_lock_at_the_threshold() {
+        let store = MemoryLoginAttemptStore::default();
+
+        for _ in 1..account_lockout_threshold() {
+            let attempt = store.record_failure(KEY, now()).unwrap();
+            assert!(!reaches_lockout(&attempt));
+        }
+
+        let attempt = store.record_failure(KEY, now()).unwrap();
+        assert!(reaches_lockout(&attempt));
+    }
+
+    #[test]
+    fn success_clears_the_failures() {
+        let store = MemoryLoginAttemptStore::default();
+        for _ in 0..5 {
+            store.record_failure(KEY, now()).unwrap();
+        }
+
+        store.clear(KEY).unwrap();
+        assert!(store.get(KEY).unwrap().is_none());
+
+        let attempt = store.record_failure(KEY, now()).unwrap();
+        assert_eq!(attempt.failures, 1);
+        assert_eq!(retry_after(&attempt, now()), None);
+    }
+
+    #[test]
+    fn keys_are_counted_apart() {
+        let store = MemoryLoginAttemptStore::default();
+        for _ in 0..5 {
+            store.record_failure(KEY, now()).unwrap();
+        }
+
+        let attempt = store.record_failure(&ip_key("127.0.0.1"), now()).unwrap();
+        assert_eq!(attempt.failures, 1);
+    }
+}
@@ -1,16 +1,250 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{http::header, web, HttpMessage, HttpRequest, HttpResponse, Responder};
 
-use crate::auth::{dto::LoginUserInputDto, service};
+use crate::{
+    auth::{
+        dto::{
+            ForgotPasswordInputDto, ImpersonateInputDto, LoginUserInputDto, MfaCodeInputDto,
+            MfaVerifyInputDto, OidcCallbackInputDto, OidcLinkOutputDto, RefreshTokenInputDto,
+            ResetPasswordInputDto,
+        },
+        keys::JWT_KEYS,
+        mfa,
+        models::LoggedUser,
+        oidc::{self, OIDC_PROVIDERS},
+        service,
+    },
+    errors::ServiceError,
+};
 
-pub async fn login(new_user: web::Json<LoginUserInputDto>) -> impl Responder {
+pub async fn login(req: HttpRequest, new_user: web::Json<LoginUserInputDto>) -> impl Responder {
     if let Err(e) = new_user.validate() {
         return HttpResponse::from_error(e);
     }
 
-    let output = match service::login(new_user.into_inner()) {
+    let client_ip = req.peer_addr().map(|addr| addr.ip().to_string());
+
+    let output = match service::login(new_user.into_inner(), client_ip) {
         Ok(output) => output,
         Err(e) => return HttpResponse::from_error(e),
     };
 
     HttpResponse::Ok().json(output).into()
 }
+
+pub async fn refresh(input: web::Json<RefreshTokenInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    match service::refresh(input.into_inner()) {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn logout(req: HttpRequest) -> impl Responder {
+    let ext = req.extensions();
+    let user = match ext.get::<LoggedUser>() {
+        Some(user) => user,
+        None => return HttpResponse::from_error(ServiceError::Unauthorized),
+    };
+
+    match service::logout(&user.jti) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn logout_all(req: HttpRequest) -> impl Responder {
+    let ext = req.extensions();
+    let user = match ext.get::<LoggedUser>() {
+        Some(user) => user,
+        None => return HttpResponse::from_error(ServiceError::Unauthorized),
+    };
+
+    match service::logout_all(user.id) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn forgot_password(input: web::Json<ForgotPasswordInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    match service::forgot_password(input.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn reset_password(input: web::Json<ResetPasswordInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    match service::reset_password(input.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn unlock_user(req: HttpRequest, path: web::Path<i32>) -> impl Responder {
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
+
+    match service::unlock_user(admin.id, path.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn force_password_reset(req: HttpRequest, path: web::Path<i32>) -> impl Responder {
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
+
+    match service::force_password_reset(admin.id, path.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn impersonate(
+    req: HttpRequest,
+    path: web::Path<i32>,
+    input: web::Json<ImpersonateInputDto>,
+) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    let ext = req.extensions();
+    let actor = ext.get::<LoggedUser>().unwrap();
+
+    match service::impersonate(actor, path.into_inner(), input.into_inner()) {
+        Ok(impersonation) => HttpResponse::Created().json(impersonation),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn jwks() -> impl Responder {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "public, max-age=300"))
+        .json(&JWT_KEYS.jwks)
+}
+
+pub async fn list_oidc_providers() -> impl Responder {
+    let mut providers: Vec<&String> = OIDC_PROVIDERS.keys().collect();
+    providers.sort();
+
+    HttpResponse::Ok().json(providers)
+}
+
+pub async fn oidc_authorize(path: web::Path<String>) -> impl Responder {
+    match service::oidc_authorize(&path.into_inner(), None).await {
+        Ok((url, state_cookie)) => HttpResponse::Found()
+            .insert_header((header::LOCATION, url))
+            .cookie(state_cookie)
+            .finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn oidc_link(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    let user_id = req.extensions().get::<LoggedUser>().unwrap().id;
+
+    match service::oidc_authorize(&path.into_inner(), Some(user_id)).await {
+        Ok((authorization_url, state_cookie)) => HttpResponse::Ok()
+            .cookie(state_cookie)
+            .json(OidcLinkOutputDto { authorization_url }),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn oidc_callback(
+    req: HttpRequest,
+    path: web::Path<String>,
+    input: web::Query<OidcCallbackInputDto>,
+) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    if let Err(e) = oidc::check_state_cookie(&req, &input.state) {
+        return HttpResponse::from_error(e);
+    }
+
+    match service::oidc_login(&path.into_inner(), input.into_inner()).await {
+        Ok(output) => HttpResponse::Ok()
+            .cookie(oidc::removed_state_cookie())
+            .json(output),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn verify_mfa(input: web::Json<MfaVerifyInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    match mfa::verify_challenge(input.into_inner()) {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn enroll_totp(req: HttpRequest) -> impl Responder {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match mfa::start_enrollment(user.id) {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn confirm_totp(req: HttpRequest, input: web::Json<MfaCodeInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match mfa::confirm_enrollment(user.id, &user.jti, &input.code) {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn disable_totp(req: HttpRequest, input: web::Json<MfaCodeInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match mfa::disable(user.id, &user.roles, &input.code) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn regenerate_recovery_codes(
+    req: HttpRequest,
+    input: web::Json<MfaCodeInputDto>,
+) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(e);
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match mfa::regenerate_recovery_codes(user.id, &input.code) {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
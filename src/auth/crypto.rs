@@ -1,65 +1,263 @@
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::errors::ServiceError;
 
+use super::keys::JWT_KEYS;
+
 #[derive(Debug, Serialize, Deserialize)]
-struct Claims {
-    exp: usize,
-    sub: i32,
+pub struct Claims {
+    pub exp: usize,
+    pub sub: i32,
+    pub jti: String,
+    /// Whether the session went through a second factor.
+    #[serde(default)]
+    pub mfa: bool,
+    /// Set on impersonation tokens, `sub` is then the impersonated user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub rich: Option<RichClaims>,
 }
 
-pub fn encrypt_password(password: &str) -> Result<String, ServiceError> {
-    let salt: [u8; 16] = rand::thread_rng()
-        .sample_iter(Alphanumeric)
-        .take(16)
-        .map(char::from)
-        .collect::<String>()
-        .as_bytes()
-        .try_into()
-        .unwrap();
+/// The user actually behind an impersonation token, as in RFC 8693.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: i32,
+}
 
-    match bcrypt::hash_with_salt(password, 4, salt) {
-        Ok(hashed_password) => Ok(hashed_password.to_string()),
-        Err(_) => Err(ServiceError::InternalServerError),
+/// Authorization data embedded in the token when `RICH_TOKEN_CLAIMS` is on, so requests
+/// don't need to load the user and its roles from the database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RichClaims {
+    pub roles: Vec<String>,
+    pub ver: i32,
+    pub email_verified: bool,
+}
+
+pub fn rich_claims_enabled() -> bool {
+    std::env::var("RICH_TOKEN_CLAIMS")
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordHasher {
+    Bcrypt,
+    #[cfg(feature = "argon2")]
+    Argon2id,
+}
+
+/// Hasher used for new passwords, picked with `PASSWORD_HASHER`. Argon2id needs the
+/// `argon2` cargo feature, otherwise we fall back to bcrypt.
+pub fn password_hasher() -> PasswordHasher {
+    match std::env::var("PASSWORD_HASHER").as_deref() {
+        #[cfg(feature = "argon2")]
+        Ok("argon2id") => PasswordHasher::Argon2id,
+        _ => PasswordHasher::Bcrypt,
+    }
+}
+
+pub fn bcrypt_cost() -> u32 {
+    std::env::var("BCRYPT_COST")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(bcrypt::DEFAULT_COST)
+}
+
+/// Every stored hash is self describing: bcrypt hashes start with `$2a$`/`$2b$`/`$2y$`
+/// and argon2 ones with `$argon2id$`, so both formats can live side by side in `users`.
+pub fn encrypt_password(password: &str) -> Result<String, ServiceError> {
+    match password_hasher() {
+        PasswordHasher::Bcrypt => {
+            bcrypt::hash(password, bcrypt_cost()).map_err(|_| ServiceError::InternalServerError)
+        }
+        #[cfg(feature = "argon2")]
+        PasswordHasher::Argon2id => argon2_hash(password),
     }
 }
 
 pub fn verify_password(password: &str, hashed_password: &str) -> Result<bool, ServiceError> {
+    if hashed_password.starts_with("$argon2") {
+        return argon2_verify(password, hashed_password);
+    }
+
     match bcrypt::verify(password, hashed_password) {
         Ok(is_valid) => Ok(is_valid),
         Err(_) => Err(ServiceError::InternalServerError),
     }
 }
 
-pub fn generate_token(user_id: i32) -> Result<String, ServiceError> {
-    let claims = Claims {
-        sub: user_id,
-        exp: (chrono::Utc::now() + chrono::Duration::days(1)).timestamp() as usize,
+/// Tells whether a hash that just verified should be replaced, either because it was made
+/// with a weaker cost, an older bcrypt prefix or a hasher that is no longer the configured one.
+pub fn needs_rehash(hashed_password: &str) -> bool {
+    match password_hasher() {
+        PasswordHasher::Bcrypt => {
+            if !hashed_password.starts_with("$2b$") {
+                return true;
+            }
+
+            match hashed_password.parse::<bcrypt::HashParts>() {
+                Ok(parts) => parts.get_cost() < bcrypt_cost(),
+                Err(_) => true,
+            }
+        }
+        #[cfg(feature = "argon2")]
+        PasswordHasher::Argon2id => argon2_needs_rehash(hashed_password),
+    }
+}
+
+#[cfg(feature = "argon2")]
+fn argon2_hash(password: &str) -> Result<String, ServiceError> {
+    use argon2::password_hash::{PasswordHasher as _, SaltString};
+
+    let salt = SaltString::generate(&mut rand::thread_rng());
+
+    argon2::Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+#[cfg(feature = "argon2")]
+fn argon2_verify(password: &str, hashed_password: &str) -> Result<bool, ServiceError> {
+    use argon2::password_hash::{Error, PasswordHash, PasswordVerifier};
+
+    let hash = PasswordHash::new(hashed_password).map_err(|_| ServiceError::InternalServerError)?;
+
+    match argon2::Argon2::default().verify_password(password.as_bytes(), &hash) {
+        Ok(_) => Ok(true),
+        Err(Error::Password) => Ok(false),
+        Err(_) => Err(ServiceError::InternalServerError),
+    }
+}
+
+#[cfg(not(feature = "argon2"))]
+fn argon2_verify(_password: &str, _hashed_password: &str) -> Result<bool, ServiceError> {
+    log::error!("found an argon2 password hash but the `argon2` feature is disabled");
+    Err(ServiceError::InternalServerError)
+}
+
+#[cfg(feature = "argon2")]
+fn argon2_needs_rehash(hashed_password: &str) -> bool {
+    use argon2::password_hash::PasswordHash;
+
+    let hash = match PasswordHash::new(hashed_password) {
+        Ok(hash) => hash,
+        Err(_) => return true,
     };
 
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+    if hash.algorithm != argon2::Algorithm::Argon2id.ident() {
+        return true;
+    }
+
+    match argon2::Params::try_from(&hash) {
+        Ok(params) => {
+            let wanted = argon2::Params::default();
+            params.m_cost() < wanted.m_cost()
+                || params.t_cost() < wanted.t_cost()
+                || params.p_cost() < wanted.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+pub fn access_token_ttl() -> chrono::Duration {
+    let minutes = std::env::var("ACCESS_TOKEN_TTL_MINUTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(15);
+
+    chrono::Duration::minutes(minutes)
+}
+
+pub fn refresh_token_ttl() -> chrono::Duration {
+    let days = std::env::var("REFRESH_TOKEN_TTL_DAYS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
 
-    let token = jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(secret.as_ref()),
-    )
-    .map_err(|_| ServiceError::InternalServerError)?;
+    chrono::Duration::days(days)
+}
+
+pub fn generate_token(
+    user_id: i32,
+    jti: &str,
+    mfa: bool,
+    rich: Option<RichClaims>,
+) -> Result<String, ServiceError> {
+    sign(&Claims {
+        sub: user_id,
+        exp: (chrono::Utc::now() + access_token_ttl()).timestamp() as usize,
+        jti: jti.to_string(),
+        mfa,
+        act: None,
+        rich,
+    })
+}
+
+/// Token letting `actor_id` act as `user_id` until `expires_at`. Its holder already went
+/// through the account policies, second factor included, to get it.
+pub fn generate_impersonation_token(
+    user_id: i32,
+    actor_id: i32,
+    jti: &str,
+    mfa: bool,
+    expires_at: chrono::NaiveDateTime,
+) -> Result<String, ServiceError> {
+    sign(&Claims {
+        sub: user_id,
+        exp: expires_at.and_utc().timestamp() as usize,
+        jti: jti.to_string(),
+        mfa,
+        act: Some(Actor { sub: actor_id }),
+        rich: None,
+    })
+}
+
+fn sign(claims: &Claims) -> Result<String, ServiceError> {
+    let mut header = jsonwebtoken::Header::new(JWT_KEYS.algorithm);
+    header.kid = JWT_KEYS.signing_kid.clone();
+
+    let token = jsonwebtoken::encode(&header, claims, &JWT_KEYS.encoding_key)
+        .map_err(|_| ServiceError::InternalServerError)?;
 
     Ok(token)
 }
 
-pub fn decode_token(token: &str) -> Result<i32, ServiceError> {
-    let secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+pub fn decode_token(token: &str) -> Result<Claims, ServiceError> {
+    let header = jsonwebtoken::decode_header(token).map_err(|_| ServiceError::Unauthorized)?;
 
-    let token_data = jsonwebtoken::decode::<Claims>(
-        token,
-        &jsonwebtoken::DecodingKey::from_secret(secret.as_ref()),
-        &jsonwebtoken::Validation::default(),
-    )
-    .map_err(|_| ServiceError::Unauthorized)?;
+    let (algorithm, key) = match &header.kid {
+        Some(kid) => match JWT_KEYS.verification_keys.get(kid) {
+            Some(key) => (key.algorithm, &key.key),
+            None => return Err(ServiceError::Unauthorized),
+        },
+        None => match &JWT_KEYS.legacy_key {
+            Some(key) => (jsonwebtoken::Algorithm::HS256, key),
+            None => return Err(ServiceError::Unauthorized),
+        },
+    };
+
+    let token_data =
+        jsonwebtoken::decode::<Claims>(token, key, &jsonwebtoken::Validation::new(algorithm))
+            .map_err(|_| ServiceError::Unauthorized)?;
+
+    Ok(token_data.claims)
+}
+
+/// Generates a random, url-safe opaque token such as a refresh token or a session id.
+pub fn generate_opaque_token(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
 
-    Ok(token_data.claims.sub)
+/// Opaque tokens carry enough entropy that a plain SHA-256 is enough to store them at rest.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::errors::ServiceError;
+use crate::{errors::ServiceError, user};
 
 #[derive(Debug, Deserialize)]
 pub struct LoginUserInputDto {
@@ -23,4 +23,155 @@ impl LoginUserInputDto {
 #[derive(Debug, Serialize)]
 pub struct LoginUserOutputDto {
     pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshTokenInputDto {
+    pub refresh_token: String,
+}
+
+impl RefreshTokenInputDto {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.refresh_token.is_empty() {
+            return Err(ServiceError::BadRequest("Refresh token is required".into()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForgotPasswordInputDto {
+    pub email: String,
+}
+
+impl ForgotPasswordInputDto {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.email.is_empty() {
+            return Err(ServiceError::BadRequest("Email is required".into()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordInputDto {
+    pub token: String,
+    pub password: String,
+}
+
+impl ResetPasswordInputDto {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.token.is_empty() {
+            return Err(ServiceError::BadRequest("Token is required".into()));
+        }
+        user::dto::validate_password_strength(&self.password).map_err(ServiceError::BadRequest)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackInputDto {
+    pub state: String,
+    pub code: Option<String>,
+    pub error: Option<String>,
+}
+
+impl OidcCallbackInputDto {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if let Some(error) = &self.error {
+            return Err(ServiceError::BadRequest(format!(
+                "Identity provider returned {}",
+                error
+            )));
+        }
+        if self.code.as_deref().unwrap_or_default().is_empty() {
+            return Err(ServiceError::BadRequest("Code is required".into()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OidcLinkOutputDto {
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MfaRequiredOutputDto {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+/// Result of the first login step: either a session, or a challenge to finish with
+/// `/auth/mfa/verify` when the account has a second factor.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginOutputDto {
+    Session(LoginUserOutputDto),
+    MfaRequired(MfaRequiredOutputDto),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaVerifyInputDto {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+impl MfaVerifyInputDto {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.mfa_token.is_empty() {
+            return Err(ServiceError::BadRequest("Mfa token is required".into()));
+        }
+        if self.code.is_empty() {
+            return Err(ServiceError::BadRequest("Code is required".into()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaCodeInputDto {
+    pub code: String,
+}
+
+impl MfaCodeInputDto {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.code.is_empty() {
+            return Err(ServiceError::BadRequest("Code is required".into()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollmentOutputDto {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesOutputDto {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImpersonateInputDto {
+    /// Why the user is being impersonated, kept with the impersonation.
+    pub reason: String,
+}
+
+impl ImpersonateInputDto {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.reason.trim().is_empty() {
+            return Err(ServiceError::BadRequest("Reason is required".into()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImpersonationOutputDto {
+    pub token: String,
+    pub expires_at: chrono::NaiveDateTime,
 }
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey,
+};
+use lazy_static::lazy_static;
+use rsa::{pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey};
+
+// DER prefix of an Ed25519 SubjectPublicKeyInfo, the raw 32 byte key follows it.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+pub struct VerificationKey {
+    pub algorithm: Algorithm,
+    pub key: DecodingKey,
+}
+
+/// Signing and verification keys, read once from the environment at startup.
+///
+/// * `JWT_ALGORITHM`: `HS256` (default), `RS256` or `EdDSA`.
+/// * `JWT_SECRET`: shared secret for `HS256`. When set together with an asymmetric
+///   algorithm, tokens without a `kid` are still accepted so old sessions keep working
+///   while moving away from the shared secret.
+/// * `JWT_SIGNING_KEY` and `JWT_SIGNING_KID`: PEM private key used to sign new tokens and
+///   the `kid` written to their header.
+/// * `JWT_VERIFICATION_KEYS`: comma separated `kid=path` list of PEM public keys accepted
+///   when verifying. Keep the previous key here while rotating so no token is cut short.
+pub struct JwtKeys {
+    pub algorithm: Algorithm,
+    pub signing_kid: Option<String>,
+    pub encoding_key: EncodingKey,
+    pub verification_keys: HashMap<String, VerificationKey>,
+    pub legacy_key: Option<DecodingKey>,
+    pub jwks: JwkSet,
+}
+
+fn read_pem(path: &str) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|_| panic!("Could not read key file {}", path))
+}
+
+fn public_key_to_jwk(kid: &str, pem: &[u8]) -> (Algorithm, Jwk) {
+    let pem = std::str::from_utf8(pem).expect("Public keys must be PEM encoded");
+
+    let common = |algorithm: KeyAlgorithm| CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    };
+
+    if let Ok(key) = RsaPublicKey::from_public_key_pem(pem) {
+        let jwk = Jwk {
+            common: common(KeyAlgorithm::RS256),
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+                e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+            }),
+        };
+        return (Algorithm::RS256, jwk);
+    }
+
+    let der = pem::parse(pem)
+        .unwrap_or_else(|_| panic!("Invalid PEM for key {}", kid))
+        .into_contents();
+
+    if der.len() == 44 && der.starts_with(&ED25519_SPKI_PREFIX) {
+        let jwk = Jwk {
+            common: common(KeyAlgorithm::EdDSA),
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: URL_SAFE_NO_PAD.encode(&der[12..]),
+            }),
+        };
+        return (Algorithm::EdDSA, jwk);
+    }
+
+    panic!("Key {} must be an RSA or Ed25519 public key", kid)
+}
+
+fn load_keys() -> JwtKeys {
+    let algorithm = match std::env::var("JWT_ALGORITHM").as_deref() {
+        Ok("RS256") => Algorithm::RS256,
+        Ok("EdDSA") => Algorithm::EdDSA,
+        Ok("HS256") | Err(_) => Algorithm::HS256,
+        Ok(other) => panic!("Unsupported JWT_ALGORITHM {}", other),
+    };
+
+    let secret = std::env::var("JWT_SECRET").ok();
+
+    let mut verification_keys = HashMap::new();
+    let mut jwks = JwkSet { keys: vec![] };
+
+    if let Ok(keys) = std::env::var("JWT_VERIFICATION_KEYS") {
+        for entry in keys.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (kid, path) = entry
+                .split_once('=')
+                .expect("JWT_VERIFICATION_KEYS entries must look like kid=path");
+
+            let (key_algorithm, jwk) = public_key_to_jwk(kid, &read_pem(path));
+            let key = DecodingKey::from_jwk(&jwk).expect("Invalid verification key");
+
+            verification_keys.insert(
+                kid.to_string(),
+                VerificationKey {
+                    algorithm: key_algorithm,
+                    key,
+                },
+            );
+            jwks.keys.push(jwk);
+        }
+    }
+
+    let (signing_kid, encoding_key) = match algorithm {
+        Algorithm::HS256 => {
+            let secret = secret.clone().expect("JWT_SECRET must be set");
+            (
+                std::env::var("JWT_SIGNING_KID").ok(),
+                EncodingKey::from_secret(secret.as_ref()),
+            )
+        }
+        _ => {
+            let path = std::env::var("JWT_SIGNING_KEY").expect("JWT_SIGNING_KEY must be set");
+            let kid = std::env::var("JWT_SIGNING_KID").expect("JWT_SIGNING_KID must be set");
+            let pem = read_pem(&path);
+
+            let encoding_key = match algorithm {
+                Algorithm::RS256 => EncodingKey::from_rsa_pem(&pem),
+                _ => EncodingKey::from_ed_pem(&pem),
+            }
+            .expect("Invalid JWT_SIGNING_KEY");
+
+            match verification_keys.get(&kid) {
+                Some(key) if key.algorithm == algorithm => (),
+                _ => panic!(
+                    "JWT_VERIFICATION_KEYS must contain the public key of {}",
+                    kid
+                ),
+            }
+
+            (Some(kid), encoding_key)
+        }
+    };
+
+    // With HS256 and a kid configured, new tokens carry that kid and are checked against
+    // the shared secret like the legacy ones.
+    if algorithm == Algorithm::HS256 {
+        if let Some(kid) = &signing_kid {
+            verification_keys.insert(
+                kid.clone(),
+                VerificationKey {
+                    algorithm,
+                    key: DecodingKey::from_secret(secret.as_ref().unwrap().as_ref()),
+                },
+            );
+        }
+    }
+
+    JwtKeys {
+        algorithm,
+        signing_kid,
+        encoding_key,
+        verification_keys,
+        legacy_key: secret.map(|secret| DecodingKey::from_secret(secret.as_ref())),
+        jwks,
+    }
+}
+
+lazy_static! {
+    pub static ref JWT_KEYS: JwtKeys = load_keys();
+}
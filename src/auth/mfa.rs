@@ -0,0 +1,288 @@
+use crate::{errors::ServiceError, user};
+
+use super::{
+    attempts::{self, LOGIN_ATTEMPTS},
+    crypto,
+    dto::{
+        LoginUserOutputDto, MfaRequiredOutputDto, MfaVerifyInputDto, RecoveryCodesOutputDto,
+        TotpEnrollmentOutputDto,
+    },
+    models::{NewMfaChallenge, TotpCredential},
+    repository, service, totp,
+};
+
+const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// Wrong codes allowed on a login challenge before it has to start over with the password.
+const MAX_CHALLENGE_FAILURES: i32 = 5;
+
+fn challenge_ttl() -> chrono::Duration {
+    chrono::Duration::minutes(5)
+}
+
+fn issuer() -> String {
+    std::env::var("MFA_ISSUER").unwrap_or("miniprova".to_string())
+}
+
+/// Roles listed in `MFA_REQUIRED_ROLES` (e.g. `ADMIN,TEACHER`) can only use the api
+/// through sessions that went through a second factor.
+pub fn required_roles() -> Vec<String> {
+    std::env::var("MFA_REQUIRED_ROLES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|role| role.trim().to_uppercase())
+        .filter(|role| !role.is_empty())
+        .collect()
+}
+
+pub fn is_required_for(roles: &[String]) -> bool {
+    let required = required_roles();
+    roles.iter().any(|role| required.contains(role))
+}
+
+fn get_confirmed_credential(user_id: i32) -> Result<Option<TotpCredential>, ServiceError> {
+    let credential = repository::get_totp_credential(user_id)?;
+
+    Ok(credential.filter(|credential| credential.confirmed_at.is_some()))
+}
+
+pub fn is_enrolled(user_id: i32) -> Result<bool, ServiceError> {
+    Ok(get_confirmed_credential(user_id)?.is_some())
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Returns the codes to show once, and their hashes to store.
+fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = crypto::generate_opaque_token(RECOVERY_CODE_LEN).to_lowercase();
+            let hash = crypto::hash_token(&code);
+            let (left, right) = code.split_at(RECOVERY_CODE_LEN / 2);
+
+            (format!("{}-{}", left, right), hash)
+        })
+        .unzip()
+}
+
+fn check_totp(credential: &TotpCredential, code: &str) -> Result<bool, ServiceError> {
+    let now = chrono::Utc::now().timestamp();
+
+    match totp::verify(&credential.secret, code, now, credential.last_used_step) {
+        Some(step) => repository::use_totp_step(credential.user_id, step),
+        None => Ok(false),
+    }
+}
+
+/// Accepts either the current TOTP code or one of the unused recovery codes.
+fn check_code(credential: &TotpCredential, code: &str) -> Result<bool, ServiceError> {
+    if check_totp(credential, code)? {
+        return Ok(true);
+    }
+
+    let recovery_code = normalize_recovery_code(code);
+    if recovery_code.len() != RECOVERY_CODE_LEN {
+        return Ok(false);
+    }
+
+    repository::use_recovery_code(credential.user_id, &crypto::hash_token(&recovery_code))
+}
+
+/// Runs a code check throttled like passwords: failures back off per user whatever
+/// challenge or session they come from, and enough of them lock the account.
+fn throttled(
+    user_id: i32,
+    check: impl FnOnce() -> Result<bool, ServiceError>,
+) -> Result<bool, ServiceError> {
+    let now = chrono::Utc::now().naive_utc();
+    let store = LOGIN_ATTEMPTS.as_ref();
+    let key = attempts::mfa_key(user_id);
+
+    if let Some(attempt) = store.get(&key)? {
+        if let Some(retry_after) = attempts::retry_after(&attempt, now) {
+            return Err(ServiceError::TooManyRequests(retry_after));
+        }
+    }
+
+    if let Some(locked_until) = user::repository::get_user_by_id(user_id)?
+        .and_then(|user| user.locked_until)
+        .filter(|locked_until| *locked_until > now)
+    {
+        return Err(ServiceError::TooManyRequests(
+            (locked_until - now).num_seconds().max(1),
+        ));
+    }
+
+    if check()? {
+        store.clear(&key)?;
+        return Ok(true);
+    }
+
+    let attempt = store.record_failure(&key, now)?;
+    if attempts::reaches_lockout(&attempt) {
+        repository::set_user_locked_until(
+            user_id,
+            Some(now + attempts::account_lockout_duration()),
+        )?;
+    }
+
+    Ok(false)
+}
+
+pub fn start_enrollment(user_id: i32) -> Result<TotpEnrollmentOutputDto, ServiceError> {
+    if is_enrolled(user_id)? {
+        return Err(ServiceError::BadRequest(
+            "Two-factor authentication is already enabled".into(),
+        ));
+    }
+
+    let user = match user::repository::get_user_by_id(user_id)? {
+        Some(user) => user,
+        None => return Err(ServiceError::Unauthorized),
+    };
+
+    let secret = totp::generate_secret();
+    repository::save_pending_totp_secret(user_id, &secret)?;
+
+    Ok(TotpEnrollmentOutputDto {
+        otpauth_uri: totp::otpauth_uri(&issuer(), &user.email, &secret),
+        secret,
+    })
+}
+
+/// Enables the second factor once the user proves their app generates valid codes. The
+/// session doing it counts as verified from then on, so a refresh is enough to get a
+/// token that passes `MFA_REQUIRED_ROLES`.
+pub fn confirm_enrollment(
+    user_id: i32,
+    jti: &str,
+    code: &str,
+) -> Result<RecoveryCodesOutputDto, ServiceError> {
+    let credential = match repository::get_totp_credential(user_id)? {
+        Some(credential) if credential.confirmed_at.is_none() => credential,
+        Some(_) => {
+            return Err(ServiceError::BadRequest(
+                "Two-factor authentication is already enabled".into(),
+            ))
+        }
+        None => {
+            return Err(ServiceError::BadRequest(
+                "Two-factor enrollment was not started".into(),
+            ))
+        }
+    };
+
+    if !throttled(user_id, || check_totp(&credential, code))? {
+        return Err(ServiceError::BadRequest("Invalid code".into()));
+    }
+
+    let (recovery_codes, hashes) = generate_recovery_codes();
+    repository::confirm_totp_credential(user_id, &hashes)?;
+
+    if !jti.is_empty() {
+        repository::set_session_mfa_verified(jti)?;
+    }
+
+    Ok(RecoveryCodesOutputDto { recovery_codes })
+}
+
+pub fn regenerate_recovery_codes(
+    user_id: i32,
+    code: &str,
+) -> Result<RecoveryCodesOutputDto, ServiceError> {
+    let credential = match get_confirmed_credential(user_id)? {
+        Some(credential) => credential,
+        None => {
+            return Err(ServiceError::BadRequest(
+                "Two-factor authentication is not enabled".into(),
+            ))
+        }
+    };
+
+    if !throttled(user_id, || check_totp(&credential, code))? {
+        return Err(ServiceError::BadRequest("Invalid code".into()));
+    }
+
+    let (recovery_codes, hashes) = generate_recovery_codes();
+    repository::regenerate_recovery_codes(user_id, &hashes)?;
+
+    Ok(RecoveryCodesOutputDto { recovery_codes })
+}
+
+pub fn disable(user_id: i32, roles: &[String], code: &str) -> Result<(), ServiceError> {
+    if is_required_for(roles) {
+        return Err(ServiceError::BadRequest(
+            "Two-factor authentication is required for your role".into(),
+        ));
+    }
+
+    let credential = match get_confirmed_credential(user_id)? {
+        Some(credential) => credential,
+        None => {
+            return Err(ServiceError::BadRequest(
+                "Two-factor authentication is not enabled".into(),
+            ))
+        }
+    };
+
+    if !throttled(user_id, || check_code(&credential, code))? {
+        return Err(ServiceError::BadRequest("Invalid code".into()));
+    }
+
+    repository::delete_totp_credential(user_id)
+}
+
+pub fn create_challenge(user_id: i32) -> Result<MfaRequiredOutputDto, ServiceError> {
+    let mfa_token = crypto::generate_opaque_token(64);
+    let expires_at = (chrono::Utc::now() + challenge_ttl()).naive_utc();
+
+    repository::create_mfa_challenge(NewMfaChallenge {
+        user_id,
+        token_hash: &crypto::hash_token(&mfa_token),
+        expires_at,
+    })?;
+
+    Ok(MfaRequiredOutputDto {
+        mfa_required: true,
+        mfa_token,
+        expires_at,
+    })
+}
+
+/// Second login step: trades a pending challenge and a code for a verified session.
+pub fn verify_challenge(input: MfaVerifyInputDto) -> Result<LoginUserOutputDto, ServiceError> {
+    let challenge =
+        match repository::get_mfa_challenge_by_hash(&crypto::hash_token(&input.mfa_token))? {
+            Some(challenge) => challenge,
+            None => return Err(ServiceError::Unauthorized),
+        };
+
+    if challenge.expires_at < chrono::Utc::now().naive_utc() {
+        repository::delete_mfa_challenge(challenge.id)?;
+        return Err(ServiceError::Unauthorized);
+    }
+
+    let credential = match get_confirmed_credential(challenge.user_id)? {
+        Some(credential) => credential,
+        None => return Err(ServiceError::Unauthorized),
+    };
+
+    if !throttled(challenge.user_id, || check_code(&credential, &input.code))? {
+        if challenge.failures + 1 >= MAX_CHALLENGE_FAILURES {
+            repository::delete_mfa_challenge(challenge.id)?;
+        } else {
+            repository::increment_mfa_challenge_failures(challenge.id)?;
+        }
+
+        return Err(ServiceError::BadRequest("Invalid code".into()));
+    }
+
+    repository::delete_mfa_challenge(challenge.id)?;
+    service::start_session(challenge.user_id, true)
+}
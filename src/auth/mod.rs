@@ -1,5 +1,11 @@
+mod attempts;
 pub mod controller;
 pub mod crypto;
 mod dto;
+pub mod keys;
+pub mod mfa;
 pub mod models;
+pub mod oidc;
+mod repository;
 pub mod service;
+mod totp;
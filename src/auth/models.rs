@@ -1,10 +1,179 @@
+use diesel::{deserialize::Queryable, prelude::Insertable, query_builder::AsChangeset, Selectable};
 use serde::{Deserialize, Serialize};
 
-use crate::role::enm::RoleEnum;
+use crate::schema::{
+    impersonations, login_attempts, mfa_challenges, mfa_recovery_codes, oidc_login_states,
+    password_reset_tokens, sessions, totp_credentials, user_identities,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 
 pub struct LoggedUser {
     pub id: i32,
-    pub roles: Vec<RoleEnum>,
+    pub roles: Vec<String>,
+    /// Granted by the roles, see `role::service::permissions_of`.
+    pub permissions: Vec<String>,
+    pub jti: String,
+    /// Set when the request came with a personal access token, which can only do what
+    /// its scopes allow. Login sessions aren't limited.
+    pub scopes: Option<Vec<String>>,
+    /// Set when someone else is acting as the user with an impersonation token, which
+    /// can only read.
+    pub impersonator_id: Option<i32>,
+}
+
+impl LoggedUser {
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.permissions.iter().any(|p| p == permission)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = sessions)]
+pub struct Session {
+    pub id: i32,
+    pub jti: String,
+    pub user_id: i32,
+    pub refresh_token_hash: String,
+    pub expires_at: chrono::NaiveDateTime,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub mfa_verified: bool,
+    /// The refresh token this session had before the last rotation. Seeing it again
+    /// means someone kept a copy.
+    pub previous_refresh_token_hash: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = sessions)]
+pub struct NewSession<'a> {
+    pub jti: &'a str,
+    pub user_id: i32,
+    pub refresh_token_hash: &'a str,
+    pub expires_at: chrono::NaiveDateTime,
+    pub mfa_verified: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = password_reset_tokens)]
+pub struct PasswordResetToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub expires_at: chrono::NaiveDateTime,
+    pub used_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = password_reset_tokens)]
+pub struct NewPasswordResetToken<'a> {
+    pub user_id: i32,
+    pub token_hash: &'a str,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Selectable, Insertable, AsChangeset)]
+#[diesel(table_name = login_attempts)]
+pub struct LoginAttempt {
+    pub key: String,
+    pub failures: i32,
+    pub last_failure_at: chrono::NaiveDateTime,
+    pub blocked_until: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = oidc_login_states)]
+pub struct OidcLoginState {
+    pub id: i32,
+    pub state_hash: String,
+    pub provider: String,
+    pub code_verifier: String,
+    pub nonce: String,
+    pub expires_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    /// Set when a logged in user is adding the identity to their account.
+    pub link_user_id: Option<i32>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = oidc_login_states)]
+pub struct NewOidcLoginState<'a> {
+    pub state_hash: &'a str,
+    pub provider: &'a str,
+    pub code_verifier: &'a str,
+    pub nonce: &'a str,
+    pub expires_at: chrono::NaiveDateTime,
+    pub link_user_id: Option<i32>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = user_identities)]
+pub struct NewUserIdentity<'a> {
+    pub user_id: i32,
+    pub provider: &'a str,
+    pub subject: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = totp_credentials)]
+pub struct TotpCredential {
+    pub user_id: i32,
+    pub secret: String,
+    pub confirmed_at: Option<chrono::NaiveDateTime>,
+    pub last_used_step: Option<i64>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = mfa_recovery_codes)]
+pub struct MfaRecoveryCode {
+    pub id: i32,
+    pub user_id: i32,
+    pub code_hash: String,
+    pub used_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = mfa_challenges)]
+pub struct MfaChallenge {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub failures: i32,
+    pub expires_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = mfa_challenges)]
+pub struct NewMfaChallenge<'a> {
+    pub user_id: i32,
+    pub token_hash: &'a str,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = impersonations)]
+pub struct Impersonation {
+    pub id: i32,
+    pub jti: String,
+    pub actor_id: i32,
+    pub user_id: i32,
+    pub reason: String,
+    pub expires_at: chrono::NaiveDateTime,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = impersonations)]
+pub struct NewImpersonation<'a> {
+    pub jti: &'a str,
+    pub actor_id: i32,
+    pub user_id: i32,
+    pub reason: &'a str,
+    pub expires_at: chrono::NaiveDateTime,
 }
@@ -0,0 +1,444 @@
+use std::{collections::HashMap, time::Duration};
+
+use actix_web::{
+    cookie::{self, Cookie, SameSite},
+    HttpRequest,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, DecodingKey, Validation};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{cache::TtlCache, errors::ServiceError};
+
+/// An OpenID Connect provider users can sign in with.
+///
+/// Providers are listed in `OIDC_PROVIDERS` (comma separated names) and each one is read
+/// from `OIDC_<NAME>_ISSUER`, `OIDC_<NAME>_CLIENT_ID`, `OIDC_<NAME>_CLIENT_SECRET`
+/// (optional for public clients, PKCE is always used), `OIDC_<NAME>_REDIRECT_URI` and
+/// `OIDC_<NAME>_SCOPES` (defaults to `openid email profile`). The endpoints are discovered
+/// from the issuer, so pointing it to a local mock IdP is enough to test the whole flow.
+#[derive(Debug, Clone)]
+pub struct OidcProvider {
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uri: String,
+    pub scopes: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub nonce: Option<String>,
+}
+
+fn load_providers() -> HashMap<String, OidcProvider> {
+    let names = std::env::var("OIDC_PROVIDERS").unwrap_or_default();
+
+    names
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let var = |key: &str| std::env::var(format!("OIDC_{}_{}", name.to_uppercase(), key));
+            let required = |key: &str| {
+                var(key)
+                    .unwrap_or_else(|_| panic!("OIDC_{}_{} must be set", name.to_uppercase(), key))
+            };
+
+            let provider = OidcProvider {
+                issuer: required("ISSUER").trim_end_matches('/').to_string(),
+                client_id: required("CLIENT_ID"),
+                client_secret: var("CLIENT_SECRET").ok().filter(|s| !s.is_empty()),
+                redirect_uri: required("REDIRECT_URI"),
+                scopes: var("SCOPES").unwrap_or("openid email profile".to_string()),
+                name: name.clone(),
+            };
+
+            (name, provider)
+        })
+        .collect()
+}
+
+lazy_static! {
+    pub static ref OIDC_PROVIDERS: HashMap<String, OidcProvider> = load_providers();
+    static ref METADATA: TtlCache<String, ProviderMetadata> =
+        TtlCache::new(Duration::from_secs(3600));
+    static ref JWKS: TtlCache<String, JwkSet> = TtlCache::new(Duration::from_secs(3600));
+}
+
+pub fn get_provider(name: &str) -> Result<&'static OidcProvider, ServiceError> {
+    OIDC_PROVIDERS
+        .get(name)
+        .ok_or(ServiceError::BadRequest("Unknown provider".to_string()))
+}
+
+/// S256 code challenge of a PKCE verifier (RFC 7636).
+pub fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn upstream_error(provider: &OidcProvider, e: reqwest::Error) -> ServiceError {
+    log::warn!("request to oidc provider {} failed: {}", provider.name, e);
+    ServiceError::InternalServerError
+}
+
+async fn get_json<T: serde::de::DeserializeOwned>(
+    provider: &OidcProvider,
+    url: &str,
+) -> Result<T, ServiceError> {
+    reqwest::get(url)
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| upstream_error(provider, e))?
+        .json()
+        .await
+        .map_err(|e| upstream_error(provider, e))
+}
+
+pub async fn metadata(provider: &OidcProvider) -> Result<ProviderMetadata, ServiceError> {
+    if let Some(metadata) = METADATA.get(&provider.name) {
+        return Ok(metadata);
+    }
+
+    let url = format!("{}/.well-known/openid-configuration", provider.issuer);
+    let metadata: ProviderMetadata = get_json(provider, &url).await?;
+
+    if metadata.issuer.trim_end_matches('/') != provider.issuer {
+        log::warn!("oidc provider {} reports another issuer", provider.name);
+        return Err(ServiceError::InternalServerError);
+    }
+
+    METADATA.insert(provider.name.clone(), metadata.clone());
+    Ok(metadata)
+}
+
+pub fn authorization_url(
+    provider: &OidcProvider,
+    metadata: &ProviderMetadata,
+    state: &str,
+    nonce: &str,
+    code_verifier: &str,
+) -> Result<String, ServiceError> {
+    let mut url = url::Url::parse(&metadata.authorization_endpoint)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", &provider.redirect_uri)
+        .append_pair("scope", &provider.scopes)
+        .append_pair("state", state)
+        .append_pair("nonce", nonce)
+        .append_pair("code_challenge", &code_challenge(code_verifier))
+        .append_pair("code_challenge_method", "S256");
+
+    Ok(url.into())
+}
+
+/// Holds the state in the browser that started the login, so a callback URL handed to
+/// someone else is refused. Lax because the provider sends the user back cross-site.
+pub const STATE_COOKIE: &str = "oidc_state";
+const STATE_COOKIE_PATH: &str = "/auth/oidc";
+
+pub fn state_cookie(
+    provider: &OidcProvider,
+    state: &str,
+    ttl: chrono::Duration,
+) -> Cookie<'static> {
+    Cookie::build(STATE_COOKIE, state.to_string())
+        .path(STATE_COOKIE_PATH)
+        .http_only(true)
+        .secure(provider.redirect_uri.starts_with("https://"))
+        .same_site(SameSite::Lax)
+        .max_age(cookie::time::Duration::seconds(ttl.num_seconds()))
+        .finish()
+}
+
+/// Clears the state cookie once the callback went through.
+pub fn removed_state_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::build(STATE_COOKIE, "")
+        .path(STATE_COOKIE_PATH)
+        .finish();
+    cookie.make_removal();
+    cookie
+}
+
+/// Fails unless the callback came with the cookie set for `state`.
+pub fn check_state_cookie(req: &HttpRequest, state: &str) -> Result<(), ServiceError> {
+    match req.cookie(STATE_COOKIE) {
+        Some(cookie) if bool::from(cookie.value().as_bytes().ct_eq(state.as_bytes())) => Ok(()),
+        _ => Err(ServiceError::BadRequest("Invalid state".to_string())),
+    }
+}
+
+/// Trades the authorization code for tokens and returns the verified id token claims.
+pub async fn exchange_code(
+    provider: &OidcProvider,
+    metadata: &ProviderMetadata,
+    code: &str,
+    code_verifier: &str,
+    nonce: &str,
+) -> Result<IdTokenClaims, ServiceError> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", &provider.redirect_uri),
+        ("client_id", &provider.client_id),
+        ("code_verifier", code_verifier),
+    ];
+
+    if let Some(client_secret) = &provider.client_secret {
+        form.push(("client_secret", client_secret));
+    }
+
+    let response = reqwest::Client::new()
+        .post(&metadata.token_endpoint)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| upstream_error(provider, e))?;
+
+    if !response.status().is_success() {
+        return Err(ServiceError::BadRequest(
+            "Authorization code was rejected".to_string(),
+        ));
+    }
+
+    let tokens: TokenResponse = response
+        .json()
+        .await
+        .map_err(|e| upstream_error(provider, e))?;
+
+    let claims = verify_id_token(provider, metadata, &tokens.id_token).await?;
+
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    Ok(claims)
+}
+
+async fn verify_id_token(
+    provider: &OidcProvider,
+    metadata: &ProviderMetadata,
+    id_token: &str,
+) -> Result<IdTokenClaims, ServiceError> {
+    let header = jsonwebtoken::decode_header(id_token).map_err(|_| ServiceError::Unauthorized)?;
+
+    let mut jwks = match JWKS.get(&provider.name) {
+        Some(jwks) => jwks,
+        None => get_json(provider, &metadata.jwks_uri).await?,
+    };
+
+    // The provider may have rotated its keys since we cached them.
+    let has_key = |jwks: &JwkSet| match &header.kid {
+        Some(kid) => jwks.find(kid).is_some(),
+        None => !jwks.keys.is_empty(),
+    };
+
+    if !has_key(&jwks) {
+        jwks = get_json(provider, &metadata.jwks_uri).await?;
+    }
+
+    JWKS.insert(provider.name.clone(), jwks.clone());
+
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }
+    .ok_or(ServiceError::Unauthorized)?;
+
+    let key = DecodingKey::from_jwk(jwk).map_err(|_| ServiceError::Unauthorized)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_issuer(&[&provider.issuer, &metadata.issuer]);
+
+    let token_data = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+        .map_err(|_| ServiceError::Unauthorized)?;
+
+    Ok(token_data.claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use jsonwebtoken::{EncodingKey, Header};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"mock-idp-signing-secret";
+
+    #[test]
+    fn code_challenge_is_unpadded_url_safe_sha256() {
+        assert_eq!(
+            code_challenge("abc"),
+            "ungWv48Bz-pBQUDeXa4iI7ADYaOWF3qctBD_YfIAFa0"
+        );
+    }
+
+    #[test]
+    fn authorization_url_carries_pkce_state_and_nonce() {
+        let provider = OidcProvider {
+            name: "school".to_string(),
+            issuer: "http://localhost:8080".to_string(),
+            client_id: "miniprova".to_string(),
+            client_secret: None,
+            redirect_uri: "http://localhost:3000/auth/oidc/school/callback".to_string(),
+            scopes: "openid email".to_string(),
+        };
+        let metadata = ProviderMetadata {
+            issuer: provider.issuer.clone(),
+            authorization_endpoint: "http://localhost:8080/authorize".to_string(),
+            token_endpoint: "http://localhost:8080/token".to_string(),
+            jwks_uri: "http://localhost:8080/jwks".to_string(),
+        };
+
+        let url = authorization_url(&provider, &metadata, "the-state", "the-nonce", "abc").unwrap();
+        let url = url::Url::parse(&url).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(query["response_type"], "code");
+        assert_eq!(query["client_id"], "miniprova");
+        assert_eq!(query["redirect_uri"], provider.redirect_uri);
+        assert_eq!(query["scope"], "openid email");
+        assert_eq!(query["state"], "the-state");
+        assert_eq!(query["nonce"], "the-nonce");
+        assert_eq!(query["code_challenge"], code_challenge("abc"));
+        assert_eq!(query["code_challenge_method"], "S256");
+    }
+
+    /// Stand-in for an identity provider: discovery, a token endpoint that only takes
+    /// `the-code` with `the-verifier`, and the key its id tokens are signed with.
+    async fn mock_idp(req: actix_web::HttpRequest, body: web::Bytes) -> HttpResponse {
+        let issuer = format!("http://{}", req.connection_info().host());
+
+        match req.path() {
+            "/.well-known/openid-configuration" => HttpResponse::Ok().json(serde_json::json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/authorize", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "jwks_uri": format!("{}/jwks", issuer),
+            })),
+            "/jwks" => HttpResponse::Ok().json(serde_json::json!({
+                "keys": [{ "kty": "oct", "kid": "mock", "k": URL_SAFE_NO_PAD.encode(SECRET) }],
+            })),
+            "/token" => {
+                let form: HashMap<String, String> =
+                    url::form_urlencoded::parse(&body).into_owned().collect();
+                if form.get("code").map(String::as_str) != Some("the-code")
+                    || form.get("code_verifier").map(String::as_str) != Some("the-verifier")
+                {
+                    return HttpResponse::BadRequest().finish();
+                }
+
+                let claims = serde_json::json!({
+                    "iss": issuer,
+                    "aud": "miniprova",
+                    "sub": "student-1",
+                    "email": "student@school.test",
+                    "email_verified": true,
+                    "nonce": "the-nonce",
+                    "exp": chrono::Utc::now().timestamp() + 300,
+                });
+                let mut header = Header::new(jsonwebtoken::Algorithm::HS256);
+                header.kid = Some("mock".to_string());
+                let id_token =
+                    jsonwebtoken::encode(&header, &claims, &EncodingKey::from_secret(SECRET))
+                        .unwrap();
+
+                HttpResponse::Ok().json(serde_json::json!({ "id_token": id_token }))
+            }
+            _ => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn callback_exchanges_the_code_with_a_mock_idp() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = HttpServer::new(|| App::new().default_service(web::to(mock_idp)))
+            .workers(1)
+            .listen(listener)
+            .unwrap();
+        actix_web::rt::spawn(server.run());
+
+        let provider = OidcProvider {
+            name: "mock".to_string(),
+            issuer: format!("http://{}", address),
+            client_id: "miniprova".to_string(),
+            client_secret: None,
+            redirect_uri: "http://localhost:3000/auth/oidc/mock/callback".to_string(),
+            scopes: "openid email".to_string(),
+        };
+        let metadata = metadata(&provider).await.unwrap();
+
+        let started = state_cookie(&provider, "the-state", chrono::Duration::minutes(10));
+        assert!(started.http_only().unwrap_or_default());
+        assert_eq!(started.same_site(), Some(SameSite::Lax));
+
+        // The callback only goes on in the browser that started the login.
+        let callback = |cookie: Option<Cookie<'static>>| {
+            let mut req = actix_web::test::TestRequest::get()
+                .uri("/auth/oidc/mock/callback?code=the-code&state=the-state");
+            if let Some(cookie) = cookie {
+                req = req.cookie(cookie);
+            }
+            check_state_cookie(&req.to_http_request(), "the-state")
+        };
+        assert!(matches!(callback(None), Err(ServiceError::BadRequest(_))));
+        assert!(matches!(
+            callback(Some(state_cookie(
+                &provider,
+                "other-state",
+                chrono::Duration::minutes(10)
+            ))),
+            Err(ServiceError::BadRequest(_))
+        ));
+        callback(Some(started)).unwrap();
+
+        let claims = exchange_code(
+            &provider,
+            &metadata,
+            "the-code",
+            "the-verifier",
+            "the-nonce",
+        )
+        .await
+        .unwrap();
+        assert_eq!(claims.sub, "student-1");
+        assert_eq!(claims.email.as_deref(), Some("student@school.test"));
+        assert!(claims.email_verified);
+
+        let wrong_verifier =
+            exchange_code(&provider, &metadata, "the-code", "other", "the-nonce").await;
+        assert!(matches!(wrong_verifier, Err(ServiceError::BadRequest(_))));
+
+        let replayed =
+            exchange_code(&provider, &metadata, "the-code", "the-verifier", "other").await;
+        assert!(matches!(replayed, Err(ServiceError::Unauthorized)));
+    }
+}
@@ -0,0 +1,707 @@
+use std::error::Error;
+
+use diesel::{
+    dsl::sql,
+    sql_types::{Integer, Nullable, Timestamp},
+    BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+
+use crate::{
+    audit::{self, model::NewAuditEvent},
+    db::DB_MANAGER,
+    errors::ServiceError,
+    schema::{
+        impersonations, login_attempts, mfa_challenges, mfa_recovery_codes, oidc_login_states,
+        password_reset_tokens, sessions, totp_credentials, user_identities, users,
+    },
+};
+
+use super::models::{
+    Impersonation, LoginAttempt, MfaChallenge, MfaRecoveryCode, NewImpersonation, NewMfaChallenge,
+    NewOidcLoginState, NewPasswordResetToken, NewSession, NewUserIdentity, OidcLoginState,
+    PasswordResetToken, Session, TotpCredential,
+};
+
+pub fn create_session(new_session: NewSession) -> Result<Session, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let session = diesel::insert_into(sessions::table)
+        .values(&new_session)
+        .returning(Session::as_returning())
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(session)
+}
+
+pub fn get_session_by_refresh_token_hash(hash: &str) -> Result<Option<Session>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let session = sessions::table
+        .filter(sessions::refresh_token_hash.eq(hash))
+        .select(Session::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(session)
+}
+
+pub fn get_session_by_previous_refresh_token_hash(
+    hash: &str,
+) -> Result<Option<Session>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let session = sessions::table
+        .filter(sessions::previous_refresh_token_hash.eq(hash))
+        .select(Session::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(session)
+}
+
+pub fn get_session_by_jti(session_jti: &str) -> Result<Option<Session>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let session = sessions::table
+        .filter(sessions::jti.eq(session_jti))
+        .select(Session::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(session)
+}
+
+/// Swaps the refresh token only if the session still has `old_hash` and is live, so two
+/// requests racing with the same token can't both win. The old hash is kept to catch it
+/// being replayed later. `None` when nothing matched.
+pub fn rotate_refresh_token(
+    session_id: i32,
+    old_hash: &str,
+    new_hash: &str,
+    new_expires_at: chrono::NaiveDateTime,
+) -> Result<Option<Session>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let session = diesel::update(
+        sessions::table
+            .filter(sessions::id.eq(session_id))
+            .filter(sessions::refresh_token_hash.eq(old_hash))
+            .filter(sessions::revoked_at.is_null())
+            .filter(sessions::expires_at.gt(chrono::Utc::now().naive_utc())),
+    )
+    .set((
+        sessions::refresh_token_hash.eq(new_hash),
+        sessions::previous_refresh_token_hash.eq(old_hash),
+        sessions::expires_at.eq(new_expires_at),
+    ))
+    .returning(Session::as_returning())
+    .get_result(&mut conn)
+    .optional()
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(session)
+}
+
+pub fn revoke_session_by_jti(session_jti: &str) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(
+        sessions::table
+            .filter(sessions::jti.eq(session_jti))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(&mut conn)
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn revoke_sessions_by_user_id(session_user_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(
+        sessions::table
+            .filter(sessions::user_id.eq(session_user_id))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(&mut conn)
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn revoke_other_sessions(session_user_id: i32, kept_jti: &str) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(
+        sessions::table
+            .filter(sessions::user_id.eq(session_user_id))
+            .filter(sessions::jti.ne(kept_jti))
+            .filter(sessions::revoked_at.is_null()),
+    )
+    .set(sessions::revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(&mut conn)
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn create_password_reset_token(
+    new_token: NewPasswordResetToken,
+) -> Result<PasswordResetToken, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let token = diesel::insert_into(password_reset_tokens::table)
+        .values(&new_token)
+        .returning(PasswordResetToken::as_returning())
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(token)
+}
+
+pub fn get_password_reset_token_by_hash(
+    hash: &str,
+) -> Result<Option<PasswordResetToken>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let token = password_reset_tokens::table
+        .filter(password_reset_tokens::token_hash.eq(hash))
+        .select(PasswordResetToken::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(token)
+}
+
+/// Marks the token as used, stores the new password and revokes every session of the user,
+/// all in one transaction so a token can never be consumed twice.
+pub fn reset_password(token_id: i32, user_id: i32, password: &str) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let now = chrono::Utc::now().naive_utc();
+
+    let result: Result<bool, Box<dyn Error>> = conn.transaction(|tx| {
+        let consumed = diesel::update(
+            password_reset_tokens::table
+                .find(token_id)
+                .filter(password_reset_tokens::used_at.is_null()),
+        )
+        .set(password_reset_tokens::used_at.eq(now))
+        .execute(tx)?;
+
+        if consumed == 0 {
+            return Ok(false);
+        }
+
+        // Other links sent before this one stop working too.
+        diesel::update(
+            password_reset_tokens::table
+                .filter(password_reset_tokens::user_id.eq(user_id))
+                .filter(password_reset_tokens::used_at.is_null()),
+        )
+        .set(password_reset_tokens::used_at.eq(now))
+        .execute(tx)?;
+
+        diesel::update(users::table.find(user_id))
+            .set((
+                users::password.eq(password),
+                users::locked_until.eq(None::<chrono::NaiveDateTime>),
+            ))
+            .execute(tx)?;
+
+        diesel::update(
+            sessions::table
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::revoked_at.is_null()),
+        )
+        .set(sessions::revoked_at.eq(now))
+        .execute(tx)?;
+
+        Ok(true)
+    });
+
+    match result {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ServiceError::BadRequest(
+            "Invalid or expired reset token".into(),
+        )),
+        Err(_) => Err(ServiceError::InternalServerError),
+    }
+}
+
+pub fn get_login_attempt(attempt_key: &str) -> Result<Option<LoginAttempt>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let attempt = login_attempts::table
+        .find(attempt_key)
+        .select(LoginAttempt::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(attempt)
+}
+
+/// One upsert, so failures racing on the same key are all counted.
+pub fn increment_login_attempt(
+    attempt_key: &str,
+    now: chrono::NaiveDateTime,
+    window_start: chrono::NaiveDateTime,
+) -> Result<LoginAttempt, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let attempt = diesel::insert_into(login_attempts::table)
+        .values(LoginAttempt {
+            key: attempt_key.to_string(),
+            failures: 1,
+            last_failure_at: now,
+            blocked_until: None,
+        })
+        .on_conflict(login_attempts::key)
+        .do_update()
+        .set((
+            login_attempts::failures.eq(sql::<Integer>(
+                "CASE WHEN login_attempts.last_failure_at > ",
+            )
+            .bind::<Timestamp, _>(window_start)
+            .sql(" THEN login_attempts.failures + 1 ELSE 1 END")),
+            login_attempts::blocked_until.eq(sql::<Nullable<Timestamp>>(
+                "CASE WHEN login_attempts.last_failure_at > ",
+            )
+            .bind::<Timestamp, _>(window_start)
+            .sql(" THEN login_attempts.blocked_until END")),
+            login_attempts::last_failure_at.eq(now),
+        ))
+        .returning(LoginAttempt::as_returning())
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(attempt)
+}
+
+pub fn block_login_attempt(
+    attempt_key: &str,
+    until: chrono::NaiveDateTime,
+) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(
+        login_attempts::table.find(attempt_key).filter(
+            login_attempts::blocked_until
+                .is_null()
+                .or(login_attempts::blocked_until.lt(until)),
+        ),
+    )
+    .set(login_attempts::blocked_until.eq(until))
+    .execute(&mut conn)
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn delete_login_attempt(attempt_key: &str) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::delete(login_attempts::table.find(attempt_key))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+/// Lifts a lockout on behalf of an admin.
+pub fn unlock_user(actor_id: i32, user_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let locked_until: Option<chrono::NaiveDateTime> =
+            diesel::update(users::table.find(user_id))
+                .set(users::locked_until.eq(None::<chrono::NaiveDateTime>))
+                .returning(users::locked_until)
+                .get_result(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "unlock",
+                "user",
+                user_id,
+                Some(serde_json::json!({ "locked_until": locked_until })),
+                Some(serde_json::json!({ "locked_until": null })),
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+/// Replaces the password of a user with one nobody knows, on behalf of an admin.
+pub fn force_password_reset(
+    actor_id: i32,
+    user_id: i32,
+    hashed_password: &str,
+) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        diesel::update(users::table.find(user_id))
+            .set(users::password.eq(hashed_password))
+            .execute(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "force_password_reset",
+                "user",
+                user_id,
+                None,
+                None,
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn set_user_locked_until(
+    user_id: i32,
+    until: Option<chrono::NaiveDateTime>,
+) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(users::table.find(user_id))
+        .set(users::locked_until.eq(until))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn create_oidc_login_state(new_state: NewOidcLoginState) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::insert_into(oidc_login_states::table)
+        .values(&new_state)
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+/// Deletes and returns the state, so each one can only be used once.
+pub fn take_oidc_login_state(state_hash: &str) -> Result<Option<OidcLoginState>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let state = diesel::delete(
+        oidc_login_states::table.filter(oidc_login_states::state_hash.eq(state_hash)),
+    )
+    .returning(OidcLoginState::as_returning())
+    .get_result(&mut conn)
+    .optional()
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(state)
+}
+
+pub fn delete_expired_oidc_login_states() -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::delete(
+        oidc_login_states::table
+            .filter(oidc_login_states::expires_at.lt(chrono::Utc::now().naive_utc())),
+    )
+    .execute(&mut conn)
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn get_user_id_by_identity(provider: &str, subject: &str) -> Result<Option<i32>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let user_id = user_identities::table
+        .filter(user_identities::provider.eq(provider))
+        .filter(user_identities::subject.eq(subject))
+        .select(user_identities::user_id)
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(user_id)
+}
+
+pub fn create_user_identity(new_identity: NewUserIdentity) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::insert_into(user_identities::table)
+        .values(&new_identity)
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn set_session_mfa_verified(session_jti: &str) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(sessions::table.filter(sessions::jti.eq(session_jti)))
+        .set(sessions::mfa_verified.eq(true))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn get_totp_credential(user_id: i32) -> Result<Option<TotpCredential>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let credential = totp_credentials::table
+        .find(user_id)
+        .select(TotpCredential::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(credential)
+}
+
+/// Starts over an enrollment, replacing any secret that wasn't confirmed yet.
+pub fn save_pending_totp_secret(user_id: i32, secret: &str) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::insert_into(totp_credentials::table)
+        .values((
+            totp_credentials::user_id.eq(user_id),
+            totp_credentials::secret.eq(secret),
+        ))
+        .on_conflict(totp_credentials::user_id)
+        .do_update()
+        .set((
+            totp_credentials::secret.eq(secret),
+            totp_credentials::confirmed_at.eq(None::<chrono::NaiveDateTime>),
+            totp_credentials::last_used_step.eq(None::<i64>),
+        ))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+/// Stores the last step used, only if it moved forward, so two requests can't use the
+/// same code. Returns false when another request got there first.
+pub fn use_totp_step(user_id: i32, step: i64) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let updated = diesel::update(
+        totp_credentials::table.find(user_id).filter(
+            totp_credentials::last_used_step
+                .is_null()
+                .or(totp_credentials::last_used_step.lt(step)),
+        ),
+    )
+    .set(totp_credentials::last_used_step.eq(step))
+    .execute(&mut conn)
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(updated > 0)
+}
+
+pub fn confirm_totp_credential(
+    user_id: i32,
+    recovery_code_hashes: &[String],
+) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        diesel::update(totp_credentials::table.find(user_id))
+            .set(totp_credentials::confirmed_at.eq(chrono::Utc::now().naive_utc()))
+            .execute(tx)?;
+
+        replace_recovery_codes(tx, user_id, recovery_code_hashes)?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+fn replace_recovery_codes(
+    conn: &mut diesel::PgConnection,
+    user_id: i32,
+    code_hashes: &[String],
+) -> Result<(), diesel::result::Error> {
+    diesel::delete(mfa_recovery_codes::table.filter(mfa_recovery_codes::user_id.eq(user_id)))
+        .execute(conn)?;
+
+    diesel::insert_into(mfa_recovery_codes::table)
+        .values(
+            code_hashes
+                .iter()
+                .map(|hash| {
+                    (
+                        mfa_recovery_codes::user_id.eq(user_id),
+                        mfa_recovery_codes::code_hash.eq(hash),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+        .execute(conn)?;
+
+    Ok(())
+}
+
+pub fn regenerate_recovery_codes(user_id: i32, code_hashes: &[String]) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    conn.transaction(|tx| replace_recovery_codes(tx, user_id, code_hashes))
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn delete_totp_credential(user_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        diesel::delete(totp_credentials::table.find(user_id)).execute(tx)?;
+        diesel::delete(mfa_recovery_codes::table.filter(mfa_recovery_codes::user_id.eq(user_id)))
+            .execute(tx)?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+/// Marks the recovery code as used, returns false if it doesn't exist or was already used.
+pub fn use_recovery_code(user_id: i32, code_hash: &str) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let used = diesel::update(
+        mfa_recovery_codes::table
+            .filter(mfa_recovery_codes::user_id.eq(user_id))
+            .filter(mfa_recovery_codes::code_hash.eq(code_hash))
+            .filter(mfa_recovery_codes::used_at.is_null()),
+    )
+    .set(mfa_recovery_codes::used_at.eq(chrono::Utc::now().naive_utc()))
+    .returning(MfaRecoveryCode::as_returning())
+    .get_result(&mut conn)
+    .optional()
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(used.is_some())
+}
+
+pub fn create_mfa_challenge(new_challenge: NewMfaChallenge) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::insert_into(mfa_challenges::table)
+        .values(&new_challenge)
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn get_mfa_challenge_by_hash(token_hash: &str) -> Result<Option<MfaChallenge>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let challenge = mfa_challenges::table
+        .filter(mfa_challenges::token_hash.eq(token_hash))
+        .select(MfaChallenge::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(challenge)
+}
+
+pub fn increment_mfa_challenge_failures(challenge_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(mfa_challenges::table.find(challenge_id))
+        .set(mfa_challenges::failures.eq(mfa_challenges::failures + 1))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn delete_mfa_challenge(challenge_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::delete(mfa_challenges::table.find(challenge_id))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+pub fn create_impersonation(new_impersonation: NewImpersonation) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        diesel::insert_into(impersonations::table)
+            .values(&new_impersonation)
+            .execute(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                new_impersonation.actor_id,
+                "impersonate",
+                "user",
+                new_impersonation.user_id,
+                None,
+                Some(serde_json::json!({
+                    "reason": new_impersonation.reason,
+                    "expires_at": new_impersonation.expires_at,
+                })),
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn get_impersonation_by_jti(
+    impersonation_jti: &str,
+) -> Result<Option<Impersonation>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    impersonations::table
+        .filter(impersonations::jti.eq(impersonation_jti))
+        .select(Impersonation::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn revoke_impersonations_by_actor_id(actor_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(
+        impersonations::table
+            .filter(impersonations::actor_id.eq(actor_id))
+            .filter(impersonations::revoked_at.is_null()),
+    )
+    .set(impersonations::revoked_at.eq(chrono::Utc::now().naive_utc()))
+    .execute(&mut conn)
+    .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
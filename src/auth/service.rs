@@ -1,27 +1,679 @@
-use crate::{errors::ServiceError, user};
+use std::time::Duration;
+
+use actix_web::cookie::Cookie;
+use lazy_static::lazy_static;
+
+use crate::{
+    cache::TtlCache,
+    errors::ServiceError,
+    mailer::{Mail, MAILER},
+    policy, role, user,
+};
 
 use super::{
-    crypto,
-    dto::{LoginUserInputDto, LoginUserOutputDto},
+    attempts::{self, LOGIN_ATTEMPTS},
+    crypto::{self, Claims, RichClaims},
+    dto::{
+        ForgotPasswordInputDto, ImpersonateInputDto, ImpersonationOutputDto, LoginOutputDto,
+        LoginUserInputDto, LoginUserOutputDto, OidcCallbackInputDto, RefreshTokenInputDto,
+        ResetPasswordInputDto,
+    },
+    mfa,
+    models::{
+        LoggedUser, NewImpersonation, NewOidcLoginState, NewPasswordResetToken, NewSession,
+        NewUserIdentity,
+    },
+    oidc, repository,
 };
 
-pub fn login(login: LoginUserInputDto) -> Result<LoginUserOutputDto, ServiceError> {
+pub fn login(
+    login: LoginUserInputDto,
+    client_ip: Option<String>,
+) -> Result<LoginOutputDto, ServiceError> {
+    let now = chrono::Utc::now().naive_utc();
+    let store = LOGIN_ATTEMPTS.as_ref();
+
+    let mut keys = vec![attempts::email_key(&login.email)];
+    if let Some(ip) = &client_ip {
+        keys.push(attempts::ip_key(ip));
+    }
+
+    for key in &keys {
+        if let Some(attempt) = store.get(key)? {
+            if let Some(retry_after) = attempts::retry_after(&attempt, now) {
+                return Err(ServiceError::TooManyRequests(retry_after));
+            }
+        }
+    }
+
     let user = user::repository::get_user_by_email(&login.email)
         .map_err(|_| ServiceError::InternalServerError)?;
-    if user.is_none() {
+
+    if let Some(locked_until) = user.as_ref().and_then(|user| user.locked_until) {
+        if locked_until > now {
+            return Err(ServiceError::TooManyRequests(
+                (locked_until - now).num_seconds().max(1),
+            ));
+        }
+    }
+
+    // Unknown emails still pay for a hash check, so timing doesn't tell which ones exist.
+    let is_valid = match &user {
+        Some(user) => crypto::verify_password(&login.password, &user.password)?,
+        None => {
+            crypto::verify_password(&login.password, &DUMMY_PASSWORD_HASH)?;
+            false
+        }
+    };
+
+    if !is_valid {
+        let mut lock_account = false;
+        for key in &keys {
+            let attempt = store.record_failure(key, now)?;
+            if key.starts_with("email:") {
+                lock_account = attempts::reaches_lockout(&attempt);
+            }
+        }
+
+        if let Some(user) = &user {
+            if lock_account {
+                repository::set_user_locked_until(
+                    user.id,
+                    Some(now + attempts::account_lockout_duration()),
+                )?;
+            }
+        }
+
         return Err(ServiceError::BadRequest(
             "We couldn't find a user with these credentials".into(),
         ));
     }
 
     let user = user.unwrap();
-    let is_valid = crypto::verify_password(&login.password, &user.password)?;
-    if !is_valid {
+    store.clear(&attempts::email_key(&login.email))?;
+
+    if crypto::needs_rehash(&user.password) {
+        let rehashed = crypto::encrypt_password(&login.password)
+            .and_then(|hash| user::repository::update_password(user.id, &hash));
+
+        if rehashed.is_err() {
+            log::warn!("could not rehash the password of user {}", user.id);
+        }
+    }
+
+    finish_login(user.id)
+}
+
+/// Opens a session for a user whose first factor checked out, unless they enrolled a
+/// second one, in which case they get a challenge to answer first.
+pub fn finish_login(user_id: i32) -> Result<LoginOutputDto, ServiceError> {
+    if mfa::is_enrolled(user_id)? {
+        return Ok(LoginOutputDto::MfaRequired(mfa::create_challenge(user_id)?));
+    }
+
+    Ok(LoginOutputDto::Session(start_session(user_id, false)?))
+}
+
+pub fn start_session(user_id: i32, mfa_verified: bool) -> Result<LoginUserOutputDto, ServiceError> {
+    if !user::service::is_active(user_id)? {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let jti = crypto::generate_opaque_token(32);
+    let refresh_token = crypto::generate_opaque_token(64);
+
+    repository::create_session(NewSession {
+        jti: &jti,
+        user_id,
+        refresh_token_hash: &crypto::hash_token(&refresh_token),
+        expires_at: (chrono::Utc::now() + crypto::refresh_token_ttl()).naive_utc(),
+        mfa_verified,
+    })?;
+
+    let token = issue_access_token(user_id, &jti, mfa_verified)?;
+    Ok(LoginUserOutputDto {
+        token,
+        refresh_token,
+    })
+}
+
+/// Trades a refresh token for a new pair. A token used twice means it leaked, so the
+/// session is revoked, whether the second use races the first or comes after the
+/// rotation.
+pub fn refresh(input: RefreshTokenInputDto) -> Result<LoginUserOutputDto, ServiceError> {
+    let old_hash = crypto::hash_token(&input.refresh_token);
+    let session = repository::get_session_by_refresh_token_hash(&old_hash)?;
+
+    let session = match session {
+        Some(session) => session,
+        None => {
+            if let Some(session) =
+                repository::get_session_by_previous_refresh_token_hash(&old_hash)?
+            {
+                logout(&session.jti)?;
+            }
+            return Err(ServiceError::Unauthorized);
+        }
+    };
+
+    if session.revoked_at.is_some() || session.expires_at < chrono::Utc::now().naive_utc() {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    let refresh_token = crypto::generate_opaque_token(64);
+    let session = match repository::rotate_refresh_token(
+        session.id,
+        &old_hash,
+        &crypto::hash_token(&refresh_token),
+        (chrono::Utc::now() + crypto::refresh_token_ttl()).naive_utc(),
+    )? {
+        Some(session) => session,
+        None => {
+            logout(&session.jti)?;
+            return Err(ServiceError::Unauthorized);
+        }
+    };
+
+    let token = issue_access_token(session.user_id, &session.jti, session.mfa_verified)?;
+    Ok(LoginUserOutputDto {
+        token,
+        refresh_token,
+    })
+}
+
+fn issue_access_token(user_id: i32, jti: &str, mfa: bool) -> Result<String, ServiceError> {
+    if !crypto::rich_claims_enabled() {
+        return crypto::generate_token(user_id, jti, mfa, None);
+    }
+
+    // The version is read before the roles so a concurrent role change can only leave
+    // the token with an older version, which gets revalidated on use.
+    let version = user::service::get_token_version(user_id)?;
+    let user = user::service::get_user_with_roles_by_id(user_id)?;
+
+    let (version, user) = match (version, user) {
+        (Some(version), Some(user)) => (version, user),
+        _ => return Err(ServiceError::Unauthorized),
+    };
+
+    crypto::generate_token(
+        user_id,
+        jti,
+        mfa,
+        Some(RichClaims {
+            roles: user.roles,
+            ver: version,
+            email_verified: user.email_verified_at.is_some(),
+        }),
+    )
+}
+
+fn session_cache_ttl() -> Duration {
+    let seconds = std::env::var("SESSION_CACHE_SECONDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+
+    Duration::from_secs(seconds)
+}
+
+lazy_static! {
+    // Active sessions by jti, only used with rich claims. Revocations done by this
+    // instance evict right away, other instances notice within the ttl.
+    static ref ACTIVE_SESSIONS: TtlCache<String, i32> = TtlCache::new(session_cache_ttl());
+    // Checked against when the email is unknown. Hashed with the current settings so it
+    // costs the same as a real one.
+    static ref DUMMY_PASSWORD_HASH: String =
+        crypto::encrypt_password(&crypto::generate_opaque_token(32))
+            .expect("could not hash the dummy password");
+}
+
+pub fn logout(jti: &str) -> Result<(), ServiceError> {
+    repository::revoke_session_by_jti(jti)?;
+    ACTIVE_SESSIONS.remove(&jti.to_string());
+    Ok(())
+}
+
+pub fn logout_all(user_id: i32) -> Result<(), ServiceError> {
+    repository::revoke_sessions_by_user_id(user_id)?;
+    repository::revoke_impersonations_by_actor_id(user_id)?;
+    ACTIVE_SESSIONS.retain(|_, session_user_id| *session_user_id != user_id);
+    Ok(())
+}
+
+/// Signs the user out everywhere but the session with `jti`.
+pub fn logout_others(user_id: i32, jti: &str) -> Result<(), ServiceError> {
+    repository::revoke_other_sessions(user_id, jti)?;
+    ACTIVE_SESSIONS
+        .retain(|session_jti, session_user_id| *session_user_id != user_id || session_jti == jti);
+    Ok(())
+}
+
+pub fn is_session_active(jti: &str) -> Result<bool, ServiceError> {
+    let use_cache = crypto::rich_claims_enabled();
+
+    if use_cache && ACTIVE_SESSIONS.get(&jti.to_string()).is_some() {
+        return Ok(true);
+    }
+
+    let session = repository::get_session_by_jti(jti)?;
+
+    Ok(match session {
+        Some(session) if session.revoked_at.is_none() => {
+            if use_cache {
+                ACTIVE_SESSIONS.insert(session.jti, session.user_id);
+            }
+            true
+        }
+        _ => false,
+    })
+}
+
+fn impersonation_ttl() -> chrono::Duration {
+    let minutes = std::env::var("IMPERSONATION_TTL_MINUTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(15);
+
+    chrono::Duration::minutes(minutes)
+}
+
+/// Gives `actor`, whom the policy allowed to, a short-lived token to see the app as
+/// `user_id` does. The token can't change anything and isn't refreshable, and it has
+/// passed the second factor only if the actor's session has.
+pub fn impersonate(
+    actor: &LoggedUser,
+    user_id: i32,
+    input: ImpersonateInputDto,
+) -> Result<ImpersonationOutputDto, ServiceError> {
+    let actor_id = actor.id;
+    if actor_id == user_id {
         return Err(ServiceError::BadRequest(
-            "We couldn't find a user with these credentials".into(),
+            "You can't impersonate yourself".into(),
+        ));
+    }
+
+    let user = match user::service::get_user_with_roles_by_id(user_id)? {
+        Some(user) => user,
+        None => return Err(ServiceError::BadRequest("User not found".into())),
+    };
+
+    if !user.active {
+        return Err(ServiceError::BadRequest("User is deactivated".into()));
+    }
+
+    if role::service::permissions_of(&user.roles)?
+        .iter()
+        .any(|permission| permission == policy::SUPERUSER_PERMISSION)
+    {
+        return Err(ServiceError::Forbidden);
+    }
+
+    let mfa = match repository::get_session_by_jti(&actor.jti)? {
+        Some(session) => session.mfa_verified,
+        None => return Err(ServiceError::Unauthorized),
+    };
+
+    let jti = crypto::generate_opaque_token(32);
+    let expires_at = (chrono::Utc::now() + impersonation_ttl()).naive_utc();
+
+    repository::create_impersonation(NewImpersonation {
+        jti: &jti,
+        actor_id,
+        user_id,
+        reason: input.reason.trim(),
+        expires_at,
+    })?;
+
+    log::info!("user {} is impersonating user {}", actor_id, user_id);
+
+    Ok(ImpersonationOutputDto {
+        token: crypto::generate_impersonation_token(user_id, actor_id, &jti, mfa, expires_at)?,
+        expires_at,
+    })
+}
+
+fn is_impersonation_active(jti: &str, actor_id: i32, user_id: i32) -> Result<bool, ServiceError> {
+    let impersonation = match repository::get_impersonation_by_jti(jti)? {
+        Some(impersonation) => impersonation,
+        None => return Ok(false),
+    };
+
+    if impersonation.actor_id != actor_id
+        || impersonation.user_id != user_id
+        || impersonation.revoked_at.is_some()
+        || impersonation.expires_at < chrono::Utc::now().naive_utc()
+    {
+        return Ok(false);
+    }
+
+    // The actor losing access ends their impersonations too.
+    user::service::is_active(actor_id)
+}
+
+/// Builds the `LoggedUser` of a request from its token claims. Tokens with rich claims
+/// skip the user lookup unless `set_user_roles` bumped the user's version since they
+/// were issued. With `enforce_account_policies`, users that didn't verify their email when
+/// `REQUIRE_EMAIL_VERIFICATION` is on, or whose roles need a second factor their session
+/// didn't go through, are turned away.
+pub fn authenticate(
+    claims: Claims,
+    enforce_account_policies: bool,
+) -> Result<LoggedUser, ServiceError> {
+    let is_active = match &claims.act {
+        Some(actor) => is_impersonation_active(&claims.jti, actor.sub, claims.sub)?,
+        None => is_session_active(&claims.jti)?,
+    };
+
+    if !is_active {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    let impersonator_id = claims.act.as_ref().map(|actor| actor.sub);
+
+    let require_verified_email =
+        enforce_account_policies && user::service::is_email_verification_required();
+
+    let mut logged_user = None;
+
+    if let Some(rich) = &claims.rich {
+        let current_version = user::service::get_token_version(claims.sub)?;
+
+        if current_version == Some(rich.ver) && (rich.email_verified || !require_verified_email) {
+            logged_user = Some(LoggedUser {
+                id: claims.sub,
+                permissions: role::service::permissions_of(&rich.roles)?,
+                roles: rich.roles.clone(),
+                jti: claims.jti.clone(),
+                scopes: None,
+                impersonator_id,
+            });
+        }
+    }
+
+    let logged_user = match logged_user {
+        Some(logged_user) => logged_user,
+        None => {
+            let user = match user::service::get_user_with_roles_by_id(claims.sub)? {
+                Some(user) => user,
+                None => return Err(ServiceError::Unauthorized),
+            };
+
+            if !user.active {
+                return Err(ServiceError::Unauthorized);
+            }
+
+            if require_verified_email && user.email_verified_at.is_none() {
+                return Err(ServiceError::Forbidden);
+            }
+
+            LoggedUser {
+                id: user.id,
+                permissions: role::service::permissions_of(&user.roles)?,
+                roles: user.roles,
+                jti: claims.jti,
+                scopes: None,
+                impersonator_id,
+            }
+        }
+    };
+
+    if enforce_account_policies && !claims.mfa && mfa::is_required_for(&logged_user.roles) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    Ok(logged_user)
+}
+
+fn password_reset_ttl() -> chrono::Duration {
+    let minutes = std::env::var("PASSWORD_RESET_TTL_MINUTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(60);
+
+    chrono::Duration::minutes(minutes)
+}
+
+fn invitation_ttl() -> chrono::Duration {
+    let hours = std::env::var("INVITATION_TTL_HOURS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(72);
+
+    chrono::Duration::hours(hours)
+}
+
+/// Mails a user created by an admin a reset token to choose their first password.
+pub fn send_invitation(user: &user::model::User) -> Result<(), ServiceError> {
+    let token = crypto::generate_opaque_token(64);
+    repository::create_password_reset_token(NewPasswordResetToken {
+        user_id: user.id,
+        token_hash: &crypto::hash_token(&token),
+        expires_at: (chrono::Utc::now() + invitation_ttl()).naive_utc(),
+    })?;
+
+    let mut body = format!(
+        "Hi {},\n\nAn account was created for you on miniprova. Use the token below to choose \
+         your password, it expires in {} hours.\n\n{}\n",
+        user.name,
+        invitation_ttl().num_hours(),
+        token
+    );
+
+    if let Ok(reset_url) = std::env::var("PASSWORD_RESET_URL") {
+        body.push_str(&format!("\nOr open {}?token={}\n", reset_url, token));
+    }
+
+    MAILER.send(&Mail {
+        to: user.email.clone(),
+        subject: "Welcome to miniprova".to_string(),
+        body,
+    })
+}
+
+/// Always succeeds for unknown emails so the endpoint can't be used to probe for accounts.
+pub fn forgot_password(input: ForgotPasswordInputDto) -> Result<(), ServiceError> {
+    match user::repository::get_user_by_email(&input.email)? {
+        Some(user) => send_password_reset(user),
+        None => Ok(()),
+    }
+}
+
+/// Replaces the user's password with one nobody knows, signs them out everywhere and
+/// mails them a reset token.
+pub fn force_password_reset(actor_id: i32, user_id: i32) -> Result<(), ServiceError> {
+    let user = match user::repository::get_user_by_id(user_id)? {
+        Some(user) => user,
+        None => return Err(ServiceError::BadRequest("User not found".into())),
+    };
+
+    let hashed_password = crypto::encrypt_password(&crypto::generate_opaque_token(32))?;
+    repository::force_password_reset(actor_id, user.id, &hashed_password)?;
+    logout_all(user.id)?;
+
+    send_password_reset(user)
+}
+
+fn send_password_reset(user: user::model::User) -> Result<(), ServiceError> {
+    let token = crypto::generate_opaque_token(64);
+    repository::create_password_reset_token(NewPasswordResetToken {
+        user_id: user.id,
+        token_hash: &crypto::hash_token(&token),
+        expires_at: (chrono::Utc::now() + password_reset_ttl()).naive_utc(),
+    })?;
+
+    let mut body = format!(
+        "Hi {},\n\nUse the token below to reset your password, it expires in {} minutes.\n\n{}\n",
+        user.name,
+        password_reset_ttl().num_minutes(),
+        token
+    );
+
+    if let Ok(reset_url) = std::env::var("PASSWORD_RESET_URL") {
+        body.push_str(&format!("\nOr open {}?token={}\n", reset_url, token));
+    }
+
+    body.push_str("\nIf you didn't ask for it, just ignore this message.");
+
+    MAILER.send(&Mail {
+        to: user.email,
+        subject: "Reset your miniprova password".to_string(),
+        body,
+    })?;
+
+    Ok(())
+}
+
+pub fn reset_password(input: ResetPasswordInputDto) -> Result<(), ServiceError> {
+    let token = repository::get_password_reset_token_by_hash(&crypto::hash_token(&input.token))?;
+
+    let token = match token {
+        Some(token) => token,
+        None => {
+            return Err(ServiceError::BadRequest(
+                "Invalid or expired reset token".into(),
+            ))
+        }
+    };
+
+    if token.used_at.is_some() || token.expires_at < chrono::Utc::now().naive_utc() {
+        return Err(ServiceError::BadRequest(
+            "Invalid or expired reset token".into(),
         ));
     }
 
-    let token = crypto::generate_token(user.id)?;
-    Ok(LoginUserOutputDto { token })
+    let hashed_password = crypto::encrypt_password(&input.password)?;
+    repository::reset_password(token.id, token.user_id, &hashed_password)?;
+    ACTIVE_SESSIONS.retain(|_, session_user_id| *session_user_id != token.user_id);
+
+    Ok(())
+}
+
+pub fn unlock_user(actor_id: i32, user_id: i32) -> Result<(), ServiceError> {
+    let user = user::repository::get_user_by_id(user_id)?;
+
+    let user = match user {
+        Some(user) => user,
+        None => return Err(ServiceError::BadRequest("User not found".into())),
+    };
+
+    repository::unlock_user(actor_id, user.id)?;
+    LOGIN_ATTEMPTS.clear(&attempts::email_key(&user.email))?;
+    LOGIN_ATTEMPTS.clear(&attempts::mfa_key(user.id))?;
+
+    Ok(())
+}
+
+fn oidc_state_ttl() -> chrono::Duration {
+    chrono::Duration::minutes(10)
+}
+
+/// Starts a login with an identity provider and returns where to send the user to, along
+/// with the cookie the callback has to come back with. With `link_user_id` the identity is
+/// added to that account instead.
+pub async fn oidc_authorize(
+    provider_name: &str,
+    link_user_id: Option<i32>,
+) -> Result<(String, Cookie<'static>), ServiceError> {
+    let provider = oidc::get_provider(provider_name)?;
+    let metadata = oidc::metadata(provider).await?;
+
+    let state = crypto::generate_opaque_token(32);
+    let nonce = crypto::generate_opaque_token(32);
+    let code_verifier = crypto::generate_opaque_token(64);
+
+    repository::delete_expired_oidc_login_states()?;
+    repository::create_oidc_login_state(NewOidcLoginState {
+        state_hash: &crypto::hash_token(&state),
+        provider: &provider.name,
+        code_verifier: &code_verifier,
+        nonce: &nonce,
+        expires_at: (chrono::Utc::now() + oidc_state_ttl()).naive_utc(),
+        link_user_id,
+    })?;
+
+    let url = oidc::authorization_url(provider, &metadata, &state, &nonce, &code_verifier)?;
+    Ok((url, oidc::state_cookie(provider, &state, oidc_state_ttl())))
+}
+
+/// Finishes a login with an identity provider, creating the student on their first visit
+/// or linking the identity when the login was started from an account.
+pub async fn oidc_login(
+    provider_name: &str,
+    input: OidcCallbackInputDto,
+) -> Result<LoginOutputDto, ServiceError> {
+    let provider = oidc::get_provider(provider_name)?;
+
+    let state = match repository::take_oidc_login_state(&crypto::hash_token(&input.state))? {
+        Some(state) => state,
+        None => return Err(ServiceError::BadRequest("Invalid state".to_string())),
+    };
+
+    if state.provider != provider.name || state.expires_at < chrono::Utc::now().naive_utc() {
+        return Err(ServiceError::BadRequest("Invalid state".to_string()));
+    }
+
+    let metadata = oidc::metadata(provider).await?;
+    let claims = oidc::exchange_code(
+        provider,
+        &metadata,
+        input.code.as_deref().unwrap_or_default(),
+        &state.code_verifier,
+        &state.nonce,
+    )
+    .await?;
+
+    let user_id = match repository::get_user_id_by_identity(&provider.name, &claims.sub)? {
+        Some(user_id) if state.link_user_id.is_some_and(|link_id| link_id != user_id) => {
+            return Err(ServiceError::BadRequest(
+                "Identity is linked to another account".to_string(),
+            ))
+        }
+        Some(user_id) => user_id,
+        None => {
+            let user_id = match state.link_user_id {
+                Some(link_id) => link_id,
+                None => find_or_create_oidc_user(&claims)?,
+            };
+
+            repository::create_user_identity(NewUserIdentity {
+                user_id,
+                provider: &provider.name,
+                subject: &claims.sub,
+            })?;
+
+            user_id
+        }
+    };
+
+    finish_login(user_id)
+}
+
+fn find_or_create_oidc_user(claims: &oidc::IdTokenClaims) -> Result<i32, ServiceError> {
+    let email = match &claims.email {
+        Some(email) if !email.is_empty() => email,
+        _ => {
+            return Err(ServiceError::BadRequest(
+                "Identity provider did not share an email".to_string(),
+            ))
+        }
+    };
+
+    // Only link to an existing account when the provider vouches for the address,
+    // otherwise anyone could take over an account by claiming its email. Staff and admins
+    // have to link from their account, a compromised provider shouldn't be enough.
+    if let Some(existing) = user::service::get_user_by_email(email)? {
+        if !claims.email_verified || !user::service::is_plain_student(existing.id)? {
+            return Err(ServiceError::BadRequest(
+                "Email already exists, sign in to link the provider to it".to_string(),
+            ));
+        }
+
+        return Ok(existing.id);
+    }
+
+    let name = claims.name.as_deref().unwrap_or(email);
+    let user = user::service::create_external_user(name, email, claims.email_verified)?;
+
+    Ok(user.id)
 }
@@ -1,18 +1,29 @@
-use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use actix_multipart::Multipart;
+use actix_web::{
+    http::header::{self, ContentType},
+    web, HttpMessage, HttpRequest, HttpResponse, Responder,
+};
+use futures_util::StreamExt;
 
 use crate::{
     auth::models::LoggedUser,
-    avatar::{self, dto::CreateAvatarInputDto},
+    avatar::{
+        self,
+        dto::{AvatarImageQueryDto, CreateAvatarInputDto},
+    },
     errors::ServiceError,
 };
 
+fn logged_user_id(req: &HttpRequest) -> Option<i32> {
+    req.extensions().get::<LoggedUser>().map(|user| user.id)
+}
+
 pub async fn update_user_avatar(
     req: HttpRequest,
     new_avatar: web::Json<CreateAvatarInputDto>,
 ) -> impl Responder {
-    let ext = req.extensions();
-    let user = match ext.get::<LoggedUser>() {
-        Some(user) => user,
+    let user_id = match logged_user_id(&req) {
+        Some(user_id) => user_id,
         None => return HttpResponse::from_error(ServiceError::Unauthorized),
     };
 
@@ -20,23 +31,112 @@ pub async fn update_user_avatar(
         return HttpResponse::from_error(ServiceError::BadRequest(e));
     }
 
-    let avatar = match avatar::service::create_avatar(user.id, new_avatar.into_inner()) {
+    let avatar = match avatar::service::create_avatar(user_id, new_avatar.into_inner()).await {
         Ok(avatar) => avatar,
         Err(e) => return HttpResponse::from_error(e),
     };
 
-    HttpResponse::Ok().json(avatar).into()
+    HttpResponse::Ok().json(avatar)
+}
+
+/// Bytes and content type of the `file` field of a multipart upload.
+async fn read_upload_file(mut payload: Multipart) -> Result<(Vec<u8>, String), ServiceError> {
+    let malformed = |_| ServiceError::BadRequest("Malformed multipart body".into());
+    let max_bytes = avatar::service::max_upload_bytes();
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(malformed)?;
+        let is_file = field.name() == Some("file");
+        let content_type = field.content_type().map(|mime| mime.to_string());
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(malformed)?;
+            if is_file {
+                bytes.extend_from_slice(&chunk);
+                if bytes.len() > max_bytes {
+                    return Err(ServiceError::BadRequest("Image is too large".into()));
+                }
+            }
+        }
+
+        if is_file {
+            let content_type = content_type.ok_or(ServiceError::BadRequest(
+                "File content type is required".into(),
+            ))?;
+            return Ok((bytes, content_type));
+        }
+    }
+
+    Err(ServiceError::BadRequest("File is required".into()))
+}
+
+pub async fn upload_user_avatar(req: HttpRequest, payload: Multipart) -> impl Responder {
+    let user_id = match logged_user_id(&req) {
+        Some(user_id) => user_id,
+        None => return HttpResponse::from_error(ServiceError::Unauthorized),
+    };
+
+    let (bytes, content_type) = match read_upload_file(payload).await {
+        Ok(file) => file,
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    let images =
+        match web::block(move || avatar::service::prepare_upload(&bytes, &content_type)).await {
+            Ok(Ok(images)) => images,
+            Ok(Err(e)) => return HttpResponse::from_error(e),
+            Err(_) => return HttpResponse::from_error(ServiceError::InternalServerError),
+        };
+
+    match avatar::service::save_upload(user_id, images).await {
+        Ok(avatar) => HttpResponse::Ok().json(avatar),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn get_avatar_image(
+    req: HttpRequest,
+    path: web::Path<i32>,
+    query: web::Query<AvatarImageQueryDto>,
+) -> impl Responder {
+    let (bytes, etag) = match avatar::service::get_image(path.into_inner(), query.size).await {
+        Ok(Some(image)) => image,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(e) => return HttpResponse::from_error(e),
+    };
+
+    let etag = format!("\"{}\"", etag);
+    let cache_control = (header::CACHE_CONTROL, "public, max-age=86400");
+
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+    if not_modified {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .insert_header(cache_control)
+            .finish();
+    }
+
+    HttpResponse::Ok()
+        .content_type(ContentType::png())
+        .insert_header((header::ETAG, etag))
+        .insert_header(cache_control)
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(bytes)
 }
 
 pub async fn delete_user_avatar(req: HttpRequest) -> impl Responder {
-    let ext = req.extensions();
-    let user = match ext.get::<LoggedUser>() {
-        Some(user) => user,
+    let user_id = match logged_user_id(&req) {
+        Some(user_id) => user_id,
         None => return HttpResponse::from_error(ServiceError::Unauthorized),
     };
 
-    match avatar::service::delete_avatar(user.id) {
+    match avatar::service::delete_avatar(user_id).await {
         Err(e) => HttpResponse::from_error(e),
-        Ok(_) => HttpResponse::NoContent().into(),
+        Ok(_) => HttpResponse::NoContent().finish(),
     }
 }
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::remote;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateAvatarInputDto {
     pub url: String,
@@ -7,10 +9,42 @@ pub struct CreateAvatarInputDto {
 
 impl CreateAvatarInputDto {
     pub fn validate(&self) -> Result<(), String> {
-        if self.url.is_empty() {
-            return Err("Url is required".into());
-        }
+        remote::check_url(&self.url)?;
 
         Ok(())
     }
 }
+
+/// Thumbnails kept for uploaded avatars.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AvatarSize {
+    Small,
+    #[default]
+    Large,
+}
+
+impl AvatarSize {
+    pub const ALL: [AvatarSize; 2] = [AvatarSize::Small, AvatarSize::Large];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AvatarSize::Small => "small",
+            AvatarSize::Large => "large",
+        }
+    }
+
+    /// Side of the square thumbnail.
+    pub fn pixels(&self) -> u32 {
+        match self {
+            AvatarSize::Small => 64,
+            AvatarSize::Large => 256,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AvatarImageQueryDto {
+    #[serde(default)]
+    pub size: AvatarSize,
+}
@@ -0,0 +1,140 @@
+use std::io::Cursor;
+
+use image::{
+    imageops::FilterType, metadata::Orientation, DynamicImage, ImageDecoder, ImageFormat,
+    ImageReader, Limits,
+};
+
+/// Formats accepted for avatars.
+const FORMATS: [ImageFormat; 2] = [ImageFormat::Png, ImageFormat::Jpeg];
+
+/// Guesses the format from the leading magic bytes.
+pub fn sniff(bytes: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(bytes)
+        .ok()
+        .filter(|format| FORMATS.contains(format))
+}
+
+/// Decodes a png or jpeg image whose bytes must agree with `content_type`. Jpeg images come
+/// out already turned the way their exif orientation says.
+pub fn decode(bytes: &[u8], content_type: &str, max_pixels: u64) -> Result<DynamicImage, String> {
+    let format = sniff(bytes).ok_or("Only png and jpeg images are supported")?;
+
+    let declared = content_type.split(';').next().unwrap_or_default().trim();
+    let declared = if declared.eq_ignore_ascii_case("image/jpg") {
+        "image/jpeg"
+    } else {
+        declared
+    };
+    if !declared.eq_ignore_ascii_case(format.to_mime_type()) {
+        return Err("Image content doesn't match its content type".into());
+    }
+
+    let invalid = |e: image::ImageError| {
+        log::info!("decoding avatar failed: {}", e);
+        "Invalid image".to_string()
+    };
+
+    // Room for the decoded rgba pixels plus the decoder's own buffers.
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(max_pixels * 8);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let mut decoder = reader.into_decoder().map_err(invalid)?;
+
+    let (width, height) = decoder.dimensions();
+    if width as u64 * height as u64 > max_pixels {
+        return Err("Image has too many pixels".into());
+    }
+
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder).map_err(invalid)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+/// Center square of the image scaled to `size` pixels a side.
+pub fn square_thumbnail(image: &DynamicImage, size: u32) -> DynamicImage {
+    image.resize_to_fill(size, size, FilterType::Triangle)
+}
+
+/// Png bytes of the image, with nothing but its pixels.
+pub fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(|e| {
+            log::warn!("encoding avatar failed: {}", e);
+            "Couldn't encode the image".to_string()
+        })?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        encode_png(&DynamicImage::ImageRgba8(RgbaImage::new(width, height))).unwrap()
+    }
+
+    #[test]
+    fn sniffs_magic_bytes() {
+        assert_eq!(sniff(&png(1, 1)), Some(ImageFormat::Png));
+        assert_eq!(
+            sniff(&[0xFF, 0xD8, 0xFF, 0xE0, 0, 0]),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(sniff(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn rejects_content_not_matching_its_type() {
+        let png = png(2, 2);
+
+        assert!(decode(&png, "image/png", 100).is_ok());
+        assert!(decode(&png, "image/jpeg", 100).is_err());
+        assert!(decode(b"<svg/>", "image/png", 100).is_err());
+    }
+
+    #[test]
+    fn rejects_images_with_too_many_pixels() {
+        assert!(decode(&png(20, 20), "image/png", 100).is_err());
+    }
+
+    #[test]
+    fn corrupt_jpegs_are_errors() {
+        // Start of image, then a huffman table whose symbols claim 200 bit coefficients.
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xC4, 0x00, 0x14, 0x00];
+        jpeg.extend([0x01; 16]);
+        jpeg.push(0xC8);
+        jpeg.extend([0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+        jpeg.extend([0xFF; 64]);
+
+        assert!(decode(&jpeg, "image/jpeg", 100).is_err());
+    }
+
+    #[test]
+    fn thumbnails_crop_the_center_square() {
+        // 4x2, the center columns are white and the outer ones black.
+        let image = RgbaImage::from_fn(4, 2, |x, _| {
+            if x == 1 || x == 2 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+
+        let thumbnail = square_thumbnail(&DynamicImage::ImageRgba8(image), 2).to_rgba8();
+
+        assert_eq!(thumbnail.dimensions(), (2, 2));
+        assert!(thumbnail
+            .pixels()
+            .all(|pixel| *pixel == Rgba([255, 255, 255, 255])));
+    }
+}
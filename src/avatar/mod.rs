@@ -1,5 +1,7 @@
 pub mod controller;
 pub mod dto;
+mod image;
 mod model;
+mod remote;
 mod repository;
 pub mod service;
@@ -9,11 +9,17 @@ pub struct Avatar {
     pub url: String,
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
+    pub image_etag: Option<String>,
+    /// Set on urls stored before they were checked that turned out unsafe. They are
+    /// never handed to clients.
+    pub url_blocked: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Insertable, AsChangeset)]
-#[diesel(table_name = avatars)]
+#[diesel(table_name = avatars, treat_none_as_null = true)]
 pub struct NewAvatar {
     pub user_id: i32,
     pub url: String,
+    pub image_etag: Option<String>,
+    pub url_blocked: bool,
 }
@@ -1,5 +1,6 @@
 use diesel::{
-    associations::HasTable, query_dsl::methods::FilterDsl, ExpressionMethods, RunQueryDsl, Table,
+    associations::HasTable, query_dsl::methods::FilterDsl, ExpressionMethods, OptionalExtension,
+    RunQueryDsl, Table,
 };
 
 use crate::{
@@ -9,24 +10,24 @@ use crate::{
     schema::avatars::dsl::*,
 };
 
-use super::dto::CreateAvatarInputDto;
-
-pub fn create_avatar(
-    avatar_user_id: i32,
-    new_avatar: CreateAvatarInputDto,
-) -> Result<Avatar, ServiceError> {
+pub fn get_avatar(avatar_user_id: i32) -> Result<Option<Avatar>, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
-    let new_avatar = NewAvatar {
-        user_id: avatar_user_id,
-        url: new_avatar.url,
-    };
+    avatars
+        .filter(user_id.eq(avatar_user_id))
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn upsert_avatar(new_avatar: NewAvatar) -> Result<Avatar, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
     let result_avatar: Avatar = diesel::insert_into(avatars::table())
         .values(&new_avatar)
         .on_conflict(user_id)
         .do_update()
-        .set(&new_avatar)
+        .set((&new_avatar, updated_at.eq(diesel::dsl::now)))
         .returning(avatars::all_columns())
         .get_result(&mut conn)
         .map_err(|_| ServiceError::InternalServerError)?;
@@ -1,16 +1,153 @@
-use crate::errors::ServiceError;
+use actix_web::web;
+use sha2::{Digest, Sha256};
 
-use super::{dto::CreateAvatarInputDto, model::Avatar, repository};
+use crate::{blob_store::BLOB_STORE, errors::ServiceError};
 
-pub fn create_avatar(
+use super::{
+    dto::{AvatarSize, CreateAvatarInputDto},
+    image,
+    model::{Avatar, NewAvatar},
+    remote, repository,
+};
+
+/// Images with more pixels are refused before being decoded.
+const MAX_PIXELS: u64 = 4096 * 4096;
+
+/// Largest image accepted by the upload, the request body may be a bit larger.
+pub fn max_upload_bytes() -> usize {
+    std::env::var("AVATAR_MAX_BYTES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(2 * 1024 * 1024)
+}
+
+fn image_key(avatar_user_id: i32, size: AvatarSize) -> String {
+    format!("avatars/{}/{}.png", avatar_user_id, size.as_str())
+}
+
+/// Thumbnails of an uploaded image, re-encoded so nothing but the pixels (no exif, no
+/// trailing data) is ever stored.
+pub struct AvatarImages {
+    thumbnails: Vec<(AvatarSize, Vec<u8>)>,
+}
+
+/// Validates an uploaded image and renders its thumbnails. Cpu bound, run it off the async
+/// workers.
+pub fn prepare_upload(bytes: &[u8], content_type: &str) -> Result<AvatarImages, ServiceError> {
+    if bytes.len() > max_upload_bytes() {
+        return Err(ServiceError::BadRequest("Image is too large".into()));
+    }
+
+    render(bytes, content_type)
+}
+
+fn render(bytes: &[u8], content_type: &str) -> Result<AvatarImages, ServiceError> {
+    let decoded =
+        image::decode(bytes, content_type, MAX_PIXELS).map_err(ServiceError::BadRequest)?;
+
+    let thumbnails = AvatarSize::ALL
+        .iter()
+        .map(|size| {
+            let thumbnail = image::square_thumbnail(&decoded, size.pixels());
+            Ok((*size, image::encode_png(&thumbnail)?))
+        })
+        .collect::<Result<_, String>>()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(AvatarImages { thumbnails })
+}
+
+/// Stores the thumbnails and points the user's avatar at them.
+pub async fn save_upload(
+    avatar_user_id: i32,
+    images: AvatarImages,
+) -> Result<Avatar, ServiceError> {
+    let mut hasher = Sha256::new();
+    for (_, bytes) in &images.thumbnails {
+        hasher.update(bytes);
+    }
+    let etag = hex::encode(&hasher.finalize()[..8]);
+
+    for (size, bytes) in images.thumbnails {
+        BLOB_STORE
+            .put(&image_key(avatar_user_id, size), "image/png", bytes)
+            .await?;
+    }
+
+    // The version in the url lets clients cache each upload for good.
+    repository::upsert_avatar(NewAvatar {
+        user_id: avatar_user_id,
+        url: format!("/avatars/{}?v={}", avatar_user_id, etag),
+        image_etag: Some(etag),
+        url_blocked: false,
+    })
+}
+
+/// Drops the stored thumbnails of a user, if any.
+pub async fn delete_images(avatar_user_id: i32) -> Result<(), ServiceError> {
+    for size in AvatarSize::ALL {
+        BLOB_STORE.delete(&image_key(avatar_user_id, size)).await?;
+    }
+
+    Ok(())
+}
+
+/// Thumbnail bytes along with their etag, `None` unless the avatar is an uploaded image.
+pub async fn get_image(
+    avatar_user_id: i32,
+    size: AvatarSize,
+) -> Result<Option<(Vec<u8>, String)>, ServiceError> {
+    let etag = match repository::get_avatar(avatar_user_id)?.and_then(|avatar| avatar.image_etag) {
+        Some(etag) => etag,
+        None => return Ok(None),
+    };
+
+    let bytes = BLOB_STORE.get(&image_key(avatar_user_id, size)).await?;
+
+    Ok(bytes.map(|bytes| (bytes, format!("{}-{}", etag, size.as_str()))))
+}
+
+/// In fetch mode avatar urls are downloaded once and served from our storage like uploads,
+/// otherwise clients load them from wherever they point.
+fn fetch_remote_avatars() -> bool {
+    std::env::var("AVATAR_URL_MODE").as_deref() == Ok("fetch")
+}
+
+pub async fn create_avatar(
     avatar_user_id: i32,
     new_avatar: CreateAvatarInputDto,
 ) -> Result<Avatar, ServiceError> {
-    let avatar = repository::create_avatar(avatar_user_id, new_avatar)?;
+    let url = remote::check_url(&new_avatar.url).map_err(ServiceError::BadRequest)?;
+
+    if fetch_remote_avatars() {
+        let (bytes, content_type) = remote::fetch(&url, max_upload_bytes())
+            .await
+            .map_err(ServiceError::BadRequest)?;
+        let images = web::block(move || render(&bytes, &content_type))
+            .await
+            .map_err(|_| ServiceError::InternalServerError)??;
+
+        return save_upload(avatar_user_id, images).await;
+    }
+
+    let previous = repository::get_avatar(avatar_user_id)?;
+
+    let avatar = repository::upsert_avatar(NewAvatar {
+        user_id: avatar_user_id,
+        url: url.to_string(),
+        image_etag: None,
+        url_blocked: false,
+    })?;
+
+    if previous.is_some_and(|previous| previous.image_etag.is_some()) {
+        delete_images(avatar_user_id).await?;
+    }
+
     Ok(avatar)
 }
 
-pub fn delete_avatar(avatar_user_id: i32) -> Result<(), ServiceError> {
+pub async fn delete_avatar(avatar_user_id: i32) -> Result<(), ServiceError> {
     repository::delete_avatar(avatar_user_id)?;
+    delete_images(avatar_user_id).await?;
     Ok(())
 }
@@ -3,7 +3,7 @@ use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 use crate::{auth::models::LoggedUser, errors::ServiceError};
 
 use super::{
-    dto::{CreateClassInputDto, UpdateClassInputDto},
+    dto::{AddClassStaffInputDto, CreateClassInputDto, TransferClassInputDto, UpdateClassInputDto},
     service,
 };
 
@@ -38,21 +38,21 @@ pub async fn get_class_by_id(path: web::Path<i32>) -> impl Responder {
     }
 }
 
-pub async fn delete_class(path: web::Path<i32>, req: HttpRequest) -> impl Responder {
+pub async fn delete_class(req: HttpRequest, path: web::Path<i32>) -> impl Responder {
     let class_id = path.into_inner();
     let ext = req.extensions();
     let user = ext.get::<LoggedUser>().unwrap();
 
-    match service::delete_class_by_id(user.id, class_id) {
+    match service::delete_class_by_id(user, class_id) {
         Err(e) => HttpResponse::from_error(e),
         Ok(_) => HttpResponse::NoContent().finish(),
     }
 }
 
 pub async fn update_class(
+    req: HttpRequest,
     path: web::Path<i32>,
     class: web::Json<UpdateClassInputDto>,
-    req: HttpRequest,
 ) -> impl Responder {
     match class.validate() {
         Err(e) => return HttpResponse::from_error(ServiceError::BadRequest(e)),
@@ -63,7 +63,7 @@ pub async fn update_class(
     let ext = req.extensions();
     let user = ext.get::<LoggedUser>().unwrap();
 
-    let class = match service::update_class(user.id, class_id, class.into_inner()) {
+    let class = match service::update_class(user, class_id, class.into_inner()) {
         Err(e) => return HttpResponse::from_error(e),
         Ok(class) => class,
     };
@@ -117,3 +117,57 @@ pub async fn list_classes_by_teacher(req: HttpRequest) -> impl Responder {
 
     HttpResponse::Ok().json(classes).into()
 }
+
+pub async fn list_class_staff(path: web::Path<i32>) -> impl Responder {
+    match service::list_class_staff(path.into_inner()) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(staff) => HttpResponse::Ok().json(staff),
+    }
+}
+
+pub async fn add_class_staff(
+    req: HttpRequest,
+    path: web::Path<i32>,
+    staff: web::Json<AddClassStaffInputDto>,
+) -> impl Responder {
+    if let Err(e) = staff.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::add_class_staff(user.id, path.into_inner(), staff.into_inner()) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(_) => HttpResponse::NoContent().finish(),
+    }
+}
+
+pub async fn remove_class_staff(req: HttpRequest, path: web::Path<(i32, i32)>) -> impl Responder {
+    let (class_id, user_id) = path.into_inner();
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::remove_class_staff(user.id, class_id, user_id) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(_) => HttpResponse::NoContent().finish(),
+    }
+}
+
+pub async fn transfer_class(
+    req: HttpRequest,
+    path: web::Path<i32>,
+    transfer: web::Json<TransferClassInputDto>,
+) -> impl Responder {
+    if let Err(e) = transfer.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::transfer_class(user.id, path.into_inner(), transfer.into_inner()) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(_) => HttpResponse::NoContent().finish(),
+    }
+}
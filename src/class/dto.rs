@@ -1,3 +1,4 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,3 +49,74 @@ impl UpdateClassInputDto {
         Ok(())
     }
 }
+
+/// Role of a user in the staff of a class. Every class has exactly one owner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClassStaffRole {
+    Owner,
+    CoTeacher,
+    Monitor,
+}
+
+impl ClassStaffRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClassStaffRole::Owner => "owner",
+            ClassStaffRole::CoTeacher => "co_teacher",
+            ClassStaffRole::Monitor => "monitor",
+        }
+    }
+
+    /// Permission a user needs to be given the role.
+    pub fn required_permission(&self) -> &'static str {
+        match self {
+            ClassStaffRole::Owner | ClassStaffRole::CoTeacher => "classes:teach",
+            ClassStaffRole::Monitor => "exams:monitor",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClassStaffDto {
+    pub user_id: i32,
+    pub name: String,
+    pub email: String,
+    pub role: String,
+    pub added_at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddClassStaffInputDto {
+    pub user_id: i32,
+    pub role: ClassStaffRole,
+}
+
+impl AddClassStaffInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.user_id <= 0 {
+            return Err("User id is required".to_string());
+        }
+
+        if self.role == ClassStaffRole::Owner {
+            return Err("Transfer the class to change its owner".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferClassInputDto {
+    pub user_id: i32,
+}
+
+impl TransferClassInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.user_id <= 0 {
+            return Err("User id is required".to_string());
+        }
+
+        Ok(())
+    }
+}
@@ -28,3 +28,11 @@ pub struct UpdateClass {
     pub name: Option<String>,
     pub description: Option<String>,
 }
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = class_staff)]
+pub struct NewClassStaff<'a> {
+    pub class_id: i32,
+    pub user_id: i32,
+    pub role: &'a str,
+}
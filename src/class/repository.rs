@@ -1,21 +1,46 @@
-use diesel::{ExpressionMethods, RunQueryDsl, SelectableHelper, Table};
+use std::error::Error;
 
-use crate::{db::DB_MANAGER, errors::ServiceError, schema::classes_students};
+use diesel::{Connection, ExpressionMethods, RunQueryDsl, SelectableHelper, Table};
 
-use super::model::{Class, NewClass, UpdateClass};
+use crate::{
+    audit::{
+        self,
+        model::{snapshot, NewAuditEvent},
+    },
+    db::DB_MANAGER,
+    errors::ServiceError,
+    schema::{class_staff, classes_students, users},
+};
+
+use super::{
+    dto::{ClassStaffDto, ClassStaffRole},
+    model::{Class, NewClass, NewClassStaff, UpdateClass},
+};
 use crate::diesel::OptionalExtension;
 use crate::diesel::QueryDsl;
 use crate::schema::classes::dsl::*;
 
 pub fn create_class(new_class: NewClass) -> Result<Class, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
-    let class: Class = diesel::insert_into(classes)
-        .values(&new_class)
-        .returning(classes::all_columns())
-        .get_result(&mut conn)
-        .map_err(|_| ServiceError::InternalServerError)?;
 
-    Ok(class)
+    let result: Result<Class, Box<dyn Error>> = conn.transaction(|tx| {
+        let class: Class = diesel::insert_into(classes)
+            .values(&new_class)
+            .returning(classes::all_columns())
+            .get_result(tx)?;
+
+        diesel::insert_into(class_staff::table)
+            .values(NewClassStaff {
+                class_id: class.id,
+                user_id: class.user_id,
+                role: ClassStaffRole::Owner.as_str(),
+            })
+            .execute(tx)?;
+
+        Ok(class)
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
 }
 
 pub fn get_class_by_id(class_id: i32) -> Result<Option<Class>, ServiceError> {
@@ -30,24 +55,66 @@ pub fn get_class_by_id(class_id: i32) -> Result<Option<Class>, ServiceError> {
     Ok(class)
 }
 
-pub fn delete_class_by_id(class_id: i32) -> Result<(), ServiceError> {
+pub fn delete_class_by_id(actor_id: i32, class_id: i32) -> Result<(), ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
-    diesel::delete(classes.filter(id.eq(class_id)))
-        .execute(&mut conn)
-        .map_err(|_| ServiceError::InternalServerError)?;
 
-    Ok(())
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let class: Class = diesel::delete(classes.filter(id.eq(class_id)))
+            .returning(classes::all_columns())
+            .get_result(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "delete",
+                "class",
+                class_id,
+                snapshot(&class),
+                None,
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
 }
 
-pub fn update_class(class_id: i32, update_class: UpdateClass) -> Result<Class, ServiceError> {
+pub fn update_class(
+    actor_id: i32,
+    class_id: i32,
+    update_class: UpdateClass,
+) -> Result<Class, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
-    let class = diesel::update(classes.filter(id.eq(class_id)))
-        .set(&update_class)
-        .returning(classes::all_columns())
-        .get_result(&mut conn)
-        .map_err(|_| ServiceError::InternalServerError)?;
 
-    Ok(class)
+    let result: Result<Class, Box<dyn Error>> = conn.transaction(|tx| {
+        let before: Class = classes
+            .find(class_id)
+            .select(Class::as_select())
+            .first(tx)?;
+
+        let class: Class = diesel::update(classes.filter(id.eq(class_id)))
+            .set(&update_class)
+            .returning(classes::all_columns())
+            .get_result(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "update",
+                "class",
+                class_id,
+                snapshot(&before),
+                snapshot(&class),
+            ),
+        )?;
+
+        Ok(class)
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
 }
 
 pub fn get_class_by_code(class_code: &str) -> Result<Option<Class>, ServiceError> {
@@ -113,13 +180,239 @@ pub fn list_classes_that_student_is_not_enrolled(sid: i32) -> Result<Vec<Class>,
     Ok(ccs)
 }
 
+/// Classes the user owns or co-teaches.
 pub fn list_classes_by_teacher(tid: i32) -> Result<Vec<Class>, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
     let ccs: Vec<Class> = classes
-        .filter(user_id.eq(tid))
+        .inner_join(class_staff::table)
+        .filter(class_staff::user_id.eq(tid))
+        .filter(class_staff::role.eq_any([
+            ClassStaffRole::Owner.as_str(),
+            ClassStaffRole::CoTeacher.as_str(),
+        ]))
         .select(Class::as_select())
         .load::<Class>(&mut conn)
         .map_err(|_| ServiceError::InternalServerError)?;
 
     Ok(ccs)
 }
+
+pub fn list_classes_by_owner(oid: i32) -> Result<Vec<Class>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let ccs: Vec<Class> = classes
+        .filter(user_id.eq(oid))
+        .select(Class::as_select())
+        .load::<Class>(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(ccs)
+}
+
+pub fn get_staff_role(cid: i32, uid: i32) -> Result<Option<String>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let staff_role = class_staff::table
+        .find((cid, uid))
+        .select(class_staff::role)
+        .first::<String>(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(staff_role)
+}
+
+pub fn list_class_staff(cid: i32) -> Result<Vec<ClassStaffDto>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let staff = class_staff::table
+        .inner_join(users::table)
+        .filter(class_staff::class_id.eq(cid))
+        .order(class_staff::created_at)
+        .select((
+            users::id,
+            users::name,
+            users::email,
+            class_staff::role,
+            class_staff::created_at,
+        ))
+        .load::<(i32, String, String, String, chrono::NaiveDateTime)>(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(staff
+        .into_iter()
+        .map(
+            |(uid, user_name, email, staff_role, added_at)| ClassStaffDto {
+                user_id: uid,
+                name: user_name,
+                email,
+                role: staff_role,
+                added_at,
+            },
+        )
+        .collect())
+}
+
+/// Staff roles by user id, as recorded in the audit log.
+fn staff_snapshot(uid: i32, staff_role: Option<&str>) -> Option<serde_json::Value> {
+    Some(serde_json::json!({ "staff": { uid.to_string(): staff_role } }))
+}
+
+/// Adds the user to the staff, or changes their role if they already are.
+pub fn upsert_class_staff(actor_id: i32, new_staff: NewClassStaff) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let previous_role: Option<String> = class_staff::table
+            .find((new_staff.class_id, new_staff.user_id))
+            .select(class_staff::role)
+            .first(tx)
+            .optional()?;
+
+        diesel::insert_into(class_staff::table)
+            .values(&new_staff)
+            .on_conflict((class_staff::class_id, class_staff::user_id))
+            .do_update()
+            .set(class_staff::role.eq(new_staff.role))
+            .execute(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "add_staff",
+                "class",
+                new_staff.class_id,
+                staff_snapshot(new_staff.user_id, previous_role.as_deref()),
+                staff_snapshot(new_staff.user_id, Some(new_staff.role)),
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn remove_class_staff(actor_id: i32, cid: i32, uid: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let previous_role: String = diesel::delete(class_staff::table.find((cid, uid)))
+            .returning(class_staff::role)
+            .get_result(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "remove_staff",
+                "class",
+                cid,
+                staff_snapshot(uid, Some(&previous_role)),
+                staff_snapshot(uid, None),
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+/// Makes `new_owner_id` the owner of the class and the previous owner a co-teacher.
+pub fn transfer_class(
+    actor_id: i32,
+    cid: i32,
+    old_owner_id: i32,
+    new_owner_id: i32,
+) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        // Demoted first, only one owner per class is allowed.
+        diesel::update(class_staff::table.find((cid, old_owner_id)))
+            .set(class_staff::role.eq(ClassStaffRole::CoTeacher.as_str()))
+            .execute(tx)?;
+
+        diesel::insert_into(class_staff::table)
+            .values(NewClassStaff {
+                class_id: cid,
+                user_id: new_owner_id,
+                role: ClassStaffRole::Owner.as_str(),
+            })
+            .on_conflict((class_staff::class_id, class_staff::user_id))
+            .do_update()
+            .set(class_staff::role.eq(ClassStaffRole::Owner.as_str()))
+            .execute(tx)?;
+
+        diesel::update(classes.filter(id.eq(cid)))
+            .set(user_id.eq(new_owner_id))
+            .execute(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "transfer",
+                "class",
+                cid,
+                Some(serde_json::json!({ "owner_id": old_owner_id })),
+                Some(serde_json::json!({ "owner_id": new_owner_id })),
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+/// Whether both users are on the staff of some class, in whatever role.
+pub fn share_class_staff(uid: i32, other_id: i32) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let other_staff = diesel::alias!(class_staff as other_staff);
+    let other_classes = other_staff
+        .filter(other_staff.field(class_staff::user_id).eq(other_id))
+        .select(other_staff.field(class_staff::class_id));
+
+    let count: i64 = class_staff::table
+        .filter(class_staff::user_id.eq(uid))
+        .filter(class_staff::class_id.eq_any(other_classes))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(count > 0)
+}
+
+/// Whether the student is enrolled in a class the teacher owns or co-teaches.
+pub fn teaches_student(tid: i32, sid: i32) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let taught_classes = class_staff::table
+        .filter(class_staff::user_id.eq(tid))
+        .filter(class_staff::role.eq_any([
+            ClassStaffRole::Owner.as_str(),
+            ClassStaffRole::CoTeacher.as_str(),
+        ]))
+        .select(class_staff::class_id);
+
+    let count: i64 = classes_students::table
+        .filter(classes_students::student_id.eq(sid))
+        .filter(classes_students::class_id.eq_any(taught_classes))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(count > 0)
+}
+
+/// Whether the user is on the staff of any class, in whatever role.
+pub fn is_staff_anywhere(uid: i32) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let count: i64 = class_staff::table
+        .filter(class_staff::user_id.eq(uid))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(count > 0)
+}
@@ -1,8 +1,16 @@
-use crate::errors::ServiceError;
+use crate::{
+    auth::models::LoggedUser,
+    errors::ServiceError,
+    policy::{self, Action, Resource},
+    role, user,
+};
 
 use super::{
-    dto::{CreateClassInputDto, UpdateClassInputDto},
-    model::{Class, NewClass, UpdateClass},
+    dto::{
+        AddClassStaffInputDto, ClassStaffDto, ClassStaffRole, CreateClassInputDto,
+        TransferClassInputDto, UpdateClassInputDto,
+    },
+    model::{Class, NewClass, NewClassStaff, UpdateClass},
     repository,
 };
 
@@ -31,38 +39,39 @@ pub fn get_class_by_id(class_id: i32) -> Result<Option<Class>, ServiceError> {
     Ok(class)
 }
 
-pub fn delete_class_by_id(user_id: i32, class_id: i32) -> Result<(), ServiceError> {
+pub fn get_class_by_code(code: &str) -> Result<Option<Class>, ServiceError> {
+    repository::get_class_by_code(code)
+}
+
+pub fn delete_class_by_id(user: &LoggedUser, class_id: i32) -> Result<(), ServiceError> {
+    policy::authorize(user, Action::DeleteClass, Resource::Class(class_id))?;
+
     let class = repository::get_class_by_id(class_id)?;
 
     if class.is_none() {
         return Err(ServiceError::BadRequest("Class not found".to_string()));
     }
 
-    if class.unwrap().user_id != user_id {
-        return Err(ServiceError::Forbidden);
-    }
-
-    repository::delete_class_by_id(class_id)?;
+    repository::delete_class_by_id(user.id, class_id)?;
 
     Ok(())
 }
 
 pub fn update_class(
-    user_id: i32,
+    user: &LoggedUser,
     class_id: i32,
     class: UpdateClassInputDto,
 ) -> Result<Class, ServiceError> {
+    policy::authorize(user, Action::UpdateClass, Resource::Class(class_id))?;
+
     let existing = repository::get_class_by_id(class_id)?;
 
     if existing.is_none() {
         return Err(ServiceError::BadRequest("Class not found".to_string()));
     }
 
-    if existing.unwrap().user_id != user_id {
-        return Err(ServiceError::Forbidden);
-    }
-
     let updated_class = repository::update_class(
+        user.id,
         class_id,
         UpdateClass {
             description: class.description,
@@ -113,14 +122,144 @@ pub fn list_classes_by_teacher(teacher_id: i32) -> Result<Vec<Class>, ServiceErr
     Ok(classes)
 }
 
+pub fn list_classes_by_owner(owner_id: i32) -> Result<Vec<Class>, ServiceError> {
+    repository::list_classes_by_owner(owner_id)
+}
+
+fn get_staff_role(user_id: i32, class_id: i32) -> Result<Option<String>, ServiceError> {
+    let class = repository::get_class_by_id(class_id)?;
+
+    if class.is_none() {
+        return Err(ServiceError::BadRequest("Class not found".to_string()));
+    }
+
+    repository::get_staff_role(class_id, user_id)
+}
+
+/// Whether the user owns or co-teaches the class.
 pub fn is_class_teacher(user_id: i32, class_id: i32) -> Result<bool, ServiceError> {
+    let staff_role = get_staff_role(user_id, class_id)?;
+
+    Ok(staff_role.is_some_and(|staff_role| {
+        staff_role == ClassStaffRole::Owner.as_str()
+            || staff_role == ClassStaffRole::CoTeacher.as_str()
+    }))
+}
+
+pub fn is_class_owner(user_id: i32, class_id: i32) -> Result<bool, ServiceError> {
+    let staff_role = get_staff_role(user_id, class_id)?;
+
+    Ok(staff_role.is_some_and(|staff_role| staff_role == ClassStaffRole::Owner.as_str()))
+}
+
+/// Whether the user was assigned to monitor every exam of the class.
+pub fn is_class_staff_monitor(user_id: i32, class_id: i32) -> Result<bool, ServiceError> {
+    let staff_role = get_staff_role(user_id, class_id)?;
+
+    Ok(staff_role.is_some_and(|staff_role| staff_role == ClassStaffRole::Monitor.as_str()))
+}
+
+pub fn list_class_staff(class_id: i32) -> Result<Vec<ClassStaffDto>, ServiceError> {
     let class = repository::get_class_by_id(class_id)?;
 
     if class.is_none() {
         return Err(ServiceError::BadRequest("Class not found".to_string()));
     }
 
-    Ok(class.unwrap().user_id == user_id)
+    repository::list_class_staff(class_id)
+}
+
+/// Fails unless the user exists and has the permission the staff role needs.
+fn check_can_be_staff(user_id: i32, staff_role: ClassStaffRole) -> Result<(), ServiceError> {
+    let user = match user::service::get_user_with_roles_by_id(user_id)? {
+        Some(user) => user,
+        None => return Err(ServiceError::BadRequest("User not found".to_string())),
+    };
+
+    let permission = staff_role.required_permission();
+
+    if !role::service::permissions_of(&user.roles)?
+        .iter()
+        .any(|granted| granted == permission)
+    {
+        return Err(ServiceError::BadRequest(format!(
+            "User lacks the {} permission",
+            permission
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn add_class_staff(
+    actor_id: i32,
+    class_id: i32,
+    staff: AddClassStaffInputDto,
+) -> Result<(), ServiceError> {
+    let staff_role = get_staff_role(staff.user_id, class_id)?;
+
+    if staff_role.as_deref() == Some(ClassStaffRole::Owner.as_str()) {
+        return Err(ServiceError::BadRequest(
+            "Transfer the class to change its owner".to_string(),
+        ));
+    }
+
+    check_can_be_staff(staff.user_id, staff.role)?;
+
+    repository::upsert_class_staff(
+        actor_id,
+        NewClassStaff {
+            class_id,
+            user_id: staff.user_id,
+            role: staff.role.as_str(),
+        },
+    )
+}
+
+pub fn remove_class_staff(actor_id: i32, class_id: i32, user_id: i32) -> Result<(), ServiceError> {
+    match get_staff_role(user_id, class_id)? {
+        None => Err(ServiceError::BadRequest(
+            "User is not in the class staff".to_string(),
+        )),
+        Some(staff_role) if staff_role == ClassStaffRole::Owner.as_str() => Err(
+            ServiceError::BadRequest("The owner can't be removed".to_string()),
+        ),
+        Some(_) => repository::remove_class_staff(actor_id, class_id, user_id),
+    }
+}
+
+/// Hands the class over to another teacher. The previous owner stays as a co-teacher.
+pub fn transfer_class(
+    actor_id: i32,
+    class_id: i32,
+    transfer: TransferClassInputDto,
+) -> Result<(), ServiceError> {
+    let class = match repository::get_class_by_id(class_id)? {
+        Some(class) => class,
+        None => return Err(ServiceError::BadRequest("Class not found".to_string())),
+    };
+
+    if class.user_id == transfer.user_id {
+        return Err(ServiceError::BadRequest(
+            "User already owns the class".to_string(),
+        ));
+    }
+
+    check_can_be_staff(transfer.user_id, ClassStaffRole::Owner)?;
+
+    repository::transfer_class(actor_id, class_id, class.user_id, transfer.user_id)
+}
+
+pub fn teaches_student(teacher_id: i32, student_id: i32) -> Result<bool, ServiceError> {
+    repository::teaches_student(teacher_id, student_id)
+}
+
+pub fn share_class_staff(user_id: i32, other_id: i32) -> Result<bool, ServiceError> {
+    repository::share_class_staff(user_id, other_id)
+}
+
+pub fn is_staff_anywhere(user_id: i32) -> Result<bool, ServiceError> {
+    repository::is_staff_anywhere(user_id)
 }
 
 pub fn is_student_enrolled(class_id: i32, student_id: i32) -> Result<bool, ServiceError> {
@@ -1,4 +1,4 @@
-use actix_web::{error::ResponseError, HttpResponse};
+use actix_web::{error::ResponseError, http::header, HttpResponse};
 use derive_more::Display;
 use serde::Serialize;
 
@@ -19,7 +19,10 @@ pub enum ServiceError {
     Unauthorized,
 
     #[display(fmt = "Forbidden")]
-    Forbidden
+    Forbidden,
+
+    #[display(fmt = "Too Many Requests: retry after {}s", _0)]
+    TooManyRequests(i64),
 }
 
 impl ResponseError for ServiceError {
@@ -41,6 +44,11 @@ impl ResponseError for ServiceError {
             ServiceError::Forbidden => HttpResponse::Forbidden().json(&ErrorResponse {
                 message: "Forbidden".into(),
             }),
+            ServiceError::TooManyRequests(retry_after) => HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                .json(&ErrorResponse {
+                    message: "Too many attempts. Please try again later".into(),
+                }),
         }
     }
 }
@@ -3,7 +3,10 @@ use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 use crate::{auth::models::LoggedUser, errors::ServiceError};
 
 use super::{
-    dto::{CreateExamInputDto, StudentAnswerInputDto},
+    dto::{
+        AddExamMonitorInputDto, CreateExamInputDto, GradeAnswerInputDto, GradingQueueQueryDto,
+        StudentAnswerInputDto,
+    },
     models::UpdateExam,
     service,
 };
@@ -17,7 +20,7 @@ pub async fn create_exam(req: HttpRequest, input: web::Json<CreateExamInputDto>)
     let ext = req.extensions();
     let user = ext.get::<LoggedUser>().unwrap();
 
-    let exam = match service::create_exam(user.id, input.into_inner()) {
+    let exam = match service::create_exam(user, input.into_inner()) {
         Err(e) => return HttpResponse::from_error(e),
         Ok(exam) => exam,
     };
@@ -36,13 +39,12 @@ pub async fn get_exam_by_id(path: web::Path<i32>) -> impl Responder {
     }
 }
 
-pub async fn delete_exam(path: web::Path<i32>, req: HttpRequest) -> impl Responder {
+pub async fn delete_exam(req: HttpRequest, path: web::Path<i32>) -> impl Responder {
     let exam_id = path.into_inner();
-
     let ext = req.extensions();
     let user = ext.get::<LoggedUser>().unwrap();
 
-    match service::delete_exam(user.id, exam_id) {
+    match service::delete_exam(user, exam_id) {
         Err(e) => HttpResponse::from_error(e),
         Ok(_) => HttpResponse::NoContent().finish(),
     }
@@ -59,16 +61,15 @@ pub async fn list_exams_by_class_id(path: web::Path<i32>) -> impl Responder {
 }
 
 pub async fn update_exam(
-    path: web::Path<i32>,
     req: HttpRequest,
+    path: web::Path<i32>,
     input: web::Json<UpdateExam>,
 ) -> impl Responder {
     let exam_id = path.into_inner();
-
     let ext = req.extensions();
     let user = ext.get::<LoggedUser>().unwrap();
 
-    let exam = match service::update_exam(user.id, exam_id, input.into_inner()) {
+    let exam = match service::update_exam(user, exam_id, input.into_inner()) {
         Err(e) => return HttpResponse::from_error(e),
         Ok(exam) => exam,
     };
@@ -77,31 +78,24 @@ pub async fn update_exam(
 }
 
 pub async fn update_questions_in_exam(
-    path: web::Path<i32>,
     req: HttpRequest,
+    path: web::Path<i32>,
     input: web::Json<Vec<i32>>,
 ) -> impl Responder {
     let exam_id = path.into_inner();
-
     let ext = req.extensions();
     let user = ext.get::<LoggedUser>().unwrap();
 
-    match service::update_questions_in_exam(user.id, exam_id, input.into_inner()) {
+    match service::update_questions_in_exam(user, exam_id, input.into_inner()) {
         Err(e) => HttpResponse::from_error(e),
         Ok(_) => HttpResponse::NoContent().finish(),
     }
 }
 
-pub async fn get_questions_in_exam_as_student(
-    req: HttpRequest,
-    path: web::Path<i32>,
-) -> impl Responder {
+pub async fn get_questions_in_exam_as_student(path: web::Path<i32>) -> impl Responder {
     let exam_id = path.into_inner();
 
-    let ext = req.extensions();
-    let user = ext.get::<LoggedUser>().unwrap();
-
-    let questions = match service::get_questions_in_exam_as_student(user.id, exam_id) {
+    let questions = match service::get_questions_in_exam_as_student(exam_id) {
         Err(e) => return HttpResponse::from_error(e),
         Ok(questions) => questions,
     };
@@ -139,7 +133,7 @@ pub async fn submit_answer_to_question_in_exam(
         user.id,
         exam_id,
         question_id,
-        input.into_inner().answer_id,
+        input.into_inner().response,
     ) {
         Err(e) => HttpResponse::from_error(e),
         Ok(_) => HttpResponse::NoContent().finish(),
@@ -160,16 +154,94 @@ pub async fn get_exam_results_as_student(path: web::Path<i32>, req: HttpRequest)
     HttpResponse::Ok().json(results).into()
 }
 
-pub async fn get_exam_results_as_teacher(path: web::Path<i32>, req: HttpRequest) -> impl Responder {
+pub async fn get_exam_results_as_teacher(path: web::Path<i32>) -> impl Responder {
     let exam_id = path.into_inner();
 
-    let ext = req.extensions();
-    let user = ext.get::<LoggedUser>().unwrap();
-
-    let results = match service::get_exam_results_as_teacher(user.id, exam_id) {
+    let results = match service::get_exam_results_as_teacher(exam_id) {
         Err(e) => return HttpResponse::from_error(e),
         Ok(results) => results,
     };
 
     HttpResponse::Ok().json(results).into()
 }
+
+pub async fn list_exam_monitors(path: web::Path<i32>) -> impl Responder {
+    let exam_id = path.into_inner();
+
+    let monitors = match service::list_exam_monitors(exam_id) {
+        Err(e) => return HttpResponse::from_error(e),
+        Ok(monitors) => monitors,
+    };
+
+    HttpResponse::Ok().json(monitors)
+}
+
+pub async fn add_exam_monitor(
+    req: HttpRequest,
+    path: web::Path<i32>,
+    input: web::Json<AddExamMonitorInputDto>,
+) -> impl Responder {
+    let exam_id = path.into_inner();
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::add_exam_monitor(user, exam_id, input.user_id) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(_) => HttpResponse::NoContent().finish(),
+    }
+}
+
+pub async fn remove_exam_monitor(req: HttpRequest, path: web::Path<(i32, i32)>) -> impl Responder {
+    let (exam_id, user_id) = path.into_inner();
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::remove_exam_monitor(user, exam_id, user_id) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(_) => HttpResponse::NoContent().finish(),
+    }
+}
+
+pub async fn get_grading_queue(
+    path: web::Path<i32>,
+    query: web::Query<GradingQueueQueryDto>,
+) -> impl Responder {
+    let exam_id = path.into_inner();
+
+    match service::get_grading_queue(exam_id, query.into_inner()) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(queue) => HttpResponse::Ok().json(queue),
+    }
+}
+
+pub async fn grade_answer(
+    path: web::Path<(i32, i32)>,
+    req: HttpRequest,
+    input: web::Json<GradeAnswerInputDto>,
+) -> impl Responder {
+    let (exam_id, answer_id) = path.into_inner();
+
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::grade_answer(user, exam_id, answer_id, input.into_inner()) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(_) => HttpResponse::NoContent().finish(),
+    }
+}
+
+pub async fn release_grades(path: web::Path<i32>, req: HttpRequest) -> impl Responder {
+    let exam_id = path.into_inner();
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::release_grades(user, exam_id) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(exam) => HttpResponse::Ok().json(exam),
+    }
+}
@@ -1,6 +1,8 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
+use crate::question::grading::Response;
+
 #[derive(Serialize, Deserialize)]
 pub struct CreateExamInputDto {
     pub name: String,
@@ -23,18 +25,25 @@ impl CreateExamInputDto {
     }
 }
 
+/// One of `answer_id`, `answer_ids`, `boolean`, `number` or `text`, whichever the question
+/// type takes.
 #[derive(Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct StudentAnswerInputDto {
-    pub answer_id: i32,
+    pub response: Response,
 }
 
 impl StudentAnswerInputDto {
     pub fn validate(&self) -> Result<(), String> {
-        if self.answer_id <= 0 {
-            return Err("Answer id is required".to_string());
+        match &self.response {
+            Response::AnswerId(answer_id) if *answer_id <= 0 => {
+                Err("Answer id is required".to_string())
+            }
+            Response::AnswerIds(answer_ids) if answer_ids.is_empty() => {
+                Err("Pick at least 1 answer".to_string())
+            }
+            _ => Ok(()),
         }
-
-        Ok(())
     }
 }
 
@@ -48,6 +57,99 @@ pub struct StudentExamResultDto {
 #[derive(Serialize, Deserialize)]
 pub struct StudentExamAnswerResultDto {
     pub question_id: i32,
+    /// The picked answer of single choice questions, 0 otherwise.
     pub answer_id: i32,
+    pub response: Option<Response>,
     pub is_correct: bool,
+    /// Credit between 0 and 1, `None` until someone grades the answer.
+    pub score: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<String>,
+}
+
+/// Where an exam is at with the answers that are graded by hand.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum GradingStatus {
+    /// Students are still answering.
+    Open,
+    InProgress,
+    /// Students can see their manual scores and feedback, which are final by then.
+    Released,
+}
+
+fn anonymous_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct GradingQueueQueryDto {
+    /// Lists the answers already graded too.
+    #[serde(default)]
+    pub include_graded: bool,
+    /// Leaves out who wrote each answer unless turned off.
+    #[serde(default = "anonymous_by_default")]
+    pub anonymous: bool,
+}
+
+#[derive(Serialize)]
+pub struct GradingQueueDto {
+    pub status: GradingStatus,
+    /// Answers still without a score.
+    pub pending: usize,
+    pub answers: Vec<GradingAnswerDto>,
+}
+
+#[derive(Serialize)]
+pub struct GradingAnswerDto {
+    pub id: i32,
+    pub question_id: i32,
+    pub question: String,
+    pub response: Option<Response>,
+    pub score: Option<f32>,
+    pub feedback: Option<String>,
+    pub graded_by: Option<i32>,
+    pub graded_at: Option<NaiveDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub student_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub student_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GradeAnswerInputDto {
+    /// Credit between 0 and 1.
+    pub score: f32,
+    pub feedback: Option<String>,
+}
+
+impl GradeAnswerInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.score) {
+            return Err("Score must be between 0 and 1".to_string());
+        }
+
+        if self
+            .feedback
+            .as_ref()
+            .is_some_and(|feedback| feedback.chars().count() > 10_000)
+        {
+            return Err("Feedback can't be longer than 10000 characters".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExamMonitorDto {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub assigned_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AddExamMonitorInputDto {
+    pub user_id: i32,
 }
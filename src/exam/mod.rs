@@ -2,4 +2,4 @@ pub mod controller;
 mod dto;
 mod models;
 mod repository;
-mod service;
+pub mod service;
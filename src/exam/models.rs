@@ -2,7 +2,7 @@ use chrono::NaiveDateTime;
 use diesel::{deserialize::Queryable, prelude::Insertable, query_builder::AsChangeset};
 use serde::{Deserialize, Serialize};
 
-use crate::schema::*;
+use crate::{question::grading::Response, schema::*};
 
 #[derive(Debug, Serialize, Deserialize, Queryable)]
 pub struct Exam {
@@ -12,6 +12,7 @@ pub struct Exam {
     pub end_date: NaiveDateTime,
     pub class_id: i32,
     pub created_at: NaiveDateTime,
+    pub grades_released_at: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -34,11 +35,38 @@ pub struct UpdateExam {
 #[derive(Debug, Serialize, Deserialize, Queryable)]
 pub struct StudentAnswer {
     pub id: i32,
-    pub user_id: i32,
+    pub user_id: Option<i32>,
     pub exam_id: i32,
     pub question_id: i32,
-    pub answer_id: i32,
+    pub answer_id: Option<i32>,
     pub created_at: NaiveDateTime,
+    pub response: Option<serde_json::Value>,
+    pub manual_score: Option<f32>,
+    pub feedback: Option<String>,
+    pub graded_by: Option<i32>,
+    pub graded_at: Option<NaiveDateTime>,
+    /// The version of the question the student answered.
+    pub question_version_id: i32,
+}
+
+impl StudentAnswer {
+    /// What the student answered, whichever column it went to.
+    pub fn response(&self) -> Option<Response> {
+        match (self.answer_id, &self.response) {
+            (Some(answer_id), _) => Some(Response::AnswerId(answer_id)),
+            (None, Some(response)) => serde_json::from_value(response.clone()).ok(),
+            (None, None) => None,
+        }
+    }
+
+    /// Score and feedback given by hand, hidden until the grades are released.
+    pub fn manual_grade(&self, released: bool) -> (Option<f32>, Option<String>) {
+        if !released {
+            return (None, None);
+        }
+
+        (self.manual_score, self.feedback.clone())
+    }
 }
 
 #[derive(Insertable)]
@@ -47,5 +75,7 @@ pub struct NewStudentAnswer {
     pub user_id: i32,
     pub exam_id: i32,
     pub question_id: i32,
-    pub answer_id: i32,
+    pub answer_id: Option<i32>,
+    pub response: Option<serde_json::Value>,
+    pub question_version_id: i32,
 }
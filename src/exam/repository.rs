@@ -3,18 +3,26 @@ use std::error::Error;
 use diesel::RunQueryDsl;
 
 use crate::{
+    audit::{
+        self,
+        model::{snapshot, NewAuditEvent},
+    },
     db::DB_MANAGER,
     errors::ServiceError,
     question::{
         dto::QuestionWithAnswersDto,
-        models::{Answer, Question},
+        grading::{self, Response},
+        models::{Answer, QuestionVersion},
+    },
+    schema::{
+        answers, exam_monitors, exam_questions, exams, question_versions, questions,
+        student_answers, users,
     },
-    schema::{answers, exam_questions, exams, questions, student_answers},
 };
 
 use super::{
-    dto::StudentExamResultDto,
-    models::{Exam, NewExam, UpdateExam},
+    dto::{ExamMonitorDto, StudentExamResultDto},
+    models::{Exam, NewExam, NewStudentAnswer, StudentAnswer, UpdateExam},
 };
 use crate::diesel::*;
 
@@ -52,39 +60,98 @@ pub fn get_exams_by_class_id(class_id: i32) -> Result<Vec<Exam>, ServiceError> {
     Ok(exams)
 }
 
-pub fn update_exam(exam_id: i32, new_exam: UpdateExam) -> Result<Exam, ServiceError> {
+pub fn update_exam(
+    actor_id: i32,
+    exam_id: i32,
+    new_exam: UpdateExam,
+) -> Result<Exam, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
-    let result: Exam = diesel::update(exams::table.find(exam_id))
-        .set(new_exam)
-        .get_result(&mut conn)
-        .map_err(|_| ServiceError::InternalServerError)?;
+    let result = conn.transaction::<_, Box<dyn Error>, _>(|tx| {
+        let before: Exam = exams::table.find(exam_id).first(tx)?;
+
+        let exam: Exam = diesel::update(exams::table.find(exam_id))
+            .set(new_exam)
+            .get_result(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "update",
+                "exam",
+                exam_id,
+                snapshot(&before),
+                snapshot(&exam),
+            ),
+        )?;
+
+        Ok(exam)
+    });
 
-    Ok(result)
+    result.map_err(|_| ServiceError::InternalServerError)
 }
 
-pub fn delete_exam(exam_id: i32) -> Result<(), ServiceError> {
+pub fn delete_exam(actor_id: i32, exam_id: i32) -> Result<(), ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
-    diesel::delete(exams::table.find(exam_id))
-        .execute(&mut conn)
-        .map_err(|_| ServiceError::InternalServerError)?;
+    let result = conn.transaction::<_, Box<dyn Error>, _>(|tx| {
+        let exam: Exam = diesel::delete(exams::table.find(exam_id)).get_result(tx)?;
 
-    Ok(())
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(actor_id, "delete", "exam", exam_id, snapshot(&exam), None),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
 }
 
-pub fn update_questions_in_exam(exam_id: i32, question_ids: Vec<i32>) -> Result<(), ServiceError> {
+pub fn update_questions_in_exam(
+    actor_id: i32,
+    exam_id: i32,
+    question_ids: Vec<i32>,
+) -> Result<(), ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
     let result = conn.transaction::<_, Box<dyn Error>, _>(|tx| {
+        let previous_ids: Vec<i32> = exam_questions::table
+            .filter(exam_questions::exam_id.eq(exam_id))
+            .order(exam_questions::question_id)
+            .select(exam_questions::question_id)
+            .load(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "set_questions",
+                "exam",
+                exam_id,
+                Some(serde_json::json!({ "question_ids": previous_ids })),
+                Some(serde_json::json!({ "question_ids": question_ids })),
+            ),
+        )?;
+
         diesel::delete(exam_questions::table.filter(exam_questions::exam_id.eq(exam_id)))
             .execute(tx)?;
 
+        // The exam asks the questions as they are now, later edits don't change it.
         for question_id in question_ids {
+            let question_version_id: i32 = question_versions::table
+                .inner_join(questions::table)
+                .filter(questions::id.eq(question_id))
+                .filter(question_versions::version.eq(questions::version))
+                .select(question_versions::id)
+                .first(tx)?;
+
             diesel::insert_into(exam_questions::table)
                 .values((
                     exam_questions::exam_id.eq(exam_id),
                     exam_questions::question_id.eq(question_id),
+                    exam_questions::question_version_id.eq(question_version_id),
                 ))
                 .execute(tx)?;
         }
@@ -100,22 +167,35 @@ pub fn update_questions_in_exam(exam_id: i32, question_ids: Vec<i32>) -> Result<
     Ok(())
 }
 
+/// The versions of the questions the exam asks.
+fn exam_question_versions(
+    conn: &mut PgConnection,
+    exam_id: i32,
+) -> QueryResult<Vec<QuestionVersion>> {
+    exam_questions::table
+        .inner_join(question_versions::table)
+        .filter(exam_questions::exam_id.eq(exam_id))
+        .select(question_versions::all_columns)
+        .load(conn)
+}
+
+fn version_answers(conn: &mut PgConnection, question_version_id: i32) -> QueryResult<Vec<Answer>> {
+    answers::table
+        .filter(answers::question_version_id.eq(question_version_id))
+        .order(answers::id)
+        .load(conn)
+}
+
 pub fn get_questions_in_exam(exam_id: i32) -> Result<Vec<QuestionWithAnswersDto>, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
-    let questions: Vec<Question> = exam_questions::table
-        .inner_join(questions::table)
-        .filter(exam_questions::exam_id.eq(exam_id))
-        .select(questions::all_columns)
-        .load(&mut conn)
+    let versions = exam_question_versions(&mut conn, exam_id)
         .map_err(|_| ServiceError::InternalServerError)?;
 
     let mut result = Vec::new();
 
-    for question in questions {
-        let answers: Vec<Answer> = answers::table
-            .filter(answers::question_id.eq(question.id))
-            .load(&mut conn)
+    for version in versions {
+        let answers = version_answers(&mut conn, version.id)
             .map_err(|_| ServiceError::InternalServerError)?;
 
         let answers_dto = answers
@@ -128,8 +208,11 @@ pub fn get_questions_in_exam(exam_id: i32) -> Result<Vec<QuestionWithAnswersDto>
             .collect();
 
         result.push(QuestionWithAnswersDto {
-            id: question.id,
-            question: question.question,
+            id: version.question_id,
+            version: version.version,
+            question_type: version.question_type(),
+            settings: version.settings(),
+            question: version.question,
             answers: answers_dto,
         });
     }
@@ -137,33 +220,71 @@ pub fn get_questions_in_exam(exam_id: i32) -> Result<Vec<QuestionWithAnswersDto>
     Ok(result)
 }
 
+/// The version of the question the exam asks, with its answers. `None` if the exam doesn't
+/// ask the question.
+pub fn get_exam_question_version(
+    exam_id: i32,
+    question_id: i32,
+) -> Result<Option<(QuestionVersion, Vec<Answer>)>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let version: Option<QuestionVersion> = exam_questions::table
+        .inner_join(question_versions::table)
+        .filter(exam_questions::exam_id.eq(exam_id))
+        .filter(exam_questions::question_id.eq(question_id))
+        .select(question_versions::all_columns)
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    match version {
+        Some(version) => {
+            let answers = version_answers(&mut conn, version.id)
+                .map_err(|_| ServiceError::InternalServerError)?;
+            Ok(Some((version, answers)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Single choice answers go to `answer_id`, every other kind of response to `response`.
 pub fn submit_answer_to_question_in_exam(
     exam_id: i32,
     question_id: i32,
+    question_version_id: i32,
     student_id: i32,
-    answer_id: i32,
+    response: Response,
 ) -> Result<(), ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
-    println!(
-        "submit_answer_to_question_in_exam: {}, {}, {}, {}",
-        exam_id, question_id, student_id, answer_id
-    );
+    let (answer_id, response) = match response {
+        Response::AnswerId(answer_id) => (Some(answer_id), None),
+        response => (
+            None,
+            Some(serde_json::to_value(response).map_err(|_| ServiceError::InternalServerError)?),
+        ),
+    };
 
     diesel::insert_into(student_answers::table)
-        .values((
-            student_answers::user_id.eq(student_id),
-            student_answers::exam_id.eq(exam_id),
-            student_answers::question_id.eq(question_id),
-            student_answers::answer_id.eq(answer_id),
-        ))
+        .values(&NewStudentAnswer {
+            user_id: student_id,
+            exam_id,
+            question_id,
+            answer_id,
+            response: response.clone(),
+            question_version_id,
+        })
         .on_conflict((
             student_answers::user_id,
             student_answers::exam_id,
             student_answers::question_id,
         ))
         .do_update()
-        .set(student_answers::answer_id.eq(answer_id))
+        .set((
+            student_answers::answer_id.eq(answer_id),
+            student_answers::response.eq(response),
+            student_answers::question_version_id.eq(question_version_id),
+        ))
         .execute(&mut conn)
         .map_err(|e| {
             println!("{:?}", e);
@@ -173,57 +294,79 @@ pub fn submit_answer_to_question_in_exam(
     Ok(())
 }
 
+/// Answers graded by hand only get their score and feedback `with_manual_grades`.
 pub fn get_exam_results_as_student(
     exam_id: i32,
     student_id: i32,
+    with_manual_grades: bool,
 ) -> Result<StudentExamResultDto, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
-    let questions: Vec<Question> = exam_questions::table
-        .inner_join(questions::table)
-        .filter(exam_questions::exam_id.eq(exam_id))
-        .select(questions::all_columns)
-        .load(&mut conn)
+    let versions = exam_question_versions(&mut conn, exam_id)
         .map_err(|_| ServiceError::InternalServerError)?;
 
     let mut student_answer_results = Vec::new();
 
-    for question in questions {
-        let student_answer: Option<i32> = student_answers::table
+    for version in versions {
+        let student_answer: Option<StudentAnswer> = student_answers::table
             .filter(
                 student_answers::user_id
                     .eq(student_id)
                     .and(student_answers::exam_id.eq(exam_id))
-                    .and(student_answers::question_id.eq(question.id)),
+                    .and(student_answers::question_id.eq(version.question_id)),
             )
-            .select(student_answers::answer_id)
             .first(&mut conn)
             .optional()
             .map_err(|_| ServiceError::InternalServerError)?;
 
-        let is_correct = match student_answer {
-            Some(student_answer) => {
-                let answer = answers::table
-                    .find(student_answer)
-                    .first::<Answer>(&mut conn)
+        let response = student_answer.as_ref().and_then(StudentAnswer::response);
+
+        // Unanswered questions are graded, with nothing, right away. Answers are graded
+        // against the version the student saw.
+        let mut score = match (&student_answer, &response) {
+            (Some(student_answer), Some(response)) => {
+                let answered: QuestionVersion = question_versions::table
+                    .find(student_answer.question_version_id)
+                    .first(&mut conn)
+                    .map_err(|_| ServiceError::InternalServerError)?;
+                let answers = version_answers(&mut conn, answered.id)
                     .map_err(|_| ServiceError::InternalServerError)?;
 
-                answer.is_correct
+                grading::grade(
+                    answered.question_type(),
+                    &answered.settings(),
+                    &answers,
+                    response,
+                )
             }
-            None => None::<bool>.is_some(),
+            _ => Some(0.0),
         };
 
+        let mut feedback = None;
+        if score.is_none() {
+            if let Some(student_answer) = &student_answer {
+                (score, feedback) = student_answer.manual_grade(with_manual_grades);
+            }
+        }
+
         student_answer_results.push(crate::exam::dto::StudentExamAnswerResultDto {
-            question_id: question.id,
-            answer_id: student_answer.unwrap_or(0),
-            is_correct,
+            question_id: version.question_id,
+            answer_id: match response {
+                Some(Response::AnswerId(answer_id)) => answer_id,
+                _ => 0,
+            },
+            response,
+            is_correct: score == Some(1.0),
+            score,
+            feedback,
         });
     }
 
+    // Answers still waiting for a grade count as nothing for now.
     let score = student_answer_results
         .iter()
-        .filter(|result| result.is_correct)
-        .count() as f32
+        .map(|result| result.score.unwrap_or(0.0))
+        .sum::<f32>()
         / student_answer_results.len() as f32;
 
     let student = crate::user::service::get_user_with_roles_by_id(student_id)?;
@@ -249,7 +392,8 @@ pub fn get_exam_results_as_teacher(
 
     let student_ids: Vec<i32> = student_answers::table
         .filter(student_answers::exam_id.eq(exam_id))
-        .select(student_answers::user_id)
+        .filter(student_answers::user_id.is_not_null())
+        .select(student_answers::user_id.assume_not_null())
         .distinct()
         .load(&mut conn)
         .map_err(|_| ServiceError::InternalServerError)?;
@@ -257,10 +401,252 @@ pub fn get_exam_results_as_teacher(
     let mut results = Vec::new();
 
     for student_id in student_ids {
-        let result = get_exam_results_as_student(exam_id, student_id)?;
+        let result = get_exam_results_as_student(exam_id, student_id, true)?;
 
         results.push(result);
     }
 
     Ok(results)
 }
+
+/// Answers to the exam's questions of the given types, with the question as the student
+/// saw it and the name of the student, grouped by question.
+pub fn list_answers_to_grade(
+    exam_id: i32,
+    question_types: &[&str],
+) -> Result<Vec<(StudentAnswer, String, Option<String>)>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let answers = student_answers::table
+        .inner_join(question_versions::table)
+        .left_join(users::table.on(student_answers::user_id.eq(users::id.nullable())))
+        .filter(student_answers::exam_id.eq(exam_id))
+        .filter(question_versions::question_type.eq_any(question_types))
+        .filter(
+            student_answers::question_id.eq_any(
+                exam_questions::table
+                    .filter(exam_questions::exam_id.eq(exam_id))
+                    .select(exam_questions::question_id),
+            ),
+        )
+        .order((student_answers::question_id, student_answers::id))
+        .select((
+            student_answers::all_columns,
+            question_versions::question,
+            users::name.nullable(),
+        ))
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(answers)
+}
+
+pub fn get_student_answer(answer_id: i32) -> Result<Option<StudentAnswer>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let answer = student_answers::table
+        .find(answer_id)
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(answer)
+}
+
+pub fn grade_answer(
+    actor_id: i32,
+    answer_id: i32,
+    score: f32,
+    feedback: Option<String>,
+) -> Result<StudentAnswer, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result = conn.transaction::<_, Box<dyn Error>, _>(|tx| {
+        let before: StudentAnswer = student_answers::table.find(answer_id).first(tx)?;
+
+        let answer: StudentAnswer = diesel::update(student_answers::table.find(answer_id))
+            .set((
+                student_answers::manual_score.eq(score),
+                student_answers::feedback.eq(feedback),
+                student_answers::graded_by.eq(actor_id),
+                student_answers::graded_at.eq(diesel::dsl::now),
+            ))
+            .get_result(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "grade",
+                "student_answer",
+                answer_id,
+                Some(serde_json::json!({
+                    "score": before.manual_score,
+                    "feedback": before.feedback,
+                })),
+                Some(serde_json::json!({
+                    "score": answer.manual_score,
+                    "feedback": answer.feedback,
+                })),
+            ),
+        )?;
+
+        Ok(answer)
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn release_grades(actor_id: i32, exam_id: i32) -> Result<Exam, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result = conn.transaction::<_, Box<dyn Error>, _>(|tx| {
+        let exam: Exam = diesel::update(exams::table.find(exam_id))
+            .set(exams::grades_released_at.eq(diesel::dsl::now))
+            .get_result(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "release_grades",
+                "exam",
+                exam_id,
+                None,
+                snapshot(&exam),
+            ),
+        )?;
+
+        Ok(exam)
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn list_exam_monitors(exam_id: i32) -> Result<Vec<ExamMonitorDto>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let monitors = exam_monitors::table
+        .inner_join(users::table)
+        .filter(exam_monitors::exam_id.eq(exam_id))
+        .select((
+            users::id,
+            users::name,
+            users::email,
+            exam_monitors::created_at,
+        ))
+        .load::<(i32, String, String, chrono::NaiveDateTime)>(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(monitors
+        .into_iter()
+        .map(|(id, name, email, assigned_at)| ExamMonitorDto {
+            id,
+            name,
+            email,
+            assigned_at,
+        })
+        .collect())
+}
+
+fn monitor_snapshot(user_id: i32, is_monitor: bool) -> Option<serde_json::Value> {
+    Some(serde_json::json!({ "monitors": { user_id.to_string(): is_monitor } }))
+}
+
+pub fn add_exam_monitor(actor_id: i32, exam_id: i32, user_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let added = diesel::insert_into(exam_monitors::table)
+            .values((
+                exam_monitors::exam_id.eq(exam_id),
+                exam_monitors::user_id.eq(user_id),
+            ))
+            .on_conflict_do_nothing()
+            .execute(tx)?;
+
+        if added > 0 {
+            audit::repository::record(
+                tx,
+                NewAuditEvent::new(
+                    actor_id,
+                    "add_monitor",
+                    "exam",
+                    exam_id,
+                    monitor_snapshot(user_id, false),
+                    monitor_snapshot(user_id, true),
+                ),
+            )?;
+        }
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn remove_exam_monitor(actor_id: i32, exam_id: i32, user_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let removed = diesel::delete(exam_monitors::table.find((exam_id, user_id))).execute(tx)?;
+
+        if removed > 0 {
+            audit::repository::record(
+                tx,
+                NewAuditEvent::new(
+                    actor_id,
+                    "remove_monitor",
+                    "exam",
+                    exam_id,
+                    monitor_snapshot(user_id, true),
+                    monitor_snapshot(user_id, false),
+                ),
+            )?;
+        }
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn is_exam_monitor(exam_id: i32, user_id: i32) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let count: i64 = exam_monitors::table
+        .find((exam_id, user_id))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(count > 0)
+}
+
+pub fn is_class_monitor(class_id: i32, user_id: i32) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let count: i64 = exam_monitors::table
+        .inner_join(exams::table)
+        .filter(exams::class_id.eq(class_id))
+        .filter(exam_monitors::user_id.eq(user_id))
+        .count()
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(count > 0)
+}
+
+pub fn list_classes_using_question(question_id: i32) -> Result<Vec<i32>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let class_ids = exam_questions::table
+        .inner_join(exams::table)
+        .filter(exam_questions::question_id.eq(question_id))
+        .select(exams::class_id)
+        .distinct()
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(class_ids)
+}
@@ -1,17 +1,24 @@
-use crate::{class, errors::ServiceError, question};
+use crate::{
+    auth::models::LoggedUser,
+    class,
+    errors::ServiceError,
+    policy::{self, Action, Resource},
+    question::{self, dto::QuestionType, grading::Response},
+    role, user,
+};
 
 use super::{
-    dto::{CreateExamInputDto, StudentExamResultDto},
-    models::{Exam, NewExam, UpdateExam},
+    dto::{
+        CreateExamInputDto, ExamMonitorDto, GradeAnswerInputDto, GradingAnswerDto, GradingQueueDto,
+        GradingQueueQueryDto, GradingStatus, StudentExamResultDto,
+    },
+    models::{Exam, NewExam, StudentAnswer, UpdateExam},
     repository,
 };
 
-pub fn create_exam(user_id: i32, new_exam: CreateExamInputDto) -> Result<Exam, ServiceError> {
-    let is_teacher = class::service::is_class_teacher(user_id, new_exam.class_id)?;
-
-    if !is_teacher {
-        return Err(ServiceError::Forbidden);
-    }
+pub fn create_exam(user: &LoggedUser, new_exam: CreateExamInputDto) -> Result<Exam, ServiceError> {
+    // The class comes with the body, so this one can't be checked by the route guard.
+    policy::authorize(user, Action::CreateExam, Resource::Class(new_exam.class_id))?;
 
     if new_exam.start_date > new_exam.end_date {
         return Err(ServiceError::BadRequest(
@@ -50,21 +57,19 @@ pub fn get_exams_by_class_id(class_id: i32) -> Result<Vec<Exam>, ServiceError> {
     Ok(exams)
 }
 
-pub fn update_exam(user_id: i32, exam_id: i32, new_exam: UpdateExam) -> Result<Exam, ServiceError> {
+pub fn update_exam(
+    user: &LoggedUser,
+    exam_id: i32,
+    new_exam: UpdateExam,
+) -> Result<Exam, ServiceError> {
+    policy::authorize(user, Action::UpdateExam, Resource::Exam(exam_id))?;
+
     let existing = repository::get_exam_by_id(exam_id)?;
 
     if existing.is_none() {
         return Err(ServiceError::BadRequest("Exam not found".to_string()));
     }
 
-    let existing = existing.unwrap();
-
-    let is_teacher = class::service::is_class_teacher(user_id, existing.class_id)?;
-
-    if !is_teacher {
-        return Err(ServiceError::Forbidden);
-    }
-
     if let Some(start_date) = new_exam.start_date {
         if let Some(end_date) = new_exam.end_date {
             if start_date > end_date {
@@ -83,34 +88,30 @@ pub fn update_exam(user_id: i32, exam_id: i32, new_exam: UpdateExam) -> Result<E
         }
     }
 
-    let exam = repository::update_exam(exam_id, new_exam)?;
+    let exam = repository::update_exam(user.id, exam_id, new_exam)?;
     Ok(exam)
 }
 
-pub fn delete_exam(user_id: i32, exam_id: i32) -> Result<(), ServiceError> {
+pub fn delete_exam(user: &LoggedUser, exam_id: i32) -> Result<(), ServiceError> {
+    policy::authorize(user, Action::DeleteExam, Resource::Exam(exam_id))?;
+
     let existing = repository::get_exam_by_id(exam_id)?;
 
     if existing.is_none() {
         return Err(ServiceError::BadRequest("Exam not found".to_string()));
     }
 
-    let existing = existing.unwrap();
-
-    let is_teacher = class::service::is_class_teacher(user_id, existing.class_id)?;
-
-    if !is_teacher {
-        return Err(ServiceError::Forbidden);
-    }
-
-    repository::delete_exam(exam_id)?;
+    repository::delete_exam(user.id, exam_id)?;
     Ok(())
 }
 
 pub fn update_questions_in_exam(
-    user_id: i32,
+    user: &LoggedUser,
     exam_id: i32,
     question_ids: Vec<i32>,
 ) -> Result<(), ServiceError> {
+    policy::authorize(user, Action::SetExamQuestions, Resource::Exam(exam_id))?;
+
     let existing = repository::get_exam_by_id(exam_id)?;
 
     if existing.is_none() {
@@ -119,12 +120,6 @@ pub fn update_questions_in_exam(
 
     let existing = existing.unwrap();
 
-    let is_teacher = class::service::is_class_teacher(user_id, existing.class_id)?;
-
-    if !is_teacher {
-        return Err(ServiceError::Forbidden);
-    }
-
     if existing.start_date < chrono::Utc::now().naive_utc() {
         return Err(ServiceError::BadRequest("Exam already started".to_string()));
     }
@@ -144,6 +139,18 @@ pub fn update_questions_in_exam(
                 )));
             }
 
+            // Private questions of someone else are as good as missing.
+            match question::service::can_use_question(user, *question_id) {
+                Err(e) => return Some(e),
+                Ok(false) => {
+                    return Some(ServiceError::BadRequest(format!(
+                        "Question {} not found",
+                        question_id
+                    )))
+                }
+                Ok(true) => (),
+            }
+
             None
         })
         .filter(|e| e.is_some())
@@ -155,12 +162,11 @@ pub fn update_questions_in_exam(
         return Err(error.clone());
     }
 
-    repository::update_questions_in_exam(exam_id, question_ids)?;
+    repository::update_questions_in_exam(user.id, exam_id, question_ids)?;
     Ok(())
 }
 
 pub fn get_questions_in_exam_as_student(
-    student_id: i32,
     exam_id: i32,
 ) -> Result<Vec<question::dto::QuestionWithAnswersDto>, ServiceError> {
     let exam = repository::get_exam_by_id(exam_id)?;
@@ -171,12 +177,6 @@ pub fn get_questions_in_exam_as_student(
 
     let exam = exam.unwrap();
 
-    let is_student = class::service::is_student_enrolled(exam.class_id, student_id)?;
-
-    if !is_student {
-        return Err(ServiceError::Forbidden);
-    }
-
     if exam.start_date > chrono::Utc::now().naive_utc() {
         return Err(ServiceError::BadRequest("Exam not started yet".to_string()));
     }
@@ -188,6 +188,7 @@ pub fn get_questions_in_exam_as_student(
             for answer in question.answers.iter_mut() {
                 answer.is_correct = None;
             }
+            question.settings = question.settings.without_solution();
         }
     }
 
@@ -211,7 +212,7 @@ pub fn submit_answer_to_question_in_exam(
     user_id: i32,
     exam_id: i32,
     question_id: i32,
-    answer_id: i32,
+    response: Response,
 ) -> Result<(), ServiceError> {
     let exam = repository::get_exam_by_id(exam_id)?;
 
@@ -221,12 +222,6 @@ pub fn submit_answer_to_question_in_exam(
 
     let exam = exam.unwrap();
 
-    let is_enrolled = class::service::is_student_enrolled(exam.class_id, user_id)?;
-
-    if !is_enrolled {
-        return Err(ServiceError::Forbidden);
-    }
-
     if exam.start_date > chrono::Utc::now().naive_utc() {
         return Err(ServiceError::BadRequest("Exam not started yet".to_string()));
     }
@@ -235,27 +230,23 @@ pub fn submit_answer_to_question_in_exam(
         return Err(ServiceError::BadRequest("Exam already ended".to_string()));
     }
 
-    let question = question::service::get_question_by_id(question_id)?;
-
-    if question.is_none() {
-        return Err(ServiceError::BadRequest("Question not found".to_string()));
-    }
-
-    let answers = question::service::list_answers_by_question_id(question_id)?;
-
-    let answer = answers.iter().find(|a| a.id == answer_id);
-
-    if answer.is_none() {
-        return Err(ServiceError::BadRequest("Answer not found".to_string()));
-    }
-
-    let answer = answer.unwrap();
-
-    if answer.question_id != question_id {
-        return Err(ServiceError::BadRequest("Answer not found".to_string()));
-    }
-
-    repository::submit_answer_to_question_in_exam(exam_id, question_id, user_id, answer_id)?;
+    // Answers go to the version of the question the exam asks.
+    let (version, answers) = match repository::get_exam_question_version(exam_id, question_id)? {
+        Some(version) => version,
+        None => return Err(ServiceError::BadRequest("Question not found".to_string())),
+    };
+
+    let response = response
+        .check(version.question_type(), &version.settings(), &answers)
+        .map_err(ServiceError::BadRequest)?;
+
+    repository::submit_answer_to_question_in_exam(
+        exam_id,
+        question_id,
+        version.id,
+        user_id,
+        response,
+    )?;
     Ok(())
 }
 
@@ -279,12 +270,16 @@ pub fn get_exam_results_as_student(
         return Err(ServiceError::BadRequest("Exam not ended yet".to_string()));
     }
 
-    let results = repository::get_exam_results_as_student(exam_id, user_id)?;
+    // Scores given by hand stay hidden until they are released.
+    let results = repository::get_exam_results_as_student(
+        exam_id,
+        user_id,
+        exam.grades_released_at.is_some(),
+    )?;
     Ok(results)
 }
 
 pub fn get_exam_results_as_teacher(
-    user_id: i32,
     exam_id: i32,
 ) -> Result<Vec<StudentExamResultDto>, ServiceError> {
     let exam = repository::get_exam_by_id(exam_id)?;
@@ -293,14 +288,317 @@ pub fn get_exam_results_as_teacher(
         return Err(ServiceError::BadRequest("Exam not found".to_string()));
     }
 
+    let results = repository::get_exam_results_as_teacher(exam_id)?;
+    Ok(results)
+}
+
+fn grading_status(exam: &Exam, now: chrono::NaiveDateTime) -> GradingStatus {
+    if exam.grades_released_at.is_some() {
+        GradingStatus::Released
+    } else if exam.end_date > now {
+        GradingStatus::Open
+    } else {
+        GradingStatus::InProgress
+    }
+}
+
+fn answers_graded_by_hand(
+    exam_id: i32,
+) -> Result<Vec<(StudentAnswer, String, Option<String>)>, ServiceError> {
+    let question_types = QuestionType::GRADED_BY_HAND.map(|question_type| question_type.as_str());
+
+    repository::list_answers_to_grade(exam_id, &question_types)
+}
+
+fn grading_answer(
+    answer: StudentAnswer,
+    question: String,
+    student_name: Option<String>,
+    anonymous: bool,
+) -> GradingAnswerDto {
+    GradingAnswerDto {
+        id: answer.id,
+        question_id: answer.question_id,
+        question,
+        response: answer.response(),
+        score: answer.manual_score,
+        feedback: answer.feedback,
+        graded_by: answer.graded_by,
+        graded_at: answer.graded_at,
+        student_id: answer.user_id.filter(|_| !anonymous),
+        student_name: student_name.filter(|_| !anonymous),
+    }
+}
+
+/// Answers that need a person to grade them, without their students unless asked.
+pub fn get_grading_queue(
+    exam_id: i32,
+    query: GradingQueueQueryDto,
+) -> Result<GradingQueueDto, ServiceError> {
+    let exam = repository::get_exam_by_id(exam_id)?;
+
+    if exam.is_none() {
+        return Err(ServiceError::BadRequest("Exam not found".to_string()));
+    }
+
     let exam = exam.unwrap();
 
-    let is_teacher = class::service::is_class_teacher(user_id, exam.class_id)?;
+    let answers = answers_graded_by_hand(exam_id)?;
+    let pending = answers
+        .iter()
+        .filter(|(answer, _, _)| answer.manual_score.is_none())
+        .count();
+
+    let answers = answers
+        .into_iter()
+        .filter(|(answer, _, _)| query.include_graded || answer.manual_score.is_none())
+        .map(|(answer, question, student_name)| {
+            grading_answer(answer, question, student_name, query.anonymous)
+        })
+        .collect();
+
+    Ok(GradingQueueDto {
+        status: grading_status(&exam, chrono::Utc::now().naive_utc()),
+        pending,
+        answers,
+    })
+}
+
+pub fn grade_answer(
+    user: &LoggedUser,
+    exam_id: i32,
+    answer_id: i32,
+    input: GradeAnswerInputDto,
+) -> Result<(), ServiceError> {
+    policy::authorize(user, Action::GradeExamAnswers, Resource::Exam(exam_id))?;
+
+    let exam = repository::get_exam_by_id(exam_id)?;
 
-    if !is_teacher {
-        return Err(ServiceError::Forbidden);
+    if exam.is_none() {
+        return Err(ServiceError::BadRequest("Exam not found".to_string()));
     }
 
-    let results = repository::get_exam_results_as_teacher(exam_id)?;
-    Ok(results)
+    // Released scores are what students were shown, they don't change behind their backs.
+    match grading_status(&exam.unwrap(), chrono::Utc::now().naive_utc()) {
+        GradingStatus::Open => {
+            return Err(ServiceError::BadRequest("Exam not ended yet".to_string()))
+        }
+        GradingStatus::Released => {
+            return Err(ServiceError::BadRequest(
+                "Grades already released".to_string(),
+            ))
+        }
+        GradingStatus::InProgress => (),
+    }
+
+    let answer = match repository::get_student_answer(answer_id)? {
+        Some(answer) if answer.exam_id == exam_id => answer,
+        _ => return Err(ServiceError::BadRequest("Answer not found".to_string())),
+    };
+
+    let question = question::service::get_version_by_id(answer.question_version_id)?;
+
+    if !question.is_some_and(|question| question.question_type().is_graded_by_hand()) {
+        return Err(ServiceError::BadRequest(
+            "Answer is graded automatically".to_string(),
+        ));
+    }
+
+    repository::grade_answer(user.id, answer_id, input.score, input.feedback)?;
+    Ok(())
+}
+
+/// Shows students their manual scores. Every answer graded by hand must have a score.
+pub fn release_grades(user: &LoggedUser, exam_id: i32) -> Result<Exam, ServiceError> {
+    policy::authorize(user, Action::ReleaseExamGrades, Resource::Exam(exam_id))?;
+
+    let exam = repository::get_exam_by_id(exam_id)?;
+
+    if exam.is_none() {
+        return Err(ServiceError::BadRequest("Exam not found".to_string()));
+    }
+
+    match grading_status(&exam.unwrap(), chrono::Utc::now().naive_utc()) {
+        GradingStatus::Open => {
+            return Err(ServiceError::BadRequest("Exam not ended yet".to_string()))
+        }
+        GradingStatus::Released => {
+            return Err(ServiceError::BadRequest(
+                "Grades already released".to_string(),
+            ))
+        }
+        GradingStatus::InProgress => (),
+    }
+
+    let pending = answers_graded_by_hand(exam_id)?
+        .iter()
+        .filter(|(answer, _, _)| answer.manual_score.is_none())
+        .count();
+
+    if pending > 0 {
+        return Err(ServiceError::BadRequest(format!(
+            "{} answers still need grading",
+            pending
+        )));
+    }
+
+    repository::release_grades(user.id, exam_id)
+}
+
+pub fn list_exam_monitors(exam_id: i32) -> Result<Vec<ExamMonitorDto>, ServiceError> {
+    let exam = repository::get_exam_by_id(exam_id)?;
+
+    if exam.is_none() {
+        return Err(ServiceError::BadRequest("Exam not found".to_string()));
+    }
+
+    repository::list_exam_monitors(exam_id)
+}
+
+pub fn add_exam_monitor(
+    actor: &LoggedUser,
+    exam_id: i32,
+    user_id: i32,
+) -> Result<(), ServiceError> {
+    policy::authorize(actor, Action::ManageExamMonitors, Resource::Exam(exam_id))?;
+
+    let exam = repository::get_exam_by_id(exam_id)?;
+
+    if exam.is_none() {
+        return Err(ServiceError::BadRequest("Exam not found".to_string()));
+    }
+
+    let monitor = user::service::get_user_with_roles_by_id(user_id)?;
+
+    let monitor = match monitor {
+        Some(monitor) => monitor,
+        None => return Err(ServiceError::BadRequest("User not found".to_string())),
+    };
+
+    if !role::service::permissions_of(&monitor.roles)?
+        .iter()
+        .any(|permission| permission == "exams:monitor")
+    {
+        return Err(ServiceError::BadRequest(
+            "User is not a monitor".to_string(),
+        ));
+    }
+
+    repository::add_exam_monitor(actor.id, exam_id, user_id)
+}
+
+pub fn remove_exam_monitor(
+    actor: &LoggedUser,
+    exam_id: i32,
+    user_id: i32,
+) -> Result<(), ServiceError> {
+    policy::authorize(actor, Action::ManageExamMonitors, Resource::Exam(exam_id))?;
+
+    repository::remove_exam_monitor(actor.id, exam_id, user_id)
+}
+
+pub fn is_exam_monitor(exam_id: i32, user_id: i32) -> Result<bool, ServiceError> {
+    repository::is_exam_monitor(exam_id, user_id)
+}
+
+pub fn is_class_monitor(class_id: i32, user_id: i32) -> Result<bool, ServiceError> {
+    repository::is_class_monitor(class_id, user_id)
+}
+
+pub fn list_classes_using_question(question_id: i32) -> Result<Vec<i32>, ServiceError> {
+    repository::list_classes_using_question(question_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    fn at(hour: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn exam(grades_released_at: Option<NaiveDateTime>) -> Exam {
+        Exam {
+            id: 1,
+            name: "Midterm".to_string(),
+            start_date: at(8),
+            end_date: at(10),
+            class_id: 1,
+            created_at: at(0),
+            grades_released_at,
+        }
+    }
+
+    fn graded_answer() -> StudentAnswer {
+        StudentAnswer {
+            id: 7,
+            user_id: Some(4),
+            exam_id: 1,
+            question_id: 3,
+            answer_id: None,
+            created_at: at(9),
+            response: Some(serde_json::json!({ "text": "Because of the tides" })),
+            manual_score: Some(0.5),
+            feedback: Some("Half right".to_string()),
+            graded_by: Some(2),
+            graded_at: Some(at(11)),
+            question_version_id: 3,
+        }
+    }
+
+    #[test]
+    fn grading_status_follows_the_exam() {
+        assert_eq!(grading_status(&exam(None), at(9)), GradingStatus::Open);
+        assert_eq!(
+            grading_status(&exam(None), at(11)),
+            GradingStatus::InProgress
+        );
+        assert_eq!(
+            grading_status(&exam(Some(at(12))), at(13)),
+            GradingStatus::Released
+        );
+    }
+
+    #[test]
+    fn grading_queue_is_anonymous_by_default() {
+        let query = web::Query::<GradingQueueQueryDto>::from_query("").unwrap();
+        assert!(query.anonymous);
+        assert!(!query.include_graded);
+
+        let answer = grading_answer(
+            graded_answer(),
+            "Why?".to_string(),
+            Some("Ana".to_string()),
+            query.anonymous,
+        );
+        assert_eq!(answer.student_id, None);
+        assert_eq!(answer.student_name, None);
+
+        let query = web::Query::<GradingQueueQueryDto>::from_query("anonymous=false").unwrap();
+        let answer = grading_answer(
+            graded_answer(),
+            "Why?".to_string(),
+            Some("Ana".to_string()),
+            query.anonymous,
+        );
+        assert_eq!(answer.student_id, Some(4));
+        assert_eq!(answer.student_name.as_deref(), Some("Ana"));
+    }
+
+    #[test]
+    fn manual_grades_stay_hidden_until_released() {
+        let answer = graded_answer();
+
+        assert_eq!(answer.manual_grade(false), (None, None));
+        assert_eq!(
+            answer.manual_grade(true),
+            (Some(0.5), Some("Half right".to_string()))
+        );
+    }
 }
@@ -0,0 +1,76 @@
+use std::{fs, path::PathBuf};
+
+use lazy_static::lazy_static;
+
+use crate::errors::ServiceError;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), ServiceError>;
+}
+
+/// Writes every message as a plain text file to a local outbox directory.
+pub struct FileMailer {
+    outbox: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(outbox: impl Into<PathBuf>) -> Self {
+        FileMailer {
+            outbox: outbox.into(),
+        }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: &Mail) -> Result<(), ServiceError> {
+        fs::create_dir_all(&self.outbox).map_err(|_| ServiceError::InternalServerError)?;
+
+        let file_name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%d%H%M%S%f"),
+            mail.to.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+        );
+
+        let content = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            mail.to, mail.subject, mail.body
+        );
+
+        fs::write(self.outbox.join(file_name), content)
+            .map_err(|_| ServiceError::InternalServerError)?;
+
+        Ok(())
+    }
+}
+
+/// Only logs the message, handy when running the api locally.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, mail: &Mail) -> Result<(), ServiceError> {
+        log::info!("mail to {} | {}\n{}", mail.to, mail.subject, mail.body);
+
+        Ok(())
+    }
+}
+
+fn mailer_from_env() -> Box<dyn Mailer> {
+    match std::env::var("MAILER").as_deref() {
+        Ok("file") => {
+            let outbox = std::env::var("MAILER_OUTBOX_DIR").unwrap_or("outbox".to_string());
+            Box::new(FileMailer::new(outbox))
+        }
+        _ => Box::new(LogMailer),
+    }
+}
+
+lazy_static! {
+    pub static ref MAILER: Box<dyn Mailer> = mailer_from_env();
+}
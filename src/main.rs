@@ -1,18 +1,26 @@
 use actix_web::{middleware::Logger, web, App, HttpServer};
 use db::DB_MANAGER;
 use dotenvy::dotenv;
-use role::enm::RoleEnum::*;
+use middleware::PolicyMiddleware;
+use policy::Action;
 
+mod api_token;
+mod audit;
 mod auth;
 mod avatar;
+mod blob_store;
+mod cache;
 mod class;
 mod db;
 mod errors;
 mod exam;
+mod mailer;
 mod middleware;
+mod policy;
 mod question;
 mod role;
 mod schema;
+mod topic;
 mod user;
 
 extern crate diesel;
@@ -24,6 +32,8 @@ async fn main() -> std::io::Result<()> {
     env_logger::init();
 
     DB_MANAGER.lock().unwrap().start_connection().await;
+    lazy_static::initialize(&auth::keys::JWT_KEYS);
+    lazy_static::initialize(&auth::oidc::OIDC_PROVIDERS);
 
     HttpServer::new(move || {
         App::new()
@@ -31,34 +41,206 @@ async fn main() -> std::io::Result<()> {
             .service(
                 web::scope("/users")
                     .service(web::resource("").post(user::controller::create_user))
+                    .service(web::resource("/verify").post(user::controller::verify_email))
                     .service(
-                        web::resource("/{user_id}/roles")
-                            .wrap(middleware::RoleMiddleware(vec![ADMIN]))
+                        web::resource("/verify/resend")
+                            .wrap(PolicyMiddleware(Action::ManageAccount))
                             .wrap(middleware::AuthMiddleware)
+                            .post(user::controller::resend_verification_email),
+                    )
+                    .service(
+                        web::resource("/me")
+                            .wrap(PolicyMiddleware(Action::ManageAccount))
+                            .wrap(middleware::AuthMiddleware)
+                            .get(user::controller::get_me)
+                            .patch(user::controller::update_me)
+                            .delete(user::controller::delete_me),
+                    )
+                    .service(
+                        web::resource("/me/password")
+                            .wrap(PolicyMiddleware(Action::ManageAccount))
+                            .wrap(middleware::AuthMiddleware)
+                            .post(user::controller::change_password),
+                    )
+                    .service(
+                        web::resource("/{user_id}/roles")
+                            .wrap(PolicyMiddleware(Action::SetUserRoles))
+                            .wrap(middleware::VerifiedAuthMiddleware)
                             .patch(user::controller::set_user_roles),
+                    )
+                    .service(
+                        web::resource("/{user_id}/unlock")
+                            .wrap(PolicyMiddleware(Action::UnlockUser))
+                            .wrap(middleware::VerifiedAuthMiddleware)
+                            .post(auth::controller::unlock_user),
+                    )
+                    .service(
+                        web::resource("/{user_id}/impersonate")
+                            .wrap(PolicyMiddleware(Action::Impersonate))
+                            .wrap(middleware::VerifiedAuthMiddleware)
+                            .post(auth::controller::impersonate),
                     ),
             )
+            .service(
+                web::scope("/admin/users")
+                    .wrap(middleware::VerifiedAuthMiddleware)
+                    .service(
+                        web::resource("")
+                            .wrap(PolicyMiddleware(Action::ListUsers))
+                            .get(user::controller::list_users),
+                    )
+                    .service(
+                        web::resource("/import")
+                            .wrap(PolicyMiddleware(Action::ImportUsers))
+                            .post(user::controller::import_users),
+                    )
+                    .service(
+                        web::resource("/{user_id}/deactivate")
+                            .wrap(PolicyMiddleware(Action::SetUserActive))
+                            .post(user::controller::deactivate_user),
+                    )
+                    .service(
+                        web::resource("/{user_id}/reactivate")
+                            .wrap(PolicyMiddleware(Action::SetUserActive))
+                            .post(user::controller::reactivate_user),
+                    )
+                    .service(
+                        web::resource("/{user_id}/password-reset")
+                            .wrap(PolicyMiddleware(Action::ForcePasswordReset))
+                            .post(auth::controller::force_password_reset),
+                    ),
+            )
+            .service(
+                web::scope("/admin/roles")
+                    .wrap(PolicyMiddleware(Action::ManageRoles))
+                    .wrap(middleware::VerifiedAuthMiddleware)
+                    .service(
+                        web::resource("")
+                            .get(role::controller::list_roles)
+                            .post(role::controller::create_role),
+                    )
+                    .service(
+                        web::resource("/{role_name}")
+                            .patch(role::controller::update_role)
+                            .delete(role::controller::delete_role),
+                    ),
+            )
+            .service(
+                web::resource("/admin/permissions")
+                    .wrap(PolicyMiddleware(Action::ManageRoles))
+                    .wrap(middleware::VerifiedAuthMiddleware)
+                    .get(role::controller::list_permissions),
+            )
+            .service(
+                web::scope("/admin/audit-events")
+                    .wrap(PolicyMiddleware(Action::ViewAuditLog))
+                    .wrap(middleware::VerifiedAuthMiddleware)
+                    .service(web::resource("").get(audit::controller::list_events))
+                    .service(web::resource("/export").get(audit::controller::export_events)),
+            )
             .service(
                 web::scope("/avatars")
-                    .wrap(middleware::AuthMiddleware)
                     .service(
                         web::resource("")
+                            .wrap(PolicyMiddleware(Action::ManageAccount))
+                            .wrap(middleware::AuthMiddleware)
                             .post(avatar::controller::update_user_avatar)
                             .delete(avatar::controller::delete_user_avatar),
+                    )
+                    .service(
+                        web::resource("/upload")
+                            .wrap(PolicyMiddleware(Action::ManageAccount))
+                            .wrap(middleware::AuthMiddleware)
+                            .post(avatar::controller::upload_user_avatar),
+                    )
+                    .service(web::resource("/{user_id}").get(avatar::controller::get_avatar_image)),
+            )
+            .service(
+                web::scope("/tokens")
+                    .wrap(PolicyMiddleware(Action::ManageApiTokens))
+                    .wrap(middleware::VerifiedAuthMiddleware)
+                    .service(
+                        web::resource("")
+                            .post(api_token::controller::create_api_token)
+                            .get(api_token::controller::list_api_tokens),
+                    )
+                    .service(
+                        web::resource("/{token_id}")
+                            .delete(api_token::controller::revoke_api_token),
+                    ),
+            )
+            .service(web::resource("/.well-known/jwks.json").get(auth::controller::jwks))
+            .service(
+                web::scope("/auth")
+                    .service(web::resource("/login").post(auth::controller::login))
+                    .service(web::resource("/refresh").post(auth::controller::refresh))
+                    .service(
+                        web::resource("/oidc/providers").get(auth::controller::list_oidc_providers),
+                    )
+                    .service(
+                        web::resource("/oidc/{provider}/authorize")
+                            .get(auth::controller::oidc_authorize),
+                    )
+                    .service(
+                        web::resource("/oidc/{provider}/link")
+                            .wrap(PolicyMiddleware(Action::ManageAccount))
+                            .wrap(middleware::AuthMiddleware)
+                            .post(auth::controller::oidc_link),
+                    )
+                    .service(
+                        web::resource("/oidc/{provider}/callback")
+                            .get(auth::controller::oidc_callback),
+                    )
+                    .service(
+                        web::resource("/password/forgot").post(auth::controller::forgot_password),
+                    )
+                    .service(
+                        web::resource("/password/reset").post(auth::controller::reset_password),
+                    )
+                    .service(web::resource("/mfa/verify").post(auth::controller::verify_mfa))
+                    .service(
+                        web::resource("/mfa/totp")
+                            .wrap(PolicyMiddleware(Action::ManageMfa))
+                            .wrap(middleware::AuthMiddleware)
+                            .post(auth::controller::enroll_totp)
+                            .delete(auth::controller::disable_totp),
+                    )
+                    .service(
+                        web::resource("/mfa/totp/confirm")
+                            .wrap(PolicyMiddleware(Action::ManageMfa))
+                            .wrap(middleware::AuthMiddleware)
+                            .post(auth::controller::confirm_totp),
+                    )
+                    .service(
+                        web::resource("/mfa/recovery-codes")
+                            .wrap(PolicyMiddleware(Action::ManageMfa))
+                            .wrap(middleware::AuthMiddleware)
+                            .post(auth::controller::regenerate_recovery_codes),
+                    )
+                    .service(
+                        web::resource("/logout")
+                            .wrap(PolicyMiddleware(Action::ManageAccount))
+                            .wrap(middleware::AuthMiddleware)
+                            .post(auth::controller::logout),
+                    )
+                    .service(
+                        web::resource("/logout-all")
+                            .wrap(PolicyMiddleware(Action::ManageAccount))
+                            .wrap(middleware::AuthMiddleware)
+                            .post(auth::controller::logout_all),
                     ),
             )
-            .service(web::resource("/auth/login").post(auth::controller::login))
             .service(
                 web::scope("/classes")
-                    .wrap(middleware::AuthMiddleware)
+                    .wrap(middleware::VerifiedAuthMiddleware)
                     .service(
                         web::resource("")
-                            .wrap(middleware::RoleMiddleware(vec![TEACHER]))
+                            .wrap(PolicyMiddleware(Action::CreateClass))
                             .post(class::controller::create_class),
                     )
                     .service(
                         web::resource("/students/enrolled")
-                            .wrap(middleware::RoleMiddleware(vec![STUDENT]))
+                            .wrap(PolicyMiddleware(Action::ListEnrolledClasses))
                             .route(
                                 web::get()
                                     .to(class::controller::list_classes_that_student_is_enrolled),
@@ -66,7 +248,7 @@ async fn main() -> std::io::Result<()> {
                     )
                     .service(
                         web::resource("/students/unenrolled")
-                            .wrap(middleware::RoleMiddleware(vec![STUDENT]))
+                            .wrap(PolicyMiddleware(Action::ListUnenrolledClasses))
                             .route(
                                 web::get().to(
                                     class::controller::list_classes_that_student_is_not_enrolled,
@@ -75,7 +257,7 @@ async fn main() -> std::io::Result<()> {
                     )
                     .service(
                         web::resource("/teachers")
-                            .wrap(middleware::RoleMiddleware(vec![TEACHER]))
+                            .wrap(PolicyMiddleware(Action::ListTaughtClasses))
                             .route(web::get().to(class::controller::list_classes_by_teacher)),
                     )
                     .service(
@@ -83,95 +265,205 @@ async fn main() -> std::io::Result<()> {
                             .route(
                                 web::get()
                                     .to(class::controller::get_class_by_id)
-                                    .wrap(middleware::AuthMiddleware),
+                                    .wrap(PolicyMiddleware(Action::ReadClass)),
                             )
                             .route(
                                 web::patch()
                                     .to(class::controller::update_class)
-                                    .wrap(middleware::RoleMiddleware(vec![TEACHER])),
+                                    .wrap(PolicyMiddleware(Action::UpdateClass)),
                             )
                             .route(
                                 web::delete()
                                     .to(class::controller::delete_class)
-                                    .wrap(middleware::RoleMiddleware(vec![TEACHER])),
+                                    .wrap(PolicyMiddleware(Action::DeleteClass)),
                             ),
                     )
                     .service(
                         web::resource("/{class_id}/exams")
+                            .wrap(PolicyMiddleware(Action::ListClassExams))
                             .route(web::get().to(exam::controller::list_exams_by_class_id)),
                     )
                     .service(
                         web::resource("/{class_id}/enroll")
-                            .wrap(middleware::RoleMiddleware(vec![STUDENT]))
+                            .wrap(PolicyMiddleware(Action::EnrollInClass))
                             .route(web::post().to(class::controller::enroll_student)),
+                    )
+                    .service(
+                        web::resource("/{class_id}/staff")
+                            .route(
+                                web::get()
+                                    .to(class::controller::list_class_staff)
+                                    .wrap(PolicyMiddleware(Action::ListClassStaff)),
+                            )
+                            .route(
+                                web::post()
+                                    .to(class::controller::add_class_staff)
+                                    .wrap(PolicyMiddleware(Action::ManageClassStaff)),
+                            ),
+                    )
+                    .service(
+                        web::resource("/{class_id}/staff/{user_id}")
+                            .wrap(PolicyMiddleware(Action::ManageClassStaff))
+                            .route(web::delete().to(class::controller::remove_class_staff)),
+                    )
+                    .service(
+                        web::resource("/{class_id}/transfer")
+                            .wrap(PolicyMiddleware(Action::ManageClassStaff))
+                            .route(web::post().to(class::controller::transfer_class)),
                     ),
             )
             .service(
                 web::scope("/questions")
-                    .wrap(middleware::RoleMiddleware(vec![TEACHER]))
-                    .wrap(middleware::AuthMiddleware)
+                    .wrap(middleware::VerifiedAuthMiddleware)
                     .service(
                         web::resource("")
-                            .post(question::controller::create_question)
-                            .get(question::controller::list_questions),
+                            .route(
+                                web::post()
+                                    .to(question::controller::create_question)
+                                    .wrap(PolicyMiddleware(Action::CreateQuestion)),
+                            )
+                            .route(
+                                web::get()
+                                    .to(question::controller::list_questions)
+                                    .wrap(PolicyMiddleware(Action::ListQuestions)),
+                            ),
+                    )
+                    .service(
+                        web::resource("/facets")
+                            .wrap(PolicyMiddleware(Action::ListQuestions))
+                            .get(question::controller::get_question_facets),
                     )
                     .service(
                         web::resource("/{question_id}")
-                            .get(question::controller::get_question_by_id)
-                            .delete(question::controller::delete_question_by_id)
-                            .patch(question::controller::update_question_by_id),
+                            .route(
+                                web::get()
+                                    .to(question::controller::get_question_by_id)
+                                    .wrap(PolicyMiddleware(Action::ReadQuestion)),
+                            )
+                            .route(
+                                web::delete()
+                                    .to(question::controller::delete_question_by_id)
+                                    .wrap(PolicyMiddleware(Action::DeleteQuestion)),
+                            )
+                            .route(
+                                web::patch()
+                                    .to(question::controller::update_question_by_id)
+                                    .wrap(PolicyMiddleware(Action::UpdateQuestion)),
+                            ),
                     )
                     .service(
                         web::resource("/{question_id}/answers")
+                            .wrap(PolicyMiddleware(Action::ReadQuestion))
                             .get(question::controller::list_answers_by_question_id),
+                    )
+                    .service(
+                        web::resource("/{question_id}/versions")
+                            .wrap(PolicyMiddleware(Action::ReadQuestion))
+                            .get(question::controller::list_question_versions),
+                    )
+                    .service(
+                        web::resource("/{question_id}/versions/diff")
+                            .wrap(PolicyMiddleware(Action::ReadQuestion))
+                            .get(question::controller::diff_question_versions),
                     ),
             )
             .service(
-                web::scope("/exams")
-                    .wrap(middleware::AuthMiddleware)
+                web::scope("/topics")
+                    .wrap(middleware::VerifiedAuthMiddleware)
                     .service(
                         web::resource("")
-                            .wrap(middleware::RoleMiddleware(vec![TEACHER]))
-                            .post(exam::controller::create_exam),
-                    )
+                            .route(
+                                web::get()
+                                    .to(topic::controller::list_topics)
+                                    .wrap(PolicyMiddleware(Action::ListQuestions)),
+                            )
+                            .route(
+                                web::post()
+                                    .to(topic::controller::create_topic)
+                                    .wrap(PolicyMiddleware(Action::ManageTopics)),
+                            ),
+                    ),
+            )
+            .service(
+                web::scope("/exams")
+                    .wrap(middleware::VerifiedAuthMiddleware)
+                    // The class is in the body, `exam::service::create_exam` checks the policy itself.
+                    .service(web::resource("").post(exam::controller::create_exam))
                     .service(
                         web::resource("/{exam_id}")
-                            .wrap(middleware::RoleMiddleware(vec![TEACHER]))
-                            .get(exam::controller::get_exam_by_id)
-                            .delete(exam::controller::delete_exam)
-                            .patch(exam::controller::update_exam),
+                            .route(
+                                web::get()
+                                    .to(exam::controller::get_exam_by_id)
+                                    .wrap(PolicyMiddleware(Action::ReadExam)),
+                            )
+                            .route(
+                                web::delete()
+                                    .to(exam::controller::delete_exam)
+                                    .wrap(PolicyMiddleware(Action::DeleteExam)),
+                            )
+                            .route(
+                                web::patch()
+                                    .to(exam::controller::update_exam)
+                                    .wrap(PolicyMiddleware(Action::UpdateExam)),
+                            ),
                     )
                     .service(
                         web::resource("/{exam_id}/questions").route(
                             web::post()
                                 .to(exam::controller::update_questions_in_exam)
-                                .wrap(middleware::RoleMiddleware(vec![TEACHER])),
+                                .wrap(PolicyMiddleware(Action::SetExamQuestions)),
                         ),
                     )
+                    .service(
+                        web::resource("/{exam_id}/monitors")
+                            .wrap(PolicyMiddleware(Action::ManageExamMonitors))
+                            .get(exam::controller::list_exam_monitors)
+                            .post(exam::controller::add_exam_monitor),
+                    )
+                    .service(
+                        web::resource("/{exam_id}/monitors/{user_id}")
+                            .wrap(PolicyMiddleware(Action::ManageExamMonitors))
+                            .delete(exam::controller::remove_exam_monitor),
+                    )
                     .service(
                         web::resource("/{exam_id}/questions/students")
-                            .wrap(middleware::RoleMiddleware(vec![STUDENT]))
+                            .wrap(PolicyMiddleware(Action::TakeExam))
                             .get(exam::controller::get_questions_in_exam_as_student),
                     )
                     .service(
                         web::resource("/{exam_id}/questions/teachers")
-                            .wrap(middleware::RoleMiddleware(vec![TEACHER, MONITOR]))
+                            .wrap(PolicyMiddleware(Action::ViewExamQuestions))
                             .get(exam::controller::get_questions_in_exam_as_teacher),
                     )
                     .service(
                         web::resource("/{exam_id}/question/{question_id}/submit")
-                            .wrap(middleware::RoleMiddleware(vec![STUDENT]))
+                            .wrap(PolicyMiddleware(Action::SubmitExamAnswer))
                             .post(exam::controller::submit_answer_to_question_in_exam),
                     )
                     .service(
                         web::resource("/{exam_id}/results")
-                            .wrap(middleware::RoleMiddleware(vec![TEACHER, MONITOR]))
+                            .wrap(PolicyMiddleware(Action::ViewExamResults))
                             .get(exam::controller::get_exam_results_as_teacher),
                     )
                     .service(
                         web::resource("/{exam_id}/results/students")
-                            .wrap(middleware::RoleMiddleware(vec![STUDENT]))
+                            .wrap(PolicyMiddleware(Action::ViewOwnExamResults))
                             .get(exam::controller::get_exam_results_as_student),
+                    )
+                    .service(
+                        web::resource("/{exam_id}/grading")
+                            .wrap(PolicyMiddleware(Action::GradeExamAnswers))
+                            .get(exam::controller::get_grading_queue),
+                    )
+                    .service(
+                        web::resource("/{exam_id}/grading/release")
+                            .wrap(PolicyMiddleware(Action::ReleaseExamGrades))
+                            .post(exam::controller::release_grades),
+                    )
+                    .service(
+                        web::resource("/{exam_id}/grading/{answer_id}")
+                            .wrap(PolicyMiddleware(Action::GradeExamAnswers))
+                            .put(exam::controller::grade_answer),
                     ),
             )
     })
@@ -8,10 +8,10 @@ use actix_web::{
 use futures_util::future::LocalBoxFuture;
 
 use crate::{
+    api_token,
     auth::{self, models::LoggedUser},
     errors::ServiceError,
-    role::enm::RoleEnum,
-    user,
+    policy::{self, Action, Resource, ResourceKind},
 };
 
 // There are two steps in middleware processing.
@@ -35,12 +35,39 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(Authentication { service }))
+        ready(Ok(Authentication {
+            service,
+            enforce_account_policies: false,
+        }))
+    }
+}
+
+// Same as `AuthMiddleware`, but also enforces the account policies: a verified email
+// when `REQUIRE_EMAIL_VERIFICATION` is on, and a second factor for `MFA_REQUIRED_ROLES`.
+pub struct VerifiedAuthMiddleware;
+
+impl<S> Transform<S, ServiceRequest> for VerifiedAuthMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
+    S::Future: 'static,
+{
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = Authentication<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(Authentication {
+            service,
+            enforce_account_policies: true,
+        }))
     }
 }
 
 pub struct Authentication<S> {
     service: S,
+    enforce_account_policies: bool,
 }
 
 impl<S> Service<ServiceRequest> for Authentication<S>
@@ -70,37 +97,31 @@ where
         .last()
         .unwrap();
 
-        let user_id = match auth::crypto::decode_token(jwt) {
-            Ok(user_id) => user_id,
-            Err(e) => {
-                return Box::pin(async {
-                    let res = req.error_response(e);
-                    Ok(res)
-                });
-            }
+        // Personal access tokens (`mp_...`) are opaque, anything else must be a login JWT.
+        let logged_user = if api_token::service::is_api_token(jwt) {
+            api_token::service::authenticate(jwt, self.enforce_account_policies)
+        } else {
+            auth::crypto::decode_token(jwt).and_then(|claims| {
+                auth::service::authenticate(claims, self.enforce_account_policies)
+            })
         };
 
-        let user = match user::service::get_user_with_roles_by_id(user_id) {
-            Ok(user) => user,
-            Err(e) => {
-                return Box::pin(async {
-                    let res = req.error_response(e);
-                    Ok(res)
-                });
+        // Impersonation tokens are for looking around, not for acting on someone's behalf.
+        let logged_user = logged_user.and_then(|logged_user| {
+            if logged_user.impersonator_id.is_some() && !req.method().is_safe() {
+                return Err(ServiceError::Forbidden);
             }
-        };
 
-        match user {
-            Some(user) => {
-                let logged_user = LoggedUser {
-                    id: user.id,
-                    roles: user.roles,
-                };
+            Ok(logged_user)
+        });
+
+        match logged_user {
+            Ok(logged_user) => {
                 req.extensions_mut().insert(logged_user);
             }
-            None => {
+            Err(e) => {
                 return Box::pin(async {
-                    let res = req.error_response(ServiceError::Unauthorized);
+                    let res = req.error_response(e);
                     Ok(res)
                 });
             }
@@ -115,9 +136,11 @@ where
     }
 }
 
-pub struct RoleMiddleware(pub Vec<RoleEnum>);
+/// Guards a route with a policy action. The resource comes from the matching path
+/// parameter (`{class_id}`, `{exam_id}`, ...), so it has to run after authentication.
+pub struct PolicyMiddleware(pub Action);
 
-impl<S> Transform<S, ServiceRequest> for RoleMiddleware
+impl<S> Transform<S, ServiceRequest> for PolicyMiddleware
 where
     S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
     S::Future: 'static,
@@ -125,23 +148,46 @@ where
     type Response = ServiceResponse;
     type Error = Error;
     type InitError = ();
-    type Transform = RoleAuthentication<S>;
+    type Transform = PolicyAuthorization<S>;
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(RoleAuthentication {
+        ready(Ok(PolicyAuthorization {
             service,
-            role: self.0.clone(),
+            action: self.0,
         }))
     }
 }
 
-pub struct RoleAuthentication<S> {
+pub struct PolicyAuthorization<S> {
     service: S,
-    role: Vec<RoleEnum>,
+    action: Action,
 }
 
-impl<S> Service<ServiceRequest> for RoleAuthentication<S>
+fn resource_from_path(req: &ServiceRequest, kind: ResourceKind) -> Result<Resource, ServiceError> {
+    let param = match kind {
+        ResourceKind::None => return Ok(Resource::None),
+        ResourceKind::User => "user_id",
+        ResourceKind::Class => "class_id",
+        ResourceKind::Question => "question_id",
+        ResourceKind::Exam => "exam_id",
+    };
+
+    let id = req
+        .match_info()
+        .get(param)
+        .and_then(|id| id.parse::<i32>().ok())
+        .ok_or(ServiceError::BadRequest(format!("Invalid {}", param)))?;
+
+    Ok(match kind {
+        ResourceKind::User => Resource::User(id),
+        ResourceKind::Class => Resource::Class(id),
+        ResourceKind::Question => Resource::Question(id),
+        _ => Resource::Exam(id),
+    })
+}
+
+impl<S> Service<ServiceRequest> for PolicyAuthorization<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse, Error = Error>,
     S::Future: 'static,
@@ -153,22 +199,18 @@ where
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let has_role;
-        {
-            let ext = req.extensions();
-            let logged_user = ext.get::<LoggedUser>().unwrap();
-
-            has_role = logged_user
-                .roles
-                .iter()
-                .any(|role| self.role.contains(role))
-                || logged_user.roles.contains(&RoleEnum::ADMIN);
-        }
+        let authorized =
+            resource_from_path(&req, self.action.resource_kind()).and_then(|resource| {
+                let ext = req.extensions();
+                let logged_user = ext.get::<LoggedUser>().unwrap();
 
-        let fut: <S as Service<ServiceRequest>>::Future = match has_role {
-            true => self.service.call(req),
-            false => {
-                let res = req.error_response(ServiceError::Forbidden);
+                policy::authorize(logged_user, self.action, resource)
+            });
+
+        let fut: <S as Service<ServiceRequest>>::Future = match authorized {
+            Ok(_) => self.service.call(req),
+            Err(e) => {
+                let res = req.error_response(e);
                 return Box::pin(async { Ok(res) });
             }
         };
@@ -0,0 +1,721 @@
+use crate::{auth::models::LoggedUser, class, errors::ServiceError, exam, question, user};
+
+/// Everything a route or a service may ask permission for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    ListUsers,
+    ImportUsers,
+    SetUserRoles,
+    UnlockUser,
+    SetUserActive,
+    ForcePasswordReset,
+    Impersonate,
+    ManageRoles,
+    ViewAuditLog,
+
+    CreateClass,
+    ReadClass,
+    UpdateClass,
+    DeleteClass,
+    ListEnrolledClasses,
+    ListUnenrolledClasses,
+    ListTaughtClasses,
+    ListClassExams,
+    EnrollInClass,
+    ListClassStaff,
+    ManageClassStaff,
+
+    CreateQuestion,
+    ListQuestions,
+    ReadQuestion,
+    UpdateQuestion,
+    DeleteQuestion,
+    ManageTopics,
+
+    CreateExam,
+    ReadExam,
+    UpdateExam,
+    DeleteExam,
+    SetExamQuestions,
+    ManageExamMonitors,
+    ViewExamQuestions,
+    TakeExam,
+    SubmitExamAnswer,
+    ViewExamResults,
+    ViewOwnExamResults,
+    GradeExamAnswers,
+    ReleaseExamGrades,
+
+    ManageAccount,
+    ManageApiTokens,
+    ManageMfa,
+}
+
+/// Permission that passes every check, whatever the resource.
+pub const SUPERUSER_PERMISSION: &str = "all";
+
+/// Scopes a personal access token can be given.
+pub const SCOPES: &[&str] = &[
+    "classes:read",
+    "classes:write",
+    "questions:read",
+    "questions:write",
+    "exams:read",
+    "exams:write",
+    "answers:write",
+    "results:read",
+    "results:write",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceKind {
+    None,
+    User,
+    Class,
+    Question,
+    Exam,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    None,
+    User(i32),
+    Class(i32),
+    Question(i32),
+    Exam(i32),
+}
+
+/// How a user relates to a resource, beyond the permissions they have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Relation {
+    /// Owns or co-teaches the class, or the class of the exam.
+    ClassTeacher,
+    /// Owns the class.
+    ClassOwner,
+    /// Is enrolled in the class, or in the class of the exam.
+    EnrolledStudent,
+    /// Was assigned to the exam or to its class, or to the class or some exam of it.
+    AssignedMonitor,
+    /// Wrote the question.
+    QuestionAuthor,
+    /// Wrote the question or finds it in the shared or public bank.
+    QuestionReader,
+    /// Teaches a class the user is enrolled in, and the user is nothing more than a student.
+    StudentTeacher,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grant {
+    Authenticated,
+    Permission(&'static str),
+    PermissionWith(&'static str, Relation),
+}
+
+use Grant::*;
+use Relation::*;
+
+impl Action {
+    /// Kind of resource the action applies to, and so the path parameter that holds its id.
+    pub fn resource_kind(&self) -> ResourceKind {
+        match self {
+            Action::SetUserRoles
+            | Action::UnlockUser
+            | Action::SetUserActive
+            | Action::ForcePasswordReset
+            | Action::Impersonate => ResourceKind::User,
+
+            Action::ReadClass
+            | Action::UpdateClass
+            | Action::DeleteClass
+            | Action::ListClassExams
+            | Action::EnrollInClass
+            | Action::ListClassStaff
+            | Action::ManageClassStaff
+            | Action::CreateExam => ResourceKind::Class,
+
+            Action::ReadQuestion | Action::UpdateQuestion | Action::DeleteQuestion => {
+                ResourceKind::Question
+            }
+
+            Action::ReadExam
+            | Action::UpdateExam
+            | Action::DeleteExam
+            | Action::SetExamQuestions
+            | Action::ManageExamMonitors
+            | Action::ViewExamQuestions
+            | Action::TakeExam
+            | Action::SubmitExamAnswer
+            | Action::ViewExamResults
+            | Action::ViewOwnExamResults
+            | Action::GradeExamAnswers
+            | Action::ReleaseExamGrades => ResourceKind::Exam,
+
+            Action::ListUsers
+            | Action::ImportUsers
+            | Action::ManageRoles
+            | Action::ViewAuditLog
+            | Action::CreateClass
+            | Action::ListEnrolledClasses
+            | Action::ListUnenrolledClasses
+            | Action::ListTaughtClasses
+            | Action::CreateQuestion
+            | Action::ListQuestions
+            | Action::ManageTopics
+            | Action::ManageAccount
+            | Action::ManageApiTokens
+            | Action::ManageMfa => ResourceKind::None,
+        }
+    }
+
+    /// Scope a personal access token needs for the action. Actions without one can only
+    /// be done from a login session.
+    pub fn scope(&self) -> Option<&'static str> {
+        match self {
+            Action::ListUsers
+            | Action::ImportUsers
+            | Action::SetUserRoles
+            | Action::UnlockUser
+            | Action::SetUserActive
+            | Action::ForcePasswordReset
+            | Action::Impersonate
+            | Action::ManageRoles
+            | Action::ViewAuditLog
+            | Action::ManageAccount
+            | Action::ManageApiTokens
+            | Action::ManageMfa => None,
+
+            Action::ReadClass
+            | Action::ListEnrolledClasses
+            | Action::ListUnenrolledClasses
+            | Action::ListTaughtClasses
+            | Action::ListClassStaff => Some("classes:read"),
+            Action::CreateClass
+            | Action::UpdateClass
+            | Action::DeleteClass
+            | Action::EnrollInClass
+            | Action::ManageClassStaff => Some("classes:write"),
+
+            Action::ListQuestions | Action::ReadQuestion => Some("questions:read"),
+            Action::CreateQuestion
+            | Action::UpdateQuestion
+            | Action::DeleteQuestion
+            | Action::ManageTopics => Some("questions:write"),
+
+            Action::ListClassExams
+            | Action::ReadExam
+            | Action::ViewExamQuestions
+            | Action::TakeExam => Some("exams:read"),
+            Action::CreateExam
+            | Action::UpdateExam
+            | Action::DeleteExam
+            | Action::SetExamQuestions
+            | Action::ManageExamMonitors => Some("exams:write"),
+
+            Action::SubmitExamAnswer => Some("answers:write"),
+            Action::ViewExamResults | Action::ViewOwnExamResults => Some("results:read"),
+            Action::GradeExamAnswers | Action::ReleaseExamGrades => Some("results:write"),
+        }
+    }
+
+    /// Who may perform the action, on top of users with the superuser permission.
+    pub fn grants(&self) -> &'static [Grant] {
+        match self {
+            Action::ListUsers
+            | Action::ImportUsers
+            | Action::SetUserRoles
+            | Action::UnlockUser
+            | Action::SetUserActive
+            | Action::ForcePasswordReset => &[Permission("users:manage")],
+            Action::Impersonate => &[
+                Permission("users:manage"),
+                PermissionWith("classes:teach", StudentTeacher),
+            ],
+            Action::ManageRoles => &[Permission("roles:manage")],
+            Action::ViewAuditLog => &[Permission("audit:read")],
+
+            Action::CreateClass | Action::ListTaughtClasses => &[Permission("classes:teach")],
+            Action::ReadClass => &[Authenticated],
+            Action::UpdateClass => &[PermissionWith("classes:teach", ClassTeacher)],
+            Action::DeleteClass | Action::ManageClassStaff => {
+                &[PermissionWith("classes:teach", ClassOwner)]
+            }
+            Action::ListClassStaff => &[
+                PermissionWith("classes:teach", ClassTeacher),
+                PermissionWith("exams:monitor", AssignedMonitor),
+            ],
+            Action::ListEnrolledClasses | Action::ListUnenrolledClasses | Action::EnrollInClass => {
+                &[Permission("classes:attend")]
+            }
+            Action::ListClassExams => &[
+                PermissionWith("classes:teach", ClassTeacher),
+                PermissionWith("classes:attend", EnrolledStudent),
+                PermissionWith("exams:monitor", AssignedMonitor),
+            ],
+
+            Action::CreateQuestion | Action::ListQuestions | Action::ManageTopics => {
+                &[Permission("questions:manage")]
+            }
+            Action::ReadQuestion => &[PermissionWith("questions:manage", QuestionReader)],
+            Action::UpdateQuestion | Action::DeleteQuestion => {
+                &[PermissionWith("questions:manage", QuestionAuthor)]
+            }
+
+            Action::CreateExam
+            | Action::UpdateExam
+            | Action::DeleteExam
+            | Action::SetExamQuestions
+            | Action::ManageExamMonitors
+            | Action::ReleaseExamGrades => &[PermissionWith("exams:manage", ClassTeacher)],
+            Action::ReadExam
+            | Action::ViewExamQuestions
+            | Action::ViewExamResults
+            | Action::GradeExamAnswers => &[
+                PermissionWith("exams:manage", ClassTeacher),
+                PermissionWith("exams:monitor", AssignedMonitor),
+            ],
+            Action::TakeExam | Action::SubmitExamAnswer | Action::ViewOwnExamResults => {
+                &[PermissionWith("exams:take", EnrolledStudent)]
+            }
+
+            Action::ManageAccount | Action::ManageApiTokens | Action::ManageMfa => &[Authenticated],
+        }
+    }
+}
+
+pub trait Relations {
+    fn has_relation(
+        &self,
+        user_id: i32,
+        relation: Relation,
+        resource: Resource,
+    ) -> Result<bool, ServiceError>;
+}
+
+/// Looks relations up in the database.
+pub struct DbRelations;
+
+impl Relations for DbRelations {
+    fn has_relation(
+        &self,
+        user_id: i32,
+        relation: Relation,
+        resource: Resource,
+    ) -> Result<bool, ServiceError> {
+        let class_id = match resource {
+            Resource::Class(class_id) => class_id,
+            Resource::Exam(exam_id) => match exam::service::get_exam_by_id(exam_id)? {
+                Some(exam) => exam.class_id,
+                // Same answer as for an exam the user can't touch, so ids can't be probed.
+                None => return Ok(false),
+            },
+            Resource::Question(question_id) if relation == QuestionAuthor => {
+                return question::service::is_question_author(user_id, question_id);
+            }
+            Resource::Question(question_id) if relation == QuestionReader => {
+                return question::service::can_read_question(user_id, question_id);
+            }
+            Resource::User(student_id) if relation == StudentTeacher => {
+                return Ok(class::service::teaches_student(user_id, student_id)?
+                    && user::service::is_plain_student(student_id)?);
+            }
+            _ => return Ok(false),
+        };
+
+        match (relation, resource) {
+            (ClassTeacher, _) => class::service::is_class_teacher(user_id, class_id),
+            (ClassOwner, _) => class::service::is_class_owner(user_id, class_id),
+            (EnrolledStudent, _) => class::service::is_student_enrolled(class_id, user_id),
+            (AssignedMonitor, _) if class::service::is_class_staff_monitor(user_id, class_id)? => {
+                Ok(true)
+            }
+            (AssignedMonitor, Resource::Exam(exam_id)) => {
+                exam::service::is_exam_monitor(exam_id, user_id)
+            }
+            (AssignedMonitor, _) => exam::service::is_class_monitor(class_id, user_id),
+            (QuestionAuthor, _) | (QuestionReader, _) | (StudentTeacher, _) => Ok(false),
+        }
+    }
+}
+
+pub fn authorize_with(
+    relations: &dyn Relations,
+    user: &LoggedUser,
+    action: Action,
+    resource: Resource,
+) -> Result<(), ServiceError> {
+    if let Some(scopes) = &user.scopes {
+        match action.scope() {
+            Some(scope) if scopes.iter().any(|s| s == scope) => (),
+            _ => return Err(ServiceError::Forbidden),
+        }
+    }
+
+    if user.has_permission(SUPERUSER_PERMISSION) {
+        return Ok(());
+    }
+
+    for grant in action.grants() {
+        let allowed = match *grant {
+            Authenticated => true,
+            Permission(permission) => user.has_permission(permission),
+            PermissionWith(permission, relation) => {
+                user.has_permission(permission)
+                    && relations.has_relation(user.id, relation, resource)?
+            }
+        };
+
+        if allowed {
+            return Ok(());
+        }
+    }
+
+    Err(ServiceError::Forbidden)
+}
+
+pub fn authorize(
+    user: &LoggedUser,
+    action: Action,
+    resource: Resource,
+) -> Result<(), ServiceError> {
+    authorize_with(&DbRelations, user, action, resource)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const ADMIN_ID: i32 = 1;
+    const OWNER_ID: i32 = 2;
+    const OTHER_TEACHER_ID: i32 = 3;
+    const ENROLLED_ID: i32 = 4;
+    const OTHER_STUDENT_ID: i32 = 5;
+    const MONITOR_ID: i32 = 6;
+    const OTHER_MONITOR_ID: i32 = 7;
+
+    // As seeded in the `permissions` table.
+    const PERMISSIONS: &[&str] = &[
+        SUPERUSER_PERMISSION,
+        "users:manage",
+        "roles:manage",
+        "classes:teach",
+        "classes:attend",
+        "questions:manage",
+        "exams:manage",
+        "exams:monitor",
+        "exams:take",
+        "audit:read",
+    ];
+
+    const CLASS_ID: i32 = 10;
+    const EXAM_ID: i32 = 20;
+    const QUESTION_ID: i32 = 30;
+    const USER_ID: i32 = 40;
+
+    struct FakeRelations(HashSet<(i32, Relation)>);
+
+    impl Relations for FakeRelations {
+        fn has_relation(
+            &self,
+            user_id: i32,
+            relation: Relation,
+            _resource: Resource,
+        ) -> Result<bool, ServiceError> {
+            Ok(self.0.contains(&(user_id, relation)))
+        }
+    }
+
+    fn relations() -> FakeRelations {
+        FakeRelations(HashSet::from([
+            (OWNER_ID, ClassTeacher),
+            (OWNER_ID, ClassOwner),
+            (OWNER_ID, QuestionAuthor),
+            (OWNER_ID, QuestionReader),
+            (OWNER_ID, StudentTeacher),
+            (ENROLLED_ID, EnrolledStudent),
+            (MONITOR_ID, AssignedMonitor),
+        ]))
+    }
+
+    // The built-in roles, as seeded.
+    fn user(id: i32) -> LoggedUser {
+        let (role, permissions): (&str, &[&str]) = match id {
+            ADMIN_ID => ("ADMIN", &["all"]),
+            OWNER_ID | OTHER_TEACHER_ID => (
+                "TEACHER",
+                &["classes:teach", "questions:manage", "exams:manage"],
+            ),
+            ENROLLED_ID | OTHER_STUDENT_ID => ("STUDENT", &["classes:attend", "exams:take"]),
+            _ => ("MONITOR", &["exams:monitor"]),
+        };
+
+        LoggedUser {
+            id,
+            roles: vec![role.to_string()],
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            jti: String::new(),
+            scopes: None,
+            impersonator_id: None,
+        }
+    }
+
+    fn resource(kind: ResourceKind) -> Resource {
+        match kind {
+            ResourceKind::None => Resource::None,
+            ResourceKind::User => Resource::User(USER_ID),
+            ResourceKind::Class => Resource::Class(CLASS_ID),
+            ResourceKind::Question => Resource::Question(QUESTION_ID),
+            ResourceKind::Exam => Resource::Exam(EXAM_ID),
+        }
+    }
+
+    // Every route of `main.rs` guarded by a policy, with the users allowed through.
+    // Columns: admin, class owner, other teacher, enrolled student, other student,
+    // assigned monitor, other monitor.
+    #[rustfmt::skip]
+    const ROUTES: &[(&str, Action, [bool; 7])] = &[
+        ("PATCH  /users/{user_id}/roles",                     Action::SetUserRoles,          [true, false, false, false, false, false, false]),
+        ("POST   /users/{user_id}/unlock",                    Action::UnlockUser,            [true, false, false, false, false, false, false]),
+        ("GET    /admin/users",                               Action::ListUsers,             [true, false, false, false, false, false, false]),
+        ("POST   /admin/users/import",                        Action::ImportUsers,           [true, false, false, false, false, false, false]),
+        ("POST   /admin/users/{user_id}/deactivate",          Action::SetUserActive,         [true, false, false, false, false, false, false]),
+        ("POST   /admin/users/{user_id}/reactivate",          Action::SetUserActive,         [true, false, false, false, false, false, false]),
+        ("POST   /admin/users/{user_id}/password-reset",      Action::ForcePasswordReset,    [true, false, false, false, false, false, false]),
+        ("POST   /users/{user_id}/impersonate",               Action::Impersonate,           [true, true,  false, false, false, false, false]),
+        ("GET    /admin/roles",                               Action::ManageRoles,           [true, false, false, false, false, false, false]),
+        ("POST   /admin/roles",                               Action::ManageRoles,           [true, false, false, false, false, false, false]),
+        ("PATCH  /admin/roles/{role_name}",                   Action::ManageRoles,           [true, false, false, false, false, false, false]),
+        ("DELETE /admin/roles/{role_name}",                   Action::ManageRoles,           [true, false, false, false, false, false, false]),
+        ("GET    /admin/permissions",                         Action::ManageRoles,           [true, false, false, false, false, false, false]),
+        ("GET    /admin/audit-events",                        Action::ViewAuditLog,          [true, false, false, false, false, false, false]),
+        ("GET    /admin/audit-events/export",                 Action::ViewAuditLog,          [true, false, false, false, false, false, false]),
+        ("POST   /classes",                                   Action::CreateClass,           [true, true,  true,  false, false, false, false]),
+        ("GET    /classes/students/enrolled",                 Action::ListEnrolledClasses,   [true, false, false, true,  true,  false, false]),
+        ("GET    /classes/students/unenrolled",               Action::ListUnenrolledClasses, [true, false, false, true,  true,  false, false]),
+        ("GET    /classes/teachers",                          Action::ListTaughtClasses,     [true, true,  true,  false, false, false, false]),
+        ("GET    /classes/{class_id}",                        Action::ReadClass,             [true, true,  true,  true,  true,  true,  true ]),
+        ("PATCH  /classes/{class_id}",                        Action::UpdateClass,           [true, true,  false, false, false, false, false]),
+        ("DELETE /classes/{class_id}",                        Action::DeleteClass,           [true, true,  false, false, false, false, false]),
+        ("GET    /classes/{class_id}/exams",                  Action::ListClassExams,        [true, true,  false, true,  false, true,  false]),
+        ("POST   /classes/{class_id}/enroll",                 Action::EnrollInClass,         [true, false, false, true,  true,  false, false]),
+        ("GET    /classes/{class_id}/staff",                  Action::ListClassStaff,        [true, true,  false, false, false, true,  false]),
+        ("POST   /classes/{class_id}/staff",                  Action::ManageClassStaff,      [true, true,  false, false, false, false, false]),
+        ("DELETE /classes/{class_id}/staff/{user_id}",        Action::ManageClassStaff,      [true, true,  false, false, false, false, false]),
+        ("POST   /classes/{class_id}/transfer",               Action::ManageClassStaff,      [true, true,  false, false, false, false, false]),
+        ("POST   /questions",                                 Action::CreateQuestion,        [true, true,  true,  false, false, false, false]),
+        ("GET    /questions",                                 Action::ListQuestions,         [true, true,  true,  false, false, false, false]),
+        ("GET    /questions/facets",                          Action::ListQuestions,         [true, true,  true,  false, false, false, false]),
+        ("GET    /questions/{question_id}",                   Action::ReadQuestion,          [true, true,  false, false, false, false, false]),
+        ("PATCH  /questions/{question_id}",                   Action::UpdateQuestion,        [true, true,  false, false, false, false, false]),
+        ("DELETE /questions/{question_id}",                   Action::DeleteQuestion,        [true, true,  false, false, false, false, false]),
+        ("GET    /questions/{question_id}/answers",           Action::ReadQuestion,          [true, true,  false, false, false, false, false]),
+        ("GET    /questions/{question_id}/versions",          Action::ReadQuestion,          [true, true,  false, false, false, false, false]),
+        ("GET    /questions/{question_id}/versions/diff",     Action::ReadQuestion,          [true, true,  false, false, false, false, false]),
+        ("GET    /topics",                                    Action::ListQuestions,         [true, true,  true,  false, false, false, false]),
+        ("POST   /topics",                                    Action::ManageTopics,          [true, true,  true,  false, false, false, false]),
+        ("POST   /exams (class in body)",                     Action::CreateExam,            [true, true,  false, false, false, false, false]),
+        ("GET    /exams/{exam_id}",                           Action::ReadExam,              [true, true,  false, false, false, true,  false]),
+        ("PATCH  /exams/{exam_id}",                           Action::UpdateExam,            [true, true,  false, false, false, false, false]),
+        ("DELETE /exams/{exam_id}",                           Action::DeleteExam,            [true, true,  false, false, false, false, false]),
+        ("POST   /exams/{exam_id}/questions",                 Action::SetExamQuestions,      [true, true,  false, false, false, false, false]),
+        ("GET    /exams/{exam_id}/monitors",                  Action::ManageExamMonitors,    [true, true,  false, false, false, false, false]),
+        ("POST   /exams/{exam_id}/monitors",                  Action::ManageExamMonitors,    [true, true,  false, false, false, false, false]),
+        ("DELETE /exams/{exam_id}/monitors/{user_id}",        Action::ManageExamMonitors,    [true, true,  false, false, false, false, false]),
+        ("GET    /exams/{exam_id}/questions/students",        Action::TakeExam,              [true, false, false, true,  false, false, false]),
+        ("GET    /exams/{exam_id}/questions/teachers",        Action::ViewExamQuestions,     [true, true,  false, false, false, true,  false]),
+        ("POST   /exams/{exam_id}/question/{question_id}/submit", Action::SubmitExamAnswer,  [true, false, false, true,  false, false, false]),
+        ("GET    /exams/{exam_id}/results",                   Action::ViewExamResults,       [true, true,  false, false, false, true,  false]),
+        ("GET    /exams/{exam_id}/results/students",          Action::ViewOwnExamResults,    [true, false, false, true,  false, false, false]),
+        ("GET    /exams/{exam_id}/grading",                   Action::GradeExamAnswers,      [true, true,  false, false, false, true,  false]),
+        ("PUT    /exams/{exam_id}/grading/{answer_id}",       Action::GradeExamAnswers,      [true, true,  false, false, false, true,  false]),
+        ("POST   /exams/{exam_id}/grading/release",           Action::ReleaseExamGrades,     [true, true,  false, false, false, false, false]),
+        ("GET    /users/me",                                  Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("PATCH  /users/me",                                  Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("DELETE /users/me",                                  Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /users/me/password",                         Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /users/verify/resend",                       Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /avatars",                                   Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("DELETE /avatars",                                   Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /avatars/upload",                            Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /auth/oidc/{provider}/link",                 Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /auth/logout",                               Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /auth/logout-all",                           Action::ManageAccount,         [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /tokens",                                    Action::ManageApiTokens,       [true, true,  true,  true,  true,  true,  true ]),
+        ("GET    /tokens",                                    Action::ManageApiTokens,       [true, true,  true,  true,  true,  true,  true ]),
+        ("DELETE /tokens/{token_id}",                         Action::ManageApiTokens,       [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /auth/mfa/totp",                             Action::ManageMfa,             [true, true,  true,  true,  true,  true,  true ]),
+        ("DELETE /auth/mfa/totp",                             Action::ManageMfa,             [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /auth/mfa/totp/confirm",                     Action::ManageMfa,             [true, true,  true,  true,  true,  true,  true ]),
+        ("POST   /auth/mfa/recovery-codes",                   Action::ManageMfa,             [true, true,  true,  true,  true,  true,  true ]),
+    ];
+
+    #[test]
+    fn routes_allow_exactly_the_expected_users() {
+        let relations = relations();
+        let users = [
+            ADMIN_ID,
+            OWNER_ID,
+            OTHER_TEACHER_ID,
+            ENROLLED_ID,
+            OTHER_STUDENT_ID,
+            MONITOR_ID,
+            OTHER_MONITOR_ID,
+        ];
+
+        for (route, action, expected) in ROUTES {
+            for (user_id, allowed) in users.iter().zip(expected) {
+                let result = authorize_with(
+                    &relations,
+                    &user(*user_id),
+                    *action,
+                    resource(action.resource_kind()),
+                );
+
+                assert_eq!(
+                    result.is_ok(),
+                    *allowed,
+                    "{} for user {} should be {}",
+                    route,
+                    user_id,
+                    if *allowed { "allowed" } else { "forbidden" }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn relations_are_only_checked_with_the_matching_permission() {
+        // A student can't pass a rule for teachers, even if they somehow own the class.
+        let relations = FakeRelations(HashSet::from([(ENROLLED_ID, ClassTeacher)]));
+
+        let result = authorize_with(
+            &relations,
+            &user(ENROLLED_ID),
+            Action::UpdateClass,
+            Resource::Class(CLASS_ID),
+        );
+
+        assert!(matches!(result, Err(ServiceError::Forbidden)));
+    }
+
+    #[test]
+    fn co_teachers_teach_but_only_owners_manage_the_class() {
+        let relations = FakeRelations(HashSet::from([(OTHER_TEACHER_ID, ClassTeacher)]));
+        let co_teacher = user(OTHER_TEACHER_ID);
+
+        let cases = [
+            (Action::UpdateClass, Resource::Class(CLASS_ID), true),
+            (Action::ListClassStaff, Resource::Class(CLASS_ID), true),
+            (Action::UpdateExam, Resource::Exam(EXAM_ID), true),
+            (Action::ViewExamResults, Resource::Exam(EXAM_ID), true),
+            (Action::DeleteClass, Resource::Class(CLASS_ID), false),
+            (Action::ManageClassStaff, Resource::Class(CLASS_ID), false),
+        ];
+
+        for (action, resource, allowed) in cases {
+            let result = authorize_with(&relations, &co_teacher, action, resource);
+            assert_eq!(result.is_ok(), allowed, "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn shared_questions_can_be_read_but_only_edited_by_their_author() {
+        let relations = FakeRelations(HashSet::from([(OTHER_TEACHER_ID, QuestionReader)]));
+        let colleague = user(OTHER_TEACHER_ID);
+        let question = Resource::Question(QUESTION_ID);
+
+        assert!(authorize_with(&relations, &colleague, Action::ReadQuestion, question).is_ok());
+        assert!(authorize_with(&relations, &colleague, Action::UpdateQuestion, question).is_err());
+        assert!(authorize_with(&relations, &colleague, Action::DeleteQuestion, question).is_err());
+    }
+
+    #[test]
+    fn api_tokens_are_limited_to_their_scopes() {
+        let relations = relations();
+        let mut owner = user(OWNER_ID);
+        owner.scopes = Some(vec!["questions:write".to_string()]);
+
+        let cases = [
+            (Action::CreateQuestion, Resource::None, true),
+            (
+                Action::UpdateQuestion,
+                Resource::Question(QUESTION_ID),
+                true,
+            ),
+            (Action::ListQuestions, Resource::None, false),
+            (Action::ViewExamResults, Resource::Exam(EXAM_ID), false),
+            (Action::ManageAccount, Resource::None, false),
+            (Action::ManageApiTokens, Resource::None, false),
+            (Action::ManageMfa, Resource::None, false),
+        ];
+
+        for (action, resource, allowed) in cases {
+            let result = authorize_with(&relations, &owner, action, resource);
+            assert_eq!(result.is_ok(), allowed, "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn api_tokens_of_admins_are_limited_to_their_scopes_too() {
+        let mut admin = user(ADMIN_ID);
+        admin.scopes = Some(vec!["results:read".to_string()]);
+
+        let relations = relations();
+
+        assert!(authorize_with(
+            &relations,
+            &admin,
+            Action::ViewExamResults,
+            Resource::Exam(EXAM_ID)
+        )
+        .is_ok());
+        assert!(authorize_with(
+            &relations,
+            &admin,
+            Action::SetUserRoles,
+            Resource::User(USER_ID)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn custom_roles_get_what_their_permissions_allow() {
+        let relations = relations();
+        let mut assistant = user(MONITOR_ID);
+        assistant.roles = vec!["TEACHING_ASSISTANT".to_string()];
+        assistant.permissions = vec!["exams:monitor".to_string(), "questions:manage".to_string()];
+
+        let cases = [
+            (Action::ViewExamResults, Resource::Exam(EXAM_ID), true),
+            (Action::CreateQuestion, Resource::None, true),
+            (Action::UpdateExam, Resource::Exam(EXAM_ID), false),
+            (Action::ListUsers, Resource::None, false),
+        ];
+
+        for (action, resource, allowed) in cases {
+            let result = authorize_with(&relations, &assistant, action, resource);
+            assert_eq!(result.is_ok(), allowed, "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn every_permission_is_known() {
+        for (_, action, _) in ROUTES {
+            for grant in action.grants() {
+                if let Permission(permission) | PermissionWith(permission, _) = grant {
+                    assert!(PERMISSIONS.contains(permission), "{}", permission);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_scope_is_known() {
+        for (_, action, _) in ROUTES {
+            if let Some(scope) = action.scope() {
+                assert!(SCOPES.contains(&scope), "{}", scope);
+            }
+        }
+    }
+}
@@ -1,16 +1,25 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 
-use crate::errors::ServiceError;
+use crate::{auth::models::LoggedUser, errors::ServiceError};
 
-use super::{dto::CreateQuestionInputDto, service};
+use super::{
+    dto::{CreateQuestionInputDto, QuestionVersionDiffQueryDto, QuestionsQueryDto},
+    service,
+};
 
-pub async fn create_question(question: web::Json<CreateQuestionInputDto>) -> impl Responder {
+pub async fn create_question(
+    req: HttpRequest,
+    question: web::Json<CreateQuestionInputDto>,
+) -> impl Responder {
     match question.validate() {
         Err(e) => return HttpResponse::from_error(ServiceError::BadRequest(e)),
         Ok(_) => (),
     }
 
-    match service::create_question(question.into_inner()) {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::create_question(user.id, question.into_inner()) {
         Err(e) => return HttpResponse::from_error(e),
         Ok(question) => question,
     };
@@ -30,8 +39,14 @@ pub async fn get_question_by_id(question_id: web::Path<i32>) -> impl Responder {
     }
 }
 
-pub async fn delete_question_by_id(question_id: web::Path<i32>) -> impl Responder {
-    match service::delete_question_by_id(question_id.into_inner()) {
+pub async fn delete_question_by_id(
+    req: HttpRequest,
+    question_id: web::Path<i32>,
+) -> impl Responder {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::delete_question_by_id(user, question_id.into_inner()) {
         Err(e) => HttpResponse::from_error(e),
         Ok(_) => HttpResponse::NoContent().finish(),
     }
@@ -46,16 +61,36 @@ pub async fn list_answers_by_question_id(question_id: web::Path<i32>) -> impl Re
     HttpResponse::Ok().json(answers).into()
 }
 
-pub async fn list_questions() -> impl Responder {
-    let questions = match service::list_questions() {
+pub async fn list_questions(
+    req: HttpRequest,
+    filter: web::Query<QuestionsQueryDto>,
+) -> impl Responder {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    let page = match service::list_questions(user, filter.into_inner()) {
         Err(e) => return HttpResponse::from_error(e),
-        Ok(questions) => questions,
+        Ok(page) => page,
     };
 
-    HttpResponse::Ok().json(questions).into()
+    HttpResponse::Ok().json(page).into()
+}
+
+pub async fn get_question_facets(
+    req: HttpRequest,
+    filter: web::Query<QuestionsQueryDto>,
+) -> impl Responder {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::get_question_facets(user, filter.into_inner()) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(facets) => HttpResponse::Ok().json(facets),
+    }
 }
 
 pub async fn update_question_by_id(
+    req: HttpRequest,
     question_id: web::Path<i32>,
     question: web::Json<CreateQuestionInputDto>,
 ) -> impl Responder {
@@ -64,10 +99,30 @@ pub async fn update_question_by_id(
         Ok(_) => (),
     }
 
-    match service::update_question(question_id.into_inner(), question.into_inner()) {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::update_question(user, question_id.into_inner(), question.into_inner()) {
         Err(e) => return HttpResponse::from_error(e),
         Ok(question) => question,
     };
 
     HttpResponse::Ok().into()
-}
\ No newline at end of file
+}
+
+pub async fn list_question_versions(question_id: web::Path<i32>) -> impl Responder {
+    match service::list_versions(question_id.into_inner()) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(versions) => HttpResponse::Ok().json(versions),
+    }
+}
+
+pub async fn diff_question_versions(
+    question_id: web::Path<i32>,
+    query: web::Query<QuestionVersionDiffQueryDto>,
+) -> impl Responder {
+    match service::diff_versions(question_id.into_inner(), query.from, query.to) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(diff) => HttpResponse::Ok().json(diff),
+    }
+}
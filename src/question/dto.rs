@@ -1,21 +1,304 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
+use super::models::Question;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CreateQuestionInputDto {
     pub question: String,
+    /// Only choice questions have answers.
+    #[serde(default)]
     pub answers: Vec<CreateAnswerInputDto>,
+    /// Defaults to private on creation, left as is on update.
+    pub visibility: Option<QuestionVisibility>,
+    #[serde(default)]
+    pub question_type: QuestionType,
+    #[serde(default)]
+    pub settings: QuestionSettings,
+    /// Replaced on update, like the fields below.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub topic_id: Option<i32>,
+    pub difficulty: Option<Difficulty>,
+    /// Free-form, must be an object.
+    #[serde(default)]
+    pub metadata: serde_json::Value,
 }
 
-impl CreateQuestionInputDto {
-    pub fn validate(&self) -> Result<(), String> {
-        if self.answers.is_empty() {
-            return Err("Must have at least 1 answer".to_string());
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        }
+    }
+}
+
+pub const MAX_TAGS: usize = 20;
+
+const MAX_TAG_LENGTH: usize = 50;
+
+const MAX_METADATA_BYTES: usize = 10_000;
+
+/// Tags are compared trimmed and lowercase.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionType {
+    #[default]
+    SingleChoice,
+    MultiSelect,
+    TrueFalse,
+    Numeric,
+    ShortText,
+    /// Graded by hand.
+    Essay,
+}
+
+impl QuestionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuestionType::SingleChoice => "single_choice",
+            QuestionType::MultiSelect => "multi_select",
+            QuestionType::TrueFalse => "true_false",
+            QuestionType::Numeric => "numeric",
+            QuestionType::ShortText => "short_text",
+            QuestionType::Essay => "essay",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "single_choice" => Some(QuestionType::SingleChoice),
+            "multi_select" => Some(QuestionType::MultiSelect),
+            "true_false" => Some(QuestionType::TrueFalse),
+            "numeric" => Some(QuestionType::Numeric),
+            "short_text" => Some(QuestionType::ShortText),
+            "essay" => Some(QuestionType::Essay),
+            _ => None,
+        }
+    }
+
+    /// Types whose answers wait for a person to grade them.
+    pub const GRADED_BY_HAND: [QuestionType; 1] = [QuestionType::Essay];
+
+    /// Whether students pick among the question's answers.
+    pub fn has_choices(&self) -> bool {
+        matches!(self, QuestionType::SingleChoice | QuestionType::MultiSelect)
+    }
+
+    pub fn is_graded_by_hand(&self) -> bool {
+        QuestionType::GRADED_BY_HAND.contains(self)
+    }
+}
+
+/// How a multi select question gives credit.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MultiSelectScoring {
+    /// Full credit for exactly the correct answers, nothing otherwise.
+    #[default]
+    AllOrNothing,
+    /// The share of answers rightly picked or left out.
+    PerOption,
+    /// Correct picks minus wrong picks over the number of correct answers, never below 0.
+    RightMinusWrong,
+}
+
+/// What grading needs besides the answers. Which fields apply depends on the question type.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct QuestionSettings {
+    /// Multi select.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scoring: Option<MultiSelectScoring>,
+    /// True or false, the right answer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correct: Option<bool>,
+    /// Numeric, answers at most `tolerance` away from `value` are right.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f64>,
+    /// Short text, the variants counted as right.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accepted_answers: Vec<String>,
+    /// Short text, defaults to false.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub case_sensitive: Option<bool>,
+    /// Short text, trims and collapses runs of whitespace. Defaults to true.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalize_whitespace: Option<bool>,
+    /// Short text and essay, in characters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+}
+
+impl QuestionSettings {
+    /// Keeps only the fields that apply to the question type.
+    pub fn for_type(self, question_type: QuestionType) -> Self {
+        match question_type {
+            QuestionType::SingleChoice => QuestionSettings::default(),
+            QuestionType::MultiSelect => QuestionSettings {
+                scoring: Some(self.scoring.unwrap_or_default()),
+                ..Default::default()
+            },
+            QuestionType::TrueFalse => QuestionSettings {
+                correct: self.correct,
+                ..Default::default()
+            },
+            QuestionType::Numeric => QuestionSettings {
+                value: self.value,
+                tolerance: Some(self.tolerance.unwrap_or(0.0)),
+                ..Default::default()
+            },
+            QuestionType::ShortText => QuestionSettings {
+                accepted_answers: self.accepted_answers,
+                case_sensitive: self.case_sensitive,
+                normalize_whitespace: self.normalize_whitespace,
+                max_length: self.max_length,
+                ..Default::default()
+            },
+            QuestionType::Essay => QuestionSettings {
+                max_length: self.max_length,
+                ..Default::default()
+            },
         }
+    }
+
+    /// What students may see before the exam ends.
+    pub fn without_solution(&self) -> Self {
+        QuestionSettings {
+            scoring: self.scoring,
+            max_length: self.max_length,
+            ..Default::default()
+        }
+    }
+}
 
+/// Who besides the author and admins can see and use a question.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuestionVisibility {
+    Private,
+    /// Staff of the classes the author is on the staff of.
+    Shared,
+    /// Everyone who manages questions.
+    Public,
+}
+
+impl QuestionVisibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuestionVisibility::Private => "private",
+            QuestionVisibility::Shared => "shared",
+            QuestionVisibility::Public => "public",
+        }
+    }
+}
+
+impl CreateQuestionInputDto {
+    pub fn validate(&self) -> Result<(), String> {
         if self.question.is_empty() {
             return Err("Question is required".to_string());
         }
 
+        if !self.question_type.has_choices() && !self.answers.is_empty() {
+            return Err(format!(
+                "{} questions don't have answers",
+                self.question_type.as_str()
+            ));
+        }
+
+        let settings = &self.settings;
+
+        match self.question_type {
+            QuestionType::SingleChoice | QuestionType::MultiSelect => {
+                if self.answers.is_empty() {
+                    return Err("Must have at least 1 answer".to_string());
+                }
+
+                let correct_answers = self.answers.iter().filter(|a| a.is_correct).count();
+                if self.question_type == QuestionType::SingleChoice && correct_answers != 1 {
+                    return Err("Must have exactly 1 correct answer".to_string());
+                }
+                if correct_answers == 0 {
+                    return Err("Must have at least 1 correct answer".to_string());
+                }
+
+                let mut answers: Vec<&str> =
+                    self.answers.iter().map(|a| a.answer.as_str()).collect();
+                answers.sort_unstable();
+                if answers.windows(2).any(|pair| pair[0] == pair[1]) {
+                    return Err("Cannot have two answers with the same value".to_string());
+                }
+            }
+            QuestionType::TrueFalse => {
+                if settings.correct.is_none() {
+                    return Err("The correct value is required".to_string());
+                }
+            }
+            QuestionType::Numeric => {
+                if !settings.value.is_some_and(f64::is_finite) {
+                    return Err("The expected value is required".to_string());
+                }
+                if settings
+                    .tolerance
+                    .is_some_and(|tolerance| !tolerance.is_finite() || tolerance < 0.0)
+                {
+                    return Err("Tolerance can't be negative".to_string());
+                }
+            }
+            QuestionType::ShortText => {
+                if settings.accepted_answers.is_empty()
+                    || settings
+                        .accepted_answers
+                        .iter()
+                        .any(|answer| answer.trim().is_empty())
+                {
+                    return Err("Must have at least 1 accepted answer, none blank".to_string());
+                }
+            }
+            QuestionType::Essay => (),
+        }
+
+        if settings.max_length == Some(0) {
+            return Err("Max length must be positive".to_string());
+        }
+
+        if self.tags.len() > MAX_TAGS {
+            return Err(format!("Can't have more than {} tags", MAX_TAGS));
+        }
+
+        for tag in self.tags.iter().map(|tag| normalize_tag(tag)) {
+            if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH || tag.contains(',') {
+                return Err(format!(
+                    "Tags must have 1 to {} characters and no commas",
+                    MAX_TAG_LENGTH
+                ));
+            }
+        }
+
+        if !(self.metadata.is_null() || self.metadata.is_object()) {
+            return Err("Metadata must be an object".to_string());
+        }
+
+        if self.metadata.to_string().len() > MAX_METADATA_BYTES {
+            return Err("Metadata is too large".to_string());
+        }
+
         Ok(())
     }
 }
@@ -29,14 +312,163 @@ pub struct CreateAnswerInputDto {
 #[derive(Serialize)]
 pub struct QuestionWithAnswersDto {
     pub id: i32,
+    pub version: i32,
     pub question: String,
+    pub question_type: QuestionType,
+    pub settings: QuestionSettings,
     pub answers: Vec<AnswerDto>,
 }
 
 #[derive(Serialize)]
+pub struct QuestionVersionDto {
+    pub version: i32,
+    pub question: String,
+    pub question_type: QuestionType,
+    pub settings: QuestionSettings,
+    pub answers: Vec<AnswerDto>,
+    pub created_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Deserialize)]
+pub struct QuestionVersionDiffQueryDto {
+    pub from: i32,
+    pub to: i32,
+}
+
+/// What changed from one version to another. Answers are compared by their text and
+/// correctness, so an answer that became correct is both removed and added.
+#[derive(Serialize)]
+pub struct QuestionVersionDiffDto {
+    pub from: i32,
+    pub to: i32,
+    /// The changed fields as they were in `from`.
+    pub before: serde_json::Value,
+    /// The changed fields as they are in `to`.
+    pub after: serde_json::Value,
+    pub removed_answers: Vec<AnswerDto>,
+    pub added_answers: Vec<AnswerDto>,
+}
+
+#[derive(Serialize, Clone)]
 pub struct AnswerDto {
     pub id: i32,
     pub answer: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_correct: Option<bool>,
 }
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum QuestionSort {
+    #[default]
+    Newest,
+    Oldest,
+    /// By question text.
+    Question,
+    /// Best matches of `q` first.
+    Relevance,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QuestionsQueryDto {
+    /// Full-text search over the question, its tags and its answers, in web search syntax.
+    pub q: Option<String>,
+    /// Comma separated, questions must have every one of them.
+    pub tags: Option<String>,
+    /// Questions of the topic or of any topic under it.
+    pub topic_id: Option<i32>,
+    pub difficulty: Option<Difficulty>,
+    pub author_id: Option<i32>,
+    #[serde(default)]
+    pub sort: QuestionSort,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl QuestionsQueryDto {
+    pub const DEFAULT_LIMIT: i64 = 50;
+    pub const MAX_LIMIT: i64 = 200;
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self
+            .limit
+            .is_some_and(|limit| !(1..=Self::MAX_LIMIT).contains(&limit))
+        {
+            return Err(format!("Limit must be between 1 and {}", Self::MAX_LIMIT));
+        }
+
+        if let Some(cursor) = &self.cursor {
+            QuestionCursor::decode(cursor)?;
+        }
+
+        if self.sort == QuestionSort::Relevance && self.search().is_none() {
+            return Err("Sorting by relevance needs a search".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn tag_list(&self) -> Vec<String> {
+        self.tags
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(normalize_tag)
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    pub fn search(&self) -> Option<&str> {
+        self.q.as_deref().map(str::trim).filter(|q| !q.is_empty())
+    }
+}
+
+/// Where a page ended, in the order of the chosen sort. Handed out as an opaque string.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct QuestionCursor {
+    pub id: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank: Option<f32>,
+}
+
+impl QuestionCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, String> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or("Invalid cursor".to_string())
+    }
+}
+
+/// A page of questions. `next_cursor` is passed back as `cursor` to get the next page and
+/// is missing on the last one.
+#[derive(Serialize)]
+pub struct QuestionPageOutputDto {
+    pub questions: Vec<Question>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FacetCountDto<T> {
+    pub value: T,
+    pub count: usize,
+}
+
+/// How many of the questions matching the filters fall under each value, most common
+/// first. Topics count the questions filed directly under them.
+#[derive(Serialize, Debug, Default)]
+pub struct QuestionFacetsDto {
+    pub total: usize,
+    pub tags: Vec<FacetCountDto<String>>,
+    pub topics: Vec<FacetCountDto<i32>>,
+    pub difficulties: Vec<FacetCountDto<String>>,
+    pub authors: Vec<FacetCountDto<i32>>,
+}
@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    dto::{MultiSelectScoring, QuestionSettings, QuestionType},
+    models::Answer,
+};
+
+/// Longest text response when the question doesn't set its own limit.
+pub const DEFAULT_MAX_TEXT_LENGTH: usize = 20_000;
+
+/// What a student answered, tagged by the field matching the question type.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    AnswerId(i32),
+    AnswerIds(Vec<i32>),
+    Boolean(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl Response {
+    /// Checks the response fits the question, returning it with answer ids deduplicated.
+    pub fn check(
+        self,
+        question_type: QuestionType,
+        settings: &QuestionSettings,
+        answers: &[Answer],
+    ) -> Result<Response, String> {
+        let known = |answer_id: &i32| answers.iter().any(|answer| answer.id == *answer_id);
+
+        match (question_type, self) {
+            (QuestionType::SingleChoice, Response::AnswerId(answer_id)) => {
+                if !known(&answer_id) {
+                    return Err("Answer not found".to_string());
+                }
+                Ok(Response::AnswerId(answer_id))
+            }
+            (QuestionType::MultiSelect, Response::AnswerIds(mut answer_ids)) => {
+                if !answer_ids.iter().all(known) {
+                    return Err("Answer not found".to_string());
+                }
+                answer_ids.sort_unstable();
+                answer_ids.dedup();
+                Ok(Response::AnswerIds(answer_ids))
+            }
+            (QuestionType::TrueFalse, Response::Boolean(value)) => Ok(Response::Boolean(value)),
+            (QuestionType::Numeric, Response::Number(value)) => {
+                if !value.is_finite() {
+                    return Err("Number must be finite".to_string());
+                }
+                Ok(Response::Number(value))
+            }
+            (QuestionType::ShortText | QuestionType::Essay, Response::Text(text)) => {
+                let max_length = settings.max_length.unwrap_or(DEFAULT_MAX_TEXT_LENGTH);
+                if text.chars().count() > max_length {
+                    return Err(format!(
+                        "Answer can't be longer than {} characters",
+                        max_length
+                    ));
+                }
+                Ok(Response::Text(text))
+            }
+            (question_type, _) => Err(format!(
+                "Wrong kind of answer for a {} question",
+                question_type.as_str()
+            )),
+        }
+    }
+}
+
+/// Folds case and whitespace the way the question asks before comparing short texts.
+pub fn normalize_text(text: &str, settings: &QuestionSettings) -> String {
+    let text = if settings.normalize_whitespace.unwrap_or(true) {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    } else {
+        text.to_string()
+    };
+
+    if settings.case_sensitive.unwrap_or(false) {
+        text
+    } else {
+        text.to_lowercase()
+    }
+}
+
+fn grade_multi_select(scoring: MultiSelectScoring, answers: &[Answer], picked: &[i32]) -> f32 {
+    let picked: HashSet<i32> = picked.iter().copied().collect();
+    let correct = answers.iter().filter(|answer| answer.is_correct).count();
+    let right_picks = answers
+        .iter()
+        .filter(|answer| answer.is_correct && picked.contains(&answer.id))
+        .count();
+    let wrong_picks = answers
+        .iter()
+        .filter(|answer| !answer.is_correct && picked.contains(&answer.id))
+        .count();
+
+    match scoring {
+        MultiSelectScoring::AllOrNothing => {
+            if right_picks == correct && wrong_picks == 0 {
+                1.0
+            } else {
+                0.0
+            }
+        }
+        MultiSelectScoring::PerOption => {
+            let right_calls = answers
+                .iter()
+                .filter(|answer| answer.is_correct == picked.contains(&answer.id))
+                .count();
+            right_calls as f32 / answers.len().max(1) as f32
+        }
+        MultiSelectScoring::RightMinusWrong => {
+            (right_picks as f32 - wrong_picks as f32).max(0.0) / correct.max(1) as f32
+        }
+    }
+}
+
+/// Credit between 0 and 1 for a response, `None` when it takes a person to grade it.
+/// Responses of the wrong kind get nothing.
+pub fn grade(
+    question_type: QuestionType,
+    settings: &QuestionSettings,
+    answers: &[Answer],
+    response: &Response,
+) -> Option<f32> {
+    if question_type.is_graded_by_hand() {
+        return None;
+    }
+
+    let credit = |right: bool| if right { 1.0 } else { 0.0 };
+
+    let score = match (question_type, response) {
+        (QuestionType::SingleChoice, Response::AnswerId(answer_id)) => credit(
+            answers
+                .iter()
+                .any(|answer| answer.id == *answer_id && answer.is_correct),
+        ),
+        (QuestionType::MultiSelect, Response::AnswerIds(answer_ids)) => {
+            grade_multi_select(settings.scoring.unwrap_or_default(), answers, answer_ids)
+        }
+        (QuestionType::TrueFalse, Response::Boolean(value)) => {
+            credit(settings.correct == Some(*value))
+        }
+        (QuestionType::Numeric, Response::Number(value)) => match settings.value {
+            // A little slack on top of the tolerance so 0.1 + 0.2 still counts as 0.3.
+            Some(expected) => credit(
+                (value - expected).abs()
+                    <= settings.tolerance.unwrap_or(0.0) + 1e-9 * expected.abs().max(1.0),
+            ),
+            None => 0.0,
+        },
+        (QuestionType::ShortText, Response::Text(text)) => {
+            let text = normalize_text(text, settings);
+            credit(
+                settings
+                    .accepted_answers
+                    .iter()
+                    .any(|accepted| normalize_text(accepted, settings) == text),
+            )
+        }
+        _ => 0.0,
+    };
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answers(correct: &[bool]) -> Vec<Answer> {
+        correct
+            .iter()
+            .enumerate()
+            .map(|(i, is_correct)| Answer {
+                id: i as i32 + 1,
+                answer: format!("answer {}", i + 1),
+                is_correct: *is_correct,
+                question_id: 1,
+                created_at: chrono::NaiveDateTime::default(),
+                question_version_id: 1,
+            })
+            .collect()
+    }
+
+    fn multi_select(scoring: MultiSelectScoring, picked: &[i32]) -> Option<f32> {
+        let settings = QuestionSettings {
+            scoring: Some(scoring),
+            ..Default::default()
+        };
+
+        // Answers 1 and 2 are correct, 3 and 4 aren't.
+        grade(
+            QuestionType::MultiSelect,
+            &settings,
+            &answers(&[true, true, false, false]),
+            &Response::AnswerIds(picked.to_vec()),
+        )
+    }
+
+    #[test]
+    fn multi_select_partial_credit() {
+        use MultiSelectScoring::*;
+
+        assert_eq!(multi_select(AllOrNothing, &[1, 2]), Some(1.0));
+        assert_eq!(multi_select(AllOrNothing, &[1]), Some(0.0));
+
+        assert_eq!(multi_select(PerOption, &[1]), Some(0.75));
+        assert_eq!(multi_select(PerOption, &[1, 3]), Some(0.5));
+
+        assert_eq!(multi_select(RightMinusWrong, &[1]), Some(0.5));
+        assert_eq!(multi_select(RightMinusWrong, &[1, 2, 3]), Some(0.5));
+        assert_eq!(multi_select(RightMinusWrong, &[1, 3, 4]), Some(0.0));
+    }
+
+    #[test]
+    fn numeric_answers_within_tolerance() {
+        let settings = QuestionSettings {
+            value: Some(9.81),
+            tolerance: Some(0.05),
+            ..Default::default()
+        };
+        let grade = |value| {
+            grade(
+                QuestionType::Numeric,
+                &settings,
+                &[],
+                &Response::Number(value),
+            )
+        };
+
+        assert_eq!(grade(9.8), Some(1.0));
+        assert_eq!(grade(9.86), Some(1.0));
+        assert_eq!(grade(9.9), Some(0.0));
+
+        let exact = QuestionSettings {
+            value: Some(0.3),
+            ..Default::default()
+        };
+        assert_eq!(
+            super::grade(
+                QuestionType::Numeric,
+                &exact,
+                &[],
+                &Response::Number(0.1 + 0.2)
+            ),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn short_text_matches_normalized_variants() {
+        let mut settings = QuestionSettings {
+            accepted_answers: vec!["New York".to_string(), "NYC".to_string()],
+            ..Default::default()
+        };
+        let grade = |settings: &QuestionSettings, text: &str| {
+            grade(
+                QuestionType::ShortText,
+                settings,
+                &[],
+                &Response::Text(text.to_string()),
+            )
+        };
+
+        assert_eq!(grade(&settings, "  new   york "), Some(1.0));
+        assert_eq!(grade(&settings, "nyc"), Some(1.0));
+        assert_eq!(grade(&settings, "Boston"), Some(0.0));
+
+        settings.case_sensitive = Some(true);
+        assert_eq!(grade(&settings, "nyc"), Some(0.0));
+    }
+
+    #[test]
+    fn essays_wait_for_a_person_and_mismatches_get_nothing() {
+        let settings = QuestionSettings {
+            correct: Some(true),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            grade(
+                QuestionType::Essay,
+                &settings,
+                &[],
+                &Response::Text("...".to_string())
+            ),
+            None
+        );
+        assert_eq!(
+            grade(
+                QuestionType::TrueFalse,
+                &settings,
+                &[],
+                &Response::Boolean(true)
+            ),
+            Some(1.0)
+        );
+        assert_eq!(
+            grade(
+                QuestionType::TrueFalse,
+                &settings,
+                &[],
+                &Response::Number(1.0)
+            ),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn responses_must_fit_the_question() {
+        let answers = answers(&[true, false]);
+        let settings = QuestionSettings::default();
+
+        assert_eq!(
+            Response::AnswerIds(vec![2, 1, 2]).check(
+                QuestionType::MultiSelect,
+                &settings,
+                &answers
+            ),
+            Ok(Response::AnswerIds(vec![1, 2]))
+        );
+        assert!(Response::AnswerId(7)
+            .check(QuestionType::SingleChoice, &settings, &answers)
+            .is_err());
+        assert!(Response::Boolean(true)
+            .check(QuestionType::Numeric, &settings, &answers)
+            .is_err());
+        assert!(Response::Number(f64::NAN)
+            .check(QuestionType::Numeric, &settings, &answers)
+            .is_err());
+    }
+}
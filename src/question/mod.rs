@@ -1,5 +1,6 @@
 pub mod controller;
 pub mod dto;
+pub mod grading;
 pub mod models;
 mod repository;
 pub mod service;
@@ -1,19 +1,76 @@
 use diesel::{prelude::Insertable, Queryable};
 use serde::Serialize;
 
-use crate::schema::{answers, questions};
+use crate::schema::{answers, question_versions, questions};
+
+use super::dto::{QuestionSettings, QuestionType};
 
 #[derive(Queryable, Serialize)]
 pub struct Question {
     pub id: i32,
     pub question: String,
     pub created_at: chrono::NaiveDateTime,
+    /// `None` for questions written before authors were recorded, there is nothing to
+    /// backfill it from so they were moved to the public bank, and for those whose author
+    /// was deleted. Either way only admins can change them.
+    pub author_id: Option<i32>,
+    pub visibility: String,
+    pub question_type: String,
+    pub settings: serde_json::Value,
+    /// The latest version, the one the fields above hold.
+    pub version: i32,
+    pub tags: Vec<String>,
+    pub topic_id: Option<i32>,
+    pub difficulty: Option<String>,
+    pub metadata: serde_json::Value,
 }
 
 #[derive(Insertable)]
 #[diesel(table_name = questions)]
 pub struct NewQuestion<'a> {
     pub question: &'a str,
+    pub author_id: i32,
+    pub visibility: &'a str,
+    pub question_type: &'a str,
+    pub settings: serde_json::Value,
+    pub tags: &'a [String],
+    pub topic_id: Option<i32>,
+    pub difficulty: Option<&'a str>,
+    pub metadata: &'a serde_json::Value,
+}
+
+/// A question as it was at some point. Versions and their answers never change.
+#[derive(Queryable, Serialize)]
+pub struct QuestionVersion {
+    pub id: i32,
+    pub question_id: i32,
+    pub version: i32,
+    pub question: String,
+    pub question_type: String,
+    pub settings: serde_json::Value,
+    pub created_by: Option<i32>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl QuestionVersion {
+    pub fn question_type(&self) -> QuestionType {
+        QuestionType::parse(&self.question_type).unwrap_or_default()
+    }
+
+    pub fn settings(&self) -> QuestionSettings {
+        serde_json::from_value(self.settings.clone()).unwrap_or_default()
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = question_versions)]
+pub struct NewQuestionVersion<'a> {
+    pub question_id: i32,
+    pub version: i32,
+    pub question: &'a str,
+    pub question_type: &'a str,
+    pub settings: serde_json::Value,
+    pub created_by: i32,
 }
 
 #[derive(Queryable, Serialize)]
@@ -23,6 +80,7 @@ pub struct Answer {
     pub is_correct: bool,
     pub question_id: i32,
     pub created_at: chrono::NaiveDateTime,
+    pub question_version_id: i32,
 }
 
 #[derive(Insertable)]
@@ -31,4 +89,5 @@ pub struct NewAnswer {
     pub answer: String,
     pub is_correct: bool,
     pub question_id: i32,
+    pub question_version_id: i32,
 }
@@ -1,41 +1,62 @@
 use crate::diesel::OptionalExtension;
 use std::error::Error;
 
-use diesel::{query_dsl::methods::FilterDsl, Connection, ExpressionMethods, RunQueryDsl};
+use diesel::{
+    dsl::{count_star, sql},
+    expression::BoxableExpression,
+    pg::Pg,
+    query_builder::{AstPass, Query, QueryFragment, QueryId},
+    query_dsl::methods::{FilterDsl, LimitDsl, OrderDsl, SelectDsl, ThenOrderDsl},
+    sql_types::{BigInt, Bool, Float, Integer, Nullable, Text},
+    BoolExpressionMethods, Connection, ExpressionMethods, NullableExpressionMethods,
+    PgArrayExpressionMethods, PgConnection, QueryResult, RunQueryDsl,
+};
 
 use crate::{
+    audit::{self, model::NewAuditEvent},
     db::DB_MANAGER,
     errors::ServiceError,
     question::models::Answer,
-    schema::{answers, questions},
+    schema::{answers, class_staff, question_search, question_versions, questions},
 };
 
 use super::{
-    dto::CreateQuestionInputDto,
-    models::{NewAnswer, NewQuestion, Question},
+    dto::{
+        CreateQuestionInputDto, QuestionCursor, QuestionSort, QuestionVisibility, QuestionsQueryDto,
+    },
+    models::{NewAnswer, NewQuestion, NewQuestionVersion, Question, QuestionVersion},
 };
 
-pub fn create_question(new_question: CreateQuestionInputDto) -> Result<(), ServiceError> {
+pub fn create_question(
+    author_id: i32,
+    new_question: CreateQuestionInputDto,
+) -> Result<(), ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
+    let visibility = new_question
+        .visibility
+        .unwrap_or(QuestionVisibility::Private);
+
     let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
         let question = diesel::insert_into(questions::table)
             .values(&NewQuestion {
                 question: &new_question.question,
+                author_id,
+                visibility: visibility.as_str(),
+                question_type: new_question.question_type.as_str(),
+                settings: serde_json::to_value(&new_question.settings)?,
+                tags: &new_question.tags,
+                topic_id: new_question.topic_id,
+                difficulty: new_question
+                    .difficulty
+                    .map(|difficulty| difficulty.as_str()),
+                metadata: &new_question.metadata,
             })
             .returning(questions::all_columns)
             .get_result::<Question>(tx)?;
 
-        for answer in new_question.answers {
-            let _: Answer = diesel::insert_into(answers::table)
-                .values(&NewAnswer {
-                    answer: answer.answer,
-                    is_correct: answer.is_correct,
-                    question_id: question.id,
-                })
-                .returning(answers::all_columns)
-                .get_result(tx)?;
-        }
+        insert_version(tx, author_id, &question, new_question)?;
+        index_question(tx, question.id)?;
 
         Ok(())
     });
@@ -59,49 +80,193 @@ pub fn get_question_by_id(question_id: i32) -> Result<Option<Question>, ServiceE
     Ok(question)
 }
 
-pub fn delete_question_by_id(question_id: i32) -> Result<(), ServiceError> {
-    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+/// Adds the question's current version, as held by its row, with the given answers.
+fn insert_version(
+    tx: &mut PgConnection,
+    actor_id: i32,
+    question: &Question,
+    new_question: CreateQuestionInputDto,
+) -> QueryResult<()> {
+    let version: QuestionVersion = diesel::insert_into(question_versions::table)
+        .values(&NewQuestionVersion {
+            question_id: question.id,
+            version: question.version,
+            question: &question.question,
+            question_type: &question.question_type,
+            settings: question.settings.clone(),
+            created_by: actor_id,
+        })
+        .get_result(tx)?;
 
-    diesel::delete(answers::table.filter(answers::question_id.eq(question_id)))
-        .execute(&mut conn)
-        .map_err(|e| {
-            println!("{:?}", e);
-            ServiceError::InternalServerError
-        })?;
+    for answer in new_question.answers {
+        diesel::insert_into(answers::table)
+            .values(&NewAnswer {
+                answer: answer.answer,
+                is_correct: answer.is_correct,
+                question_id: question.id,
+                question_version_id: version.id,
+            })
+            .execute(tx)?;
+    }
+
+    Ok(())
+}
 
-    diesel::delete(questions::table.filter(questions::id.eq(question_id)))
-        .execute(&mut conn)
-        .map_err(|e| {
-            println!("{:?}", e);
-            ServiceError::InternalServerError
-        })?;
+fn version_answers(tx: &mut PgConnection, question_version_id: i32) -> QueryResult<Vec<Answer>> {
+    answers::table
+        .filter(answers::question_version_id.eq(question_version_id))
+        .order(answers::id)
+        .load(tx)
+}
+
+fn current_answers(tx: &mut PgConnection, question: &Question) -> QueryResult<Vec<Answer>> {
+    answers::table
+        .filter(
+            answers::question_version_id.eq_any(
+                question_versions::table
+                    .filter(question_versions::question_id.eq(question.id))
+                    .filter(question_versions::version.eq(question.version))
+                    .select(question_versions::id),
+            ),
+        )
+        .order(answers::id)
+        .load(tx)
+}
+
+/// Rebuilds the search document of the question from its latest version, weighting the
+/// question over its tags over its answers.
+fn index_question(tx: &mut PgConnection, question_id: i32) -> QueryResult<()> {
+    diesel::sql_query(
+        "INSERT INTO question_search (question_id, document)
+        SELECT questions.id,
+            setweight(to_tsvector('simple', questions.question), 'A')
+                || setweight(to_tsvector('simple', array_to_string(questions.tags, ' ')), 'B')
+                || setweight(to_tsvector('simple', COALESCE(string_agg(answers.answer, ' '), '')), 'C')
+        FROM questions
+        LEFT JOIN question_versions
+            ON question_versions.question_id = questions.id
+            AND question_versions.version = questions.version
+        LEFT JOIN answers ON answers.question_version_id = question_versions.id
+        WHERE questions.id = $1
+        GROUP BY questions.id
+        ON CONFLICT (question_id) DO UPDATE SET document = EXCLUDED.document",
+    )
+    .bind::<Integer, _>(question_id)
+    .execute(tx)?;
 
     Ok(())
 }
 
+/// The question and its answers as recorded in the audit log.
+fn audit_snapshot(tx: &mut PgConnection, question_id: i32) -> QueryResult<serde_json::Value> {
+    let question: Question = questions::table
+        .filter(questions::id.eq(question_id))
+        .first(tx)?;
+    let answers = current_answers(tx, &question)?;
+
+    Ok(serde_json::json!({
+        "version": question.version,
+        "question": question.question,
+        "visibility": question.visibility,
+        "question_type": question.question_type,
+        "settings": question.settings,
+        "tags": question.tags,
+        "topic_id": question.topic_id,
+        "difficulty": question.difficulty,
+        "metadata": question.metadata,
+        "answers": answers
+            .iter()
+            .map(|answer| serde_json::json!({
+                "answer": answer.answer,
+                "is_correct": answer.is_correct,
+            }))
+            .collect::<Vec<_>>(),
+    }))
+}
+
+pub fn delete_question_by_id(actor_id: i32, question_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let before = audit_snapshot(tx, question_id)?;
+
+        diesel::delete(answers::table.filter(answers::question_id.eq(question_id))).execute(tx)?;
+        diesel::delete(
+            question_versions::table.filter(question_versions::question_id.eq(question_id)),
+        )
+        .execute(tx)?;
+        diesel::delete(questions::table.filter(questions::id.eq(question_id))).execute(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "delete",
+                "question",
+                question_id,
+                Some(before),
+                None,
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|e| {
+        log::error!("deleting question {} failed: {:?}", question_id, e);
+        ServiceError::InternalServerError
+    })
+}
+
 pub fn update_question(
+    actor_id: i32,
     question_id: i32,
     new_question: CreateQuestionInputDto,
 ) -> Result<(), ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
     let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
-        diesel::delete(answers::table.filter(answers::question_id.eq(question_id))).execute(tx)?;
+        let before = audit_snapshot(tx, question_id)?;
 
-        diesel::update(questions::table.filter(questions::id.eq(question_id)))
-            .set(questions::question.eq(&new_question.question))
-            .execute(tx)?;
+        // Earlier versions stay as they are, exams and answers may point at them.
+        let question: Question =
+            diesel::update(questions::table.filter(questions::id.eq(question_id)))
+                .set((
+                    questions::question.eq(&new_question.question),
+                    questions::question_type.eq(new_question.question_type.as_str()),
+                    questions::settings.eq(serde_json::to_value(&new_question.settings)?),
+                    questions::version.eq(questions::version + 1),
+                    questions::tags.eq(&new_question.tags),
+                    questions::topic_id.eq(new_question.topic_id),
+                    questions::difficulty.eq(new_question
+                        .difficulty
+                        .map(|difficulty| difficulty.as_str())),
+                    questions::metadata.eq(&new_question.metadata),
+                ))
+                .get_result(tx)?;
 
-        for answer in new_question.answers {
-            diesel::insert_into(answers::table)
-                .values(&NewAnswer {
-                    answer: answer.answer,
-                    is_correct: answer.is_correct,
-                    question_id,
-                })
+        if let Some(visibility) = new_question.visibility {
+            diesel::update(questions::table.filter(questions::id.eq(question_id)))
+                .set(questions::visibility.eq(visibility.as_str()))
                 .execute(tx)?;
         }
 
+        insert_version(tx, actor_id, &question, new_question)?;
+        index_question(tx, question_id)?;
+
+        let after = audit_snapshot(tx, question_id)?;
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "update",
+                "question",
+                question_id,
+                Some(before),
+                Some(after),
+            ),
+        )?;
+
         Ok(())
     });
 
@@ -112,21 +277,336 @@ pub fn update_question(
     Ok(())
 }
 
+/// Answers of the latest version of the question.
 pub fn list_answers_by_question_id(question_id: i32) -> Result<Vec<Answer>, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
-    let answers = answers::table
-        .filter(answers::question_id.eq(question_id))
-        .load::<Answer>(&mut conn)
+
+    let question: Option<Question> = questions::table
+        .filter(questions::id.eq(question_id))
+        .first(&mut conn)
+        .optional()
         .map_err(|_| ServiceError::InternalServerError)?;
 
-    Ok(answers)
+    match question {
+        Some(question) => {
+            current_answers(&mut conn, &question).map_err(|_| ServiceError::InternalServerError)
+        }
+        None => Ok(Vec::new()),
+    }
 }
 
-pub fn list_questions() -> Result<Vec<Question>, ServiceError> {
+pub fn list_versions(
+    question_id: i32,
+) -> Result<Vec<(QuestionVersion, Vec<Answer>)>, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
-    let questions = questions::table
-        .load::<Question>(&mut conn)
+
+    let result: QueryResult<Vec<(QuestionVersion, Vec<Answer>)>> = (|| {
+        let versions: Vec<QuestionVersion> = question_versions::table
+            .filter(question_versions::question_id.eq(question_id))
+            .order(question_versions::version)
+            .load(&mut conn)?;
+
+        versions
+            .into_iter()
+            .map(|version| {
+                let answers = version_answers(&mut conn, version.id)?;
+                Ok((version, answers))
+            })
+            .collect()
+    })();
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn get_version_by_id(
+    question_version_id: i32,
+) -> Result<Option<QuestionVersion>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let question_version = question_versions::table
+        .filter(question_versions::id.eq(question_version_id))
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(question_version)
+}
+
+pub fn get_version(
+    question_id: i32,
+    version: i32,
+) -> Result<Option<(QuestionVersion, Vec<Answer>)>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let question_version: Option<QuestionVersion> = question_versions::table
+        .filter(question_versions::question_id.eq(question_id))
+        .filter(question_versions::version.eq(version))
+        .first(&mut conn)
+        .optional()
         .map_err(|_| ServiceError::InternalServerError)?;
 
-    Ok(questions)
+    match question_version {
+        Some(question_version) => {
+            let answers = version_answers(&mut conn, question_version.id)
+                .map_err(|_| ServiceError::InternalServerError)?;
+            Ok(Some((question_version, answers)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Questions matching the filters. `visible_to` limits them to the ones the user wrote, the
+/// public ones and the ones shared by someone on the staff of a class with them, `None`
+/// searches the whole bank. `topic_ids` replaces the topic of the filter with its subtree.
+fn filtered_questions<'a>(
+    visible_to: Option<i32>,
+    filter: &QuestionsQueryDto,
+    topic_ids: Option<Vec<i32>>,
+) -> questions::BoxedQuery<'a, Pg> {
+    let mut query = diesel::QueryDsl::into_boxed(questions::table);
+
+    if let Some(user_id) = visible_to {
+        let own_staff = diesel::alias!(class_staff as own_staff);
+        let colleague_ids = class_staff::table
+            .filter(
+                class_staff::class_id.eq_any(
+                    own_staff
+                        .filter(own_staff.field(class_staff::user_id).eq(user_id))
+                        .select(own_staff.field(class_staff::class_id)),
+                ),
+            )
+            .select(class_staff::user_id.nullable());
+
+        query = query.filter(
+            questions::author_id
+                .eq(user_id)
+                .or(questions::visibility.eq(QuestionVisibility::Public.as_str()))
+                .or(questions::visibility
+                    .eq(QuestionVisibility::Shared.as_str())
+                    .and(questions::author_id.eq_any(colleague_ids))),
+        );
+    }
+
+    if let Some(q) = filter.search() {
+        query = query.filter(
+            questions::id.eq_any(
+                question_search::table
+                    .filter(
+                        sql::<Bool>("document @@ websearch_to_tsquery('simple', ")
+                            .bind::<Text, _>(q.to_string())
+                            .sql(")"),
+                    )
+                    .select(question_search::question_id),
+            ),
+        );
+    }
+
+    let tags = filter.tag_list();
+    if !tags.is_empty() {
+        query = query.filter(questions::tags.contains(tags));
+    }
+
+    if let Some(topic_ids) = topic_ids {
+        query = query.filter(questions::topic_id.eq_any(topic_ids));
+    }
+
+    if let Some(difficulty) = filter.difficulty {
+        query = query.filter(questions::difficulty.eq(difficulty.as_str()));
+    }
+
+    if let Some(author_id) = filter.author_id {
+        query = query.filter(questions::author_id.eq(author_id));
+    }
+
+    query
+}
+
+type SearchRank = Box<dyn BoxableExpression<questions::table, Pg, SqlType = Nullable<Float>>>;
+
+/// How well the search document of each question matches `q`.
+fn search_rank(q: &str) -> SearchRank {
+    Box::new(
+        sql::<Nullable<Float>>("(SELECT ts_rank(document, websearch_to_tsquery('simple', ")
+            .bind::<Text, _>(q.to_string())
+            .sql(")) FROM question_search WHERE question_search.question_id = questions.id)"),
+    )
+}
+
+/// A page of at most `limit` questions after the cursor, in the order of the sort. Each
+/// comes with its search rank when sorting by relevance.
+pub fn search_questions(
+    visible_to: Option<i32>,
+    filter: &QuestionsQueryDto,
+    topic_ids: Option<Vec<i32>>,
+    cursor: Option<QuestionCursor>,
+    limit: i64,
+) -> Result<Vec<(Question, Option<f32>)>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let mut query = filtered_questions(visible_to, filter, topic_ids);
+    let q = filter.search().unwrap_or_default();
+
+    query = match filter.sort {
+        QuestionSort::Newest => {
+            if let Some(cursor) = cursor {
+                query = query.filter(questions::id.lt(cursor.id));
+            }
+            query.order(questions::id.desc())
+        }
+        QuestionSort::Oldest => {
+            if let Some(cursor) = cursor {
+                query = query.filter(questions::id.gt(cursor.id));
+            }
+            query.order(questions::id)
+        }
+        QuestionSort::Question => {
+            if let Some(cursor) = cursor {
+                let question = cursor.question.unwrap_or_default();
+                query = query.filter(
+                    questions::question
+                        .gt(question.clone())
+                        .or(questions::question
+                            .eq(question)
+                            .and(questions::id.gt(cursor.id))),
+                );
+            }
+            query
+                .order(questions::question)
+                .then_order_by(questions::id)
+        }
+        QuestionSort::Relevance => {
+            if let Some(cursor) = cursor {
+                let rank = cursor.rank.unwrap_or_default();
+                query = query.filter(
+                    search_rank(q)
+                        .lt(rank)
+                        .or(search_rank(q).eq(rank).and(questions::id.lt(cursor.id))),
+                );
+            }
+            query
+                .order(search_rank(q).desc())
+                .then_order_by(questions::id.desc())
+        }
+    };
+
+    let rank: SearchRank = match filter.sort {
+        QuestionSort::Relevance => search_rank(q),
+        _ => Box::new(sql::<Nullable<Float>>("NULL")),
+    };
+
+    query
+        .select((questions::all_columns, rank))
+        .limit(limit)
+        .load::<(Question, Option<f32>)>(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+/// Counts of the values `query` selects in its only column, nulls left out, most common
+/// first and ties in value order.
+#[derive(QueryId)]
+struct Counted<Q>(Q);
+
+impl<Q: Query> Query for Counted<Q> {
+    type SqlType = (Q::SqlType, BigInt);
+}
+
+impl<Q: QueryFragment<Pg>> QueryFragment<Pg> for Counted<Q> {
+    fn walk_ast<'b>(&'b self, mut out: AstPass<'_, 'b, Pg>) -> QueryResult<()> {
+        out.push_sql("SELECT value, COUNT(*) FROM (");
+        self.0.walk_ast(out.reborrow())?;
+        out.push_sql(
+            ") AS facet (value) WHERE value IS NOT NULL \
+             GROUP BY value ORDER BY COUNT(*) DESC, value",
+        );
+        Ok(())
+    }
+}
+
+impl<Q> RunQueryDsl<PgConnection> for Counted<Q> {}
+
+fn present<T>(counts: Vec<(Option<T>, i64)>) -> Vec<(T, i64)> {
+    counts
+        .into_iter()
+        .filter_map(|(value, count)| value.map(|value| (value, count)))
+        .collect()
+}
+
+pub struct FacetCounts {
+    pub total: i64,
+    pub tags: Vec<(String, i64)>,
+    pub topics: Vec<(i32, i64)>,
+    pub difficulties: Vec<(String, i64)>,
+    pub authors: Vec<(i32, i64)>,
+}
+
+/// How many of the questions matching the filters fall under each tag, topic, difficulty
+/// and author, all counted by the database.
+pub fn count_facets(
+    visible_to: Option<i32>,
+    filter: &QuestionsQueryDto,
+    topic_ids: Option<Vec<i32>>,
+) -> Result<FacetCounts, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let filtered = || filtered_questions(visible_to, filter, topic_ids.clone());
+
+    let result: QueryResult<FacetCounts> = (|| {
+        Ok(FacetCounts {
+            total: filtered().select(count_star()).get_result(&mut conn)?,
+            tags: Counted(filtered().select(sql::<Text>("unnest(questions.tags)")))
+                .load(&mut conn)?,
+            topics: present(Counted(filtered().select(questions::topic_id)).load(&mut conn)?),
+            difficulties: present(
+                Counted(filtered().select(questions::difficulty)).load(&mut conn)?,
+            ),
+            authors: present(Counted(filtered().select(questions::author_id)).load(&mut conn)?),
+        })
+    })();
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+    use diesel::debug_query;
+
+    use super::*;
+
+    fn filter(query: &str) -> QuestionsQueryDto {
+        web::Query::<QuestionsQueryDto>::from_query(query)
+            .unwrap()
+            .into_inner()
+    }
+
+    #[test]
+    fn facets_are_grouped_by_the_database() {
+        let filter = filter("tags=algebra");
+        let tags = Counted(
+            filtered_questions(None, &filter, None).select(sql::<Text>("unnest(questions.tags)")),
+        );
+
+        let sql = debug_query::<Pg, _>(&tags).to_string();
+        assert!(sql.starts_with("SELECT value, COUNT(*) FROM (SELECT unnest(questions.tags) FROM"));
+        assert!(sql.contains("\"questions\".\"tags\" @>"));
+        assert!(sql.contains("GROUP BY value ORDER BY COUNT(*) DESC, value"));
+    }
+
+    #[test]
+    fn relevance_ranks_by_the_search_document() {
+        assert!(filter("sort=relevance").validate().is_err());
+
+        let filter = filter("q=tides&sort=relevance");
+        assert!(filter.validate().is_ok());
+
+        let query = filtered_questions(None, &filter, None)
+            .order(search_rank("tides").desc())
+            .then_order_by(questions::id.desc());
+        let sql = debug_query::<Pg, _>(&query).to_string();
+
+        assert!(sql.contains(
+            "ORDER BY (SELECT ts_rank(document, websearch_to_tsquery('simple', $2)) \
+             FROM question_search WHERE question_search.question_id = questions.id) DESC , \
+             \"questions\".\"id\" DESC"
+        ));
+    }
 }
@@ -1,36 +1,54 @@
-use crate::errors::ServiceError;
+use crate::{
+    audit, auth::models::LoggedUser, class, errors::ServiceError, exam,
+    policy::SUPERUSER_PERMISSION, topic,
+};
 
 use super::{
-    dto::CreateQuestionInputDto,
-    models::{Answer, Question},
+    dto::{
+        normalize_tag, AnswerDto, CreateQuestionInputDto, FacetCountDto, QuestionCursor,
+        QuestionFacetsDto, QuestionPageOutputDto, QuestionSort, QuestionVersionDiffDto,
+        QuestionVersionDto, QuestionVisibility, QuestionsQueryDto,
+    },
+    models::{Answer, Question, QuestionVersion},
     repository,
 };
 
-pub fn create_question(new_question: CreateQuestionInputDto) -> Result<(), ServiceError> {
-    if new_question.answers.is_empty() {
-        return Err(ServiceError::BadRequest(
-            "Must have at least 1 answer".to_string(),
-        ));
-    }
+/// Validates the question for its type and drops settings that don't apply to it.
+fn checked(
+    mut new_question: CreateQuestionInputDto,
+) -> Result<CreateQuestionInputDto, ServiceError> {
+    new_question.validate().map_err(ServiceError::BadRequest)?;
+    new_question.settings = new_question.settings.for_type(new_question.question_type);
 
-    let correct_answers = new_question.answers.iter().filter(|a| a.is_correct).count();
-    if correct_answers != 1 {
-        return Err(ServiceError::BadRequest(
-            "Must have exactly 1 correct answer".to_string(),
-        ));
+    let mut tags: Vec<String> = new_question
+        .tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+    new_question.tags = tags;
+
+    if new_question.metadata.is_null() {
+        new_question.metadata = serde_json::json!({});
     }
 
-    let mut answers = new_question.answers.clone();
-    answers.sort_by(|a, b| a.answer.cmp(&b.answer));
-    for i in 0..answers.len() - 1 {
-        if answers[i].answer == answers[i + 1].answer {
-            return Err(ServiceError::BadRequest(
-                "Cannot have two answers with the same value".to_string(),
-            ));
+    if let Some(topic_id) = new_question.topic_id {
+        if topic::service::get_topic(topic_id)?.is_none() {
+            return Err(ServiceError::BadRequest("Topic not found".to_string()));
         }
     }
 
-    repository::create_question(new_question)?;
+    Ok(new_question)
+}
+
+pub fn create_question(
+    author_id: i32,
+    new_question: CreateQuestionInputDto,
+) -> Result<(), ServiceError> {
+    let new_question = checked(new_question)?;
+
+    repository::create_question(author_id, new_question)?;
 
     Ok(())
 }
@@ -39,14 +57,68 @@ pub fn get_question_by_id(question_id: i32) -> Result<Option<Question>, ServiceE
     repository::get_question_by_id(question_id)
 }
 
-pub fn delete_question_by_id(question_id: i32) -> Result<(), ServiceError> {
-    let existing = repository::get_question_by_id(question_id)?;
+fn is_author(user_id: i32, question: &Question) -> bool {
+    question.author_id == Some(user_id)
+}
+
+pub fn is_question_author(user_id: i32, question_id: i32) -> Result<bool, ServiceError> {
+    let question = repository::get_question_by_id(question_id)?;
+
+    Ok(question.is_some_and(|question| is_author(user_id, &question)))
+}
+
+/// Whether the user wrote the question or can find it in the shared or public bank.
+pub fn can_read_question(user_id: i32, question_id: i32) -> Result<bool, ServiceError> {
+    let question = match repository::get_question_by_id(question_id)? {
+        Some(question) => question,
+        None => return Ok(false),
+    };
+
+    if is_author(user_id, &question) || question.visibility == QuestionVisibility::Public.as_str() {
+        return Ok(true);
+    }
+
+    match question.author_id {
+        Some(author_id) if question.visibility == QuestionVisibility::Shared.as_str() => {
+            class::service::share_class_staff(user_id, author_id)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Whether the user may put the question in an exam.
+pub fn can_use_question(user: &LoggedUser, question_id: i32) -> Result<bool, ServiceError> {
+    if user.has_permission(SUPERUSER_PERMISSION) {
+        return Ok(true);
+    }
+
+    can_read_question(user.id, question_id)
+}
+
+/// Only the author and admins can change or delete a question.
+fn get_editable_question(user: &LoggedUser, question_id: i32) -> Result<Question, ServiceError> {
+    let question = match repository::get_question_by_id(question_id)? {
+        Some(question) => question,
+        None => return Err(ServiceError::BadRequest("Question not found".to_string())),
+    };
+
+    if !is_author(user.id, &question) && !user.has_permission(SUPERUSER_PERMISSION) {
+        return Err(ServiceError::Forbidden);
+    }
+
+    Ok(question)
+}
+
+pub fn delete_question_by_id(user: &LoggedUser, question_id: i32) -> Result<(), ServiceError> {
+    get_editable_question(user, question_id)?;
 
-    if existing.is_none() {
-        return Err(ServiceError::BadRequest("Question not found".to_string()));
+    if !exam::service::list_classes_using_question(question_id)?.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Question is used in exams, remove it from them first".to_string(),
+        ));
     }
 
-    repository::delete_question_by_id(question_id)?;
+    repository::delete_question_by_id(user.id, question_id)?;
 
     Ok(())
 }
@@ -55,41 +127,263 @@ pub fn list_answers_by_question_id(question_id: i32) -> Result<Vec<Answer>, Serv
     repository::list_answers_by_question_id(question_id)
 }
 
-pub fn list_questions() -> Result<Vec<Question>, ServiceError> {
-    repository::list_questions()
+/// Admins search every question, everyone else their own plus the shared and public ones.
+fn visible_to(user: &LoggedUser) -> Option<i32> {
+    if user.has_permission(SUPERUSER_PERMISSION) {
+        None
+    } else {
+        Some(user.id)
+    }
 }
 
-pub fn update_question(question_id: i32, new_question: CreateQuestionInputDto) -> Result<(), ServiceError> {
-    if new_question.answers.is_empty() {
-        return Err(ServiceError::BadRequest(
-            "Must have at least 1 answer".to_string(),
-        ));
-    }
+fn topic_ids(filter: &QuestionsQueryDto) -> Result<Option<Vec<i32>>, ServiceError> {
+    filter.topic_id.map(topic::service::subtree_ids).transpose()
+}
 
-    let correct_answers = new_question.answers.iter().filter(|a| a.is_correct).count();
-    if correct_answers != 1 {
-        return Err(ServiceError::BadRequest(
-            "Must have exactly 1 correct answer".to_string(),
-        ));
+pub fn list_questions(
+    user: &LoggedUser,
+    filter: QuestionsQueryDto,
+) -> Result<QuestionPageOutputDto, ServiceError> {
+    filter.validate().map_err(ServiceError::BadRequest)?;
+
+    let cursor = match filter.cursor.as_deref() {
+        Some(cursor) => {
+            let cursor = QuestionCursor::decode(cursor).map_err(ServiceError::BadRequest)?;
+            let incomplete = match filter.sort {
+                QuestionSort::Question => cursor.question.is_none(),
+                QuestionSort::Relevance => cursor.rank.is_none(),
+                _ => false,
+            };
+            if incomplete {
+                return Err(ServiceError::BadRequest("Invalid cursor".to_string()));
+            }
+            Some(cursor)
+        }
+        None => None,
+    };
+
+    let limit = filter.limit.unwrap_or(QuestionsQueryDto::DEFAULT_LIMIT);
+
+    // One extra row tells whether there is a next page.
+    let mut questions = repository::search_questions(
+        visible_to(user),
+        &filter,
+        topic_ids(&filter)?,
+        cursor,
+        limit + 1,
+    )?;
+
+    let next_cursor = if questions.len() as i64 > limit {
+        questions.truncate(limit as usize);
+        questions.last().map(|(last, rank)| {
+            QuestionCursor {
+                id: last.id,
+                question: (filter.sort == QuestionSort::Question).then(|| last.question.clone()),
+                rank: *rank,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    Ok(QuestionPageOutputDto {
+        questions: questions
+            .into_iter()
+            .map(|(question, _)| question)
+            .collect(),
+        next_cursor,
+    })
+}
+
+pub fn get_question_facets(
+    user: &LoggedUser,
+    filter: QuestionsQueryDto,
+) -> Result<QuestionFacetsDto, ServiceError> {
+    filter.validate().map_err(ServiceError::BadRequest)?;
+
+    let counts = repository::count_facets(visible_to(user), &filter, topic_ids(&filter)?)?;
+
+    Ok(QuestionFacetsDto {
+        total: counts.total as usize,
+        tags: facet_counts(counts.tags),
+        topics: facet_counts(counts.topics),
+        difficulties: facet_counts(counts.difficulties),
+        authors: facet_counts(counts.authors),
+    })
+}
+
+fn facet_counts<T>(counts: Vec<(T, i64)>) -> Vec<FacetCountDto<T>> {
+    counts
+        .into_iter()
+        .map(|(value, count)| FacetCountDto {
+            value,
+            count: count as usize,
+        })
+        .collect()
+}
+
+pub fn update_question(
+    user: &LoggedUser,
+    question_id: i32,
+    new_question: CreateQuestionInputDto,
+) -> Result<(), ServiceError> {
+    let new_question = checked(new_question)?;
+
+    get_editable_question(user, question_id)?;
+
+    repository::update_question(user.id, question_id, new_question)?;
+
+    Ok(())
+}
+
+fn version_dto(version: QuestionVersion, answers: Vec<Answer>) -> QuestionVersionDto {
+    QuestionVersionDto {
+        version: version.version,
+        question_type: version.question_type(),
+        settings: version.settings(),
+        question: version.question,
+        answers: answers
+            .into_iter()
+            .map(|answer| AnswerDto {
+                id: answer.id,
+                answer: answer.answer,
+                is_correct: Some(answer.is_correct),
+            })
+            .collect(),
+        created_by: version.created_by,
+        created_at: version.created_at,
     }
+}
+
+pub fn get_version_by_id(
+    question_version_id: i32,
+) -> Result<Option<QuestionVersion>, ServiceError> {
+    repository::get_version_by_id(question_version_id)
+}
+
+/// Every version of the question, oldest first.
+pub fn list_versions(question_id: i32) -> Result<Vec<QuestionVersionDto>, ServiceError> {
+    Ok(repository::list_versions(question_id)?
+        .into_iter()
+        .map(|(version, answers)| version_dto(version, answers))
+        .collect())
+}
 
-    let mut answers = new_question.answers.clone();
-    answers.sort_by(|a, b| a.answer.cmp(&b.answer));
-    for i in 0..answers.len() - 1 {
-        if answers[i].answer == answers[i + 1].answer {
-            return Err(ServiceError::BadRequest(
-                "Cannot have two answers with the same value".to_string(),
-            ));
+pub fn diff_versions(
+    question_id: i32,
+    from: i32,
+    to: i32,
+) -> Result<QuestionVersionDiffDto, ServiceError> {
+    let mut versions = Vec::new();
+
+    for version in [from, to] {
+        match repository::get_version(question_id, version)? {
+            Some((version, answers)) => versions.push(version_dto(version, answers)),
+            None => {
+                return Err(ServiceError::BadRequest(format!(
+                    "Version {} not found",
+                    version
+                )))
+            }
         }
     }
 
-    let existing = repository::get_question_by_id(question_id)?;
+    let to = versions.pop().unwrap();
+    let from = versions.pop().unwrap();
+
+    Ok(diff(from, to))
+}
+
+fn diff(from: QuestionVersionDto, to: QuestionVersionDto) -> QuestionVersionDiffDto {
+    let fields = |version: &QuestionVersionDto| {
+        serde_json::json!({
+            "question": version.question,
+            "question_type": version.question_type,
+            "settings": version.settings,
+        })
+    };
+    let (before, after) = audit::model::diff(fields(&from), fields(&to));
+
+    let missing_from = |answers: &[AnswerDto], others: &[AnswerDto]| -> Vec<AnswerDto> {
+        answers
+            .iter()
+            .filter(|answer| {
+                !others.iter().any(|other| {
+                    other.answer == answer.answer && other.is_correct == answer.is_correct
+                })
+            })
+            .cloned()
+            .collect()
+    };
+
+    QuestionVersionDiffDto {
+        from: from.version,
+        to: to.version,
+        before,
+        after,
+        removed_answers: missing_from(&from.answers, &to.answers),
+        added_answers: missing_from(&to.answers, &from.answers),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::question::dto::{QuestionSettings, QuestionType};
 
-    if existing.is_none() {
-        return Err(ServiceError::BadRequest("Question not found".to_string()));
+    fn version(version: i32, question: &str, answers: &[(&str, bool)]) -> QuestionVersionDto {
+        QuestionVersionDto {
+            version,
+            question: question.to_string(),
+            question_type: QuestionType::SingleChoice,
+            settings: QuestionSettings::default(),
+            answers: answers
+                .iter()
+                .enumerate()
+                .map(|(i, (answer, is_correct))| AnswerDto {
+                    id: version * 10 + i as i32,
+                    answer: answer.to_string(),
+                    is_correct: Some(*is_correct),
+                })
+                .collect(),
+            created_by: None,
+            created_at: chrono::NaiveDateTime::default(),
+        }
     }
 
-    repository::update_question(question_id, new_question)?;
+    #[test]
+    fn diff_shows_changed_fields_and_answers() {
+        let diff = diff(
+            version(1, "2 + 2?", &[("3", true), ("4", false), ("5", false)]),
+            version(2, "2 + 2 =", &[("3", false), ("4", true), ("5", false)]),
+        );
 
-    Ok(())
-}
\ No newline at end of file
+        assert_eq!((diff.from, diff.to), (1, 2));
+        assert_eq!(diff.before, json!({"question": "2 + 2?"}));
+        assert_eq!(diff.after, json!({"question": "2 + 2 ="}));
+
+        let texts = |answers: &[AnswerDto]| {
+            answers
+                .iter()
+                .map(|answer| (answer.answer.clone(), answer.is_correct))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            texts(&diff.removed_answers),
+            vec![
+                ("3".to_string(), Some(true)),
+                ("4".to_string(), Some(false))
+            ]
+        );
+        assert_eq!(
+            texts(&diff.added_answers),
+            vec![
+                ("3".to_string(), Some(false)),
+                ("4".to_string(), Some(true))
+            ]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
+
+use crate::{auth::models::LoggedUser, errors::ServiceError};
+
+use super::{
+    dto::{CreateRoleInputDto, UpdateRoleInputDto},
+    service,
+};
+
+pub async fn list_roles() -> impl Responder {
+    match service::list_roles() {
+        Ok(roles) => HttpResponse::Ok().json(roles),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn list_permissions() -> impl Responder {
+    match service::list_permissions() {
+        Ok(permissions) => HttpResponse::Ok().json(permissions),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn create_role(req: HttpRequest, input: web::Json<CreateRoleInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
+
+    match service::create_role(admin, input.into_inner()) {
+        Ok(role) => HttpResponse::Created().json(role),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn update_role(
+    req: HttpRequest,
+    path: web::Path<String>,
+    input: web::Json<UpdateRoleInputDto>,
+) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
+
+    match service::update_role(admin, &path.into_inner(), input.into_inner()) {
+        Ok(role) => HttpResponse::Ok().json(role),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn delete_role(req: HttpRequest, path: web::Path<String>) -> impl Responder {
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
+
+    match service::delete_role(admin.id, &path.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoleOutputDto {
+    pub name: String,
+    pub description: String,
+    pub built_in: bool,
+    pub permissions: Vec<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateRoleInputDto {
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<String>,
+}
+
+impl CreateRoleInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        let is_valid_name = self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+
+        if self.name.is_empty() || self.name.len() > 50 || !is_valid_name {
+            return Err(
+                "Name must have up to 50 uppercase letters, digits or underscores".to_string(),
+            );
+        }
+
+        if self.description.is_empty() {
+            return Err("Description is required".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRoleInputDto {
+    pub description: Option<String>,
+    pub permissions: Option<Vec<String>>,
+}
+
+impl UpdateRoleInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(description) = &self.description {
+            if description.is_empty() {
+                return Err("Description is required".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,2 +1,5 @@
-pub mod enm;
+pub mod controller;
+pub mod dto;
 pub mod model;
+mod repository;
+pub mod service;
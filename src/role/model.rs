@@ -1,25 +1,36 @@
-use diesel::{deserialize::Queryable, Selectable};
+use diesel::{deserialize::Queryable, Insertable, Selectable};
 use serde::{Deserialize, Serialize};
 
 use crate::schema::*;
 
-use super::enm::RoleEnum;
-
 #[derive(Debug, Serialize, Deserialize, Selectable, Queryable)]
 #[diesel(table_name = roles)]
 pub struct Role {
     pub name: String,
     pub created_at: chrono::NaiveDateTime,
+    pub description: String,
+    /// Seeded roles, which can't be deleted.
+    pub built_in: bool,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = roles)]
+pub struct NewRole<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Selectable, Queryable)]
+#[diesel(table_name = permissions)]
+pub struct Permission {
+    pub name: String,
+    pub description: String,
 }
 
-impl From<Role> for RoleEnum {
-    fn from(value: Role) -> Self {
-        match value.name.as_str() {
-            "ADMIN" => RoleEnum::ADMIN,
-            "STUDENT" => RoleEnum::STUDENT,
-            "TEACHER" => RoleEnum::TEACHER,
-            "MONITOR" => RoleEnum::MONITOR,
-            _ => panic!("Invalid role name"),
-        }
-    }
+#[derive(Insertable, Queryable, Selectable, Debug)]
+#[diesel(table_name = role_permissions)]
+pub struct RolePermission {
+    pub role_name: String,
+    pub permission_name: String,
 }
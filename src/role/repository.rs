@@ -0,0 +1,214 @@
+use std::error::Error;
+
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+
+use crate::{
+    audit::{self, model::NewAuditEvent},
+    db::DB_MANAGER,
+    errors::ServiceError,
+    schema::{permissions, role_permissions, roles, users_roles},
+};
+
+use super::model::{NewRole, Permission, Role, RolePermission};
+
+pub fn list_roles() -> Result<Vec<Role>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    roles::table
+        .order(roles::name)
+        .select(Role::as_select())
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn get_role(role_name: &str) -> Result<Option<Role>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    roles::table
+        .find(role_name)
+        .select(Role::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+/// The names out of `role_names` that exist.
+pub fn find_role_names(role_names: &[String]) -> Result<Vec<String>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    roles::table
+        .filter(roles::name.eq_any(role_names))
+        .select(roles::name)
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn list_permissions() -> Result<Vec<Permission>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    permissions::table
+        .order(permissions::name)
+        .select(Permission::as_select())
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn list_role_permissions(role_names: &[String]) -> Result<Vec<RolePermission>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    role_permissions::table
+        .filter(role_permissions::role_name.eq_any(role_names))
+        .select(RolePermission::as_select())
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+/// The role as recorded in the audit log.
+fn audit_snapshot(
+    tx: &mut PgConnection,
+    role_name: &str,
+) -> diesel::QueryResult<serde_json::Value> {
+    let description: String = roles::table
+        .find(role_name)
+        .select(roles::description)
+        .first(tx)?;
+    let permission_names: Vec<String> = role_permissions::table
+        .filter(role_permissions::role_name.eq(role_name))
+        .order(role_permissions::permission_name)
+        .select(role_permissions::permission_name)
+        .load(tx)?;
+
+    Ok(serde_json::json!({
+        "description": description,
+        "permissions": permission_names,
+    }))
+}
+
+fn replace_permissions(
+    tx: &mut PgConnection,
+    role_name: &str,
+    permission_names: &[String],
+) -> diesel::QueryResult<()> {
+    diesel::delete(role_permissions::table.filter(role_permissions::role_name.eq(role_name)))
+        .execute(tx)?;
+
+    if permission_names.is_empty() {
+        return Ok(());
+    }
+
+    diesel::insert_into(role_permissions::table)
+        .values(
+            permission_names
+                .iter()
+                .map(|permission_name| RolePermission {
+                    role_name: role_name.to_string(),
+                    permission_name: permission_name.clone(),
+                })
+                .collect::<Vec<RolePermission>>(),
+        )
+        .execute(tx)?;
+
+    Ok(())
+}
+
+pub fn create_role(
+    actor_id: i32,
+    new_role: NewRole,
+    permission_names: &[String],
+) -> Result<Role, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<Role, Box<dyn Error>> = conn.transaction(|tx| {
+        let role = diesel::insert_into(roles::table)
+            .values(&new_role)
+            .returning(Role::as_returning())
+            .get_result(tx)?;
+
+        replace_permissions(tx, &role.name, permission_names)?;
+
+        let after = audit_snapshot(tx, &role.name)?;
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(actor_id, "create", "role", &role.name, None, Some(after)),
+        )?;
+
+        Ok(role)
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn update_role(
+    actor_id: i32,
+    role_name: &str,
+    description: Option<&str>,
+    permission_names: Option<&[String]>,
+) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let before = audit_snapshot(tx, role_name)?;
+
+        if let Some(description) = description {
+            diesel::update(roles::table.find(role_name))
+                .set(roles::description.eq(description))
+                .execute(tx)?;
+        }
+
+        if let Some(permission_names) = permission_names {
+            replace_permissions(tx, role_name, permission_names)?;
+        }
+
+        let after = audit_snapshot(tx, role_name)?;
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "update",
+                "role",
+                role_name,
+                Some(before),
+                Some(after),
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn delete_role(actor_id: i32, role_name: &str) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let before = audit_snapshot(tx, role_name)?;
+
+        diesel::delete(roles::table.find(role_name)).execute(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(actor_id, "delete", "role", role_name, Some(before), None),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn is_role_assigned(role_name: &str) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let assigned = users_roles::table
+        .filter(users_roles::role_name.eq(role_name))
+        .select(users_roles::user_id)
+        .first::<i32>(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(assigned.is_some())
+}
@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use crate::{
+    auth::models::LoggedUser, cache::TtlCache, errors::ServiceError, policy::SUPERUSER_PERMISSION,
+};
+
+use super::{
+    dto::{CreateRoleInputDto, RoleOutputDto, UpdateRoleInputDto},
+    model::{NewRole, Permission, Role},
+    repository,
+};
+
+/// Role given to users who sign up or are created without one.
+pub const DEFAULT_ROLE: &str = "STUDENT";
+
+fn role_cache_ttl() -> Duration {
+    let seconds = std::env::var("ROLE_CACHE_SECONDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+
+    Duration::from_secs(seconds)
+}
+
+lazy_static! {
+    // Permissions by role name. Changes done by this instance evict right away, other
+    // instances notice within the ttl.
+    static ref ROLE_PERMISSIONS: TtlCache<String, Vec<String>> = TtlCache::new(role_cache_ttl());
+}
+
+/// Every permission granted by any of the roles.
+pub fn permissions_of(role_names: &[String]) -> Result<Vec<String>, ServiceError> {
+    let mut permissions = Vec::new();
+    let mut missing = Vec::new();
+
+    for role_name in role_names {
+        match ROLE_PERMISSIONS.get(role_name) {
+            Some(role_permissions) => permissions.extend(role_permissions),
+            None => missing.push(role_name.clone()),
+        }
+    }
+
+    if !missing.is_empty() {
+        let role_permissions = repository::list_role_permissions(&missing)?;
+
+        for role_name in missing {
+            let granted: Vec<String> = role_permissions
+                .iter()
+                .filter(|role_permission| role_permission.role_name == role_name)
+                .map(|role_permission| role_permission.permission_name.clone())
+                .collect();
+
+            permissions.extend(granted.iter().cloned());
+            ROLE_PERMISSIONS.insert(role_name, granted);
+        }
+    }
+
+    permissions.sort();
+    permissions.dedup();
+
+    Ok(permissions)
+}
+
+pub fn list_role_names() -> Result<Vec<String>, ServiceError> {
+    Ok(repository::list_roles()?
+        .into_iter()
+        .map(|role| role.name)
+        .collect())
+}
+
+/// Fails with the first of `role_names` that doesn't exist.
+pub fn check_roles_exist(role_names: &[String]) -> Result<(), ServiceError> {
+    let existing = repository::find_role_names(role_names)?;
+
+    match role_names.iter().find(|name| !existing.contains(name)) {
+        Some(name) => Err(ServiceError::BadRequest(format!("Invalid role {}", name))),
+        None => Ok(()),
+    }
+}
+
+fn check_permissions_exist(permission_names: &[String]) -> Result<(), ServiceError> {
+    let existing = repository::list_permissions()?;
+
+    match permission_names
+        .iter()
+        .find(|name| !existing.iter().any(|permission| permission.name == **name))
+    {
+        Some(name) => Err(ServiceError::BadRequest(format!(
+            "Invalid permission {}",
+            name
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Only holders of the superuser permission can hand it out.
+fn can_grant(admin: &LoggedUser, permission_names: &[String]) -> bool {
+    admin.has_permission(SUPERUSER_PERMISSION)
+        || !permission_names
+            .iter()
+            .any(|name| name == SUPERUSER_PERMISSION)
+}
+
+fn check_can_grant(admin: &LoggedUser, permission_names: &[String]) -> Result<(), ServiceError> {
+    match can_grant(admin, permission_names) {
+        true => Ok(()),
+        false => Err(ServiceError::Forbidden),
+    }
+}
+
+/// Fails unless `admin` may give someone every one of the roles.
+pub fn check_can_assign(admin: &LoggedUser, role_names: &[String]) -> Result<(), ServiceError> {
+    check_can_grant(admin, &permissions_of(role_names)?)
+}
+
+fn to_output(role: Role) -> Result<RoleOutputDto, ServiceError> {
+    let permissions = repository::list_role_permissions(std::slice::from_ref(&role.name))?
+        .into_iter()
+        .map(|role_permission| role_permission.permission_name)
+        .collect();
+
+    Ok(RoleOutputDto {
+        name: role.name,
+        description: role.description,
+        built_in: role.built_in,
+        permissions,
+        created_at: role.created_at,
+    })
+}
+
+pub fn list_roles() -> Result<Vec<RoleOutputDto>, ServiceError> {
+    repository::list_roles()?
+        .into_iter()
+        .map(to_output)
+        .collect()
+}
+
+pub fn list_permissions() -> Result<Vec<Permission>, ServiceError> {
+    repository::list_permissions()
+}
+
+pub fn create_role(
+    admin: &LoggedUser,
+    input: CreateRoleInputDto,
+) -> Result<RoleOutputDto, ServiceError> {
+    if repository::get_role(&input.name)?.is_some() {
+        return Err(ServiceError::BadRequest("Role already exists".into()));
+    }
+
+    check_permissions_exist(&input.permissions)?;
+    check_can_grant(admin, &input.permissions)?;
+
+    let role = repository::create_role(
+        admin.id,
+        NewRole {
+            name: &input.name,
+            description: &input.description,
+            created_at: chrono::Utc::now().naive_utc(),
+        },
+        &input.permissions,
+    )?;
+
+    to_output(role)
+}
+
+/// Built-in roles stay as seeded, like with deletes.
+pub fn update_role(
+    admin: &LoggedUser,
+    role_name: &str,
+    input: UpdateRoleInputDto,
+) -> Result<RoleOutputDto, ServiceError> {
+    let role = match repository::get_role(role_name)? {
+        Some(role) => role,
+        None => return Err(ServiceError::BadRequest("Role not found".into())),
+    };
+
+    if role.built_in {
+        return Err(ServiceError::BadRequest(
+            "Built-in roles can't be changed".into(),
+        ));
+    }
+
+    if let Some(permissions) = &input.permissions {
+        check_permissions_exist(permissions)?;
+        check_can_grant(admin, permissions)?;
+    }
+
+    repository::update_role(
+        admin.id,
+        role_name,
+        input.description.as_deref(),
+        input.permissions.as_deref(),
+    )?;
+    ROLE_PERMISSIONS.remove(&role_name.to_string());
+
+    match repository::get_role(role_name)? {
+        Some(role) => to_output(role),
+        None => Err(ServiceError::BadRequest("Role not found".into())),
+    }
+}
+
+/// Built-in roles and roles someone still has can't be deleted.
+pub fn delete_role(admin_id: i32, role_name: &str) -> Result<(), ServiceError> {
+    let role = match repository::get_role(role_name)? {
+        Some(role) => role,
+        None => return Err(ServiceError::BadRequest("Role not found".into())),
+    };
+
+    if role.built_in {
+        return Err(ServiceError::BadRequest(
+            "Built-in roles can't be deleted".into(),
+        ));
+    }
+
+    if repository::is_role_assigned(role_name)? {
+        return Err(ServiceError::BadRequest(
+            "Role is still assigned to users".into(),
+        ));
+    }
+
+    repository::delete_role(admin_id, role_name)?;
+    ROLE_PERMISSIONS.remove(&role_name.to_string());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn admin(permissions: &[&str]) -> LoggedUser {
+        LoggedUser {
+            id: 1,
+            roles: vec!["ADMIN".to_string()],
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+            jti: String::new(),
+            scopes: None,
+            impersonator_id: None,
+        }
+    }
+
+    #[test]
+    fn only_superusers_can_grant_the_superuser_permission() {
+        let admin_role = vec![SUPERUSER_PERMISSION.to_string()];
+        let teacher_role = vec!["classes:teach".to_string(), "exams:manage".to_string()];
+
+        let user_manager = admin(&["users:manage"]);
+        assert!(!can_grant(&user_manager, &admin_role));
+        assert!(can_grant(&user_manager, &teacher_role));
+        assert!(matches!(
+            check_can_grant(&user_manager, &admin_role),
+            Err(ServiceError::Forbidden)
+        ));
+
+        let superuser = admin(&[SUPERUSER_PERMISSION]);
+        assert!(can_grant(&superuser, &admin_role));
+        assert!(check_can_grant(&superuser, &admin_role).is_ok());
+    }
+}
@@ -1,5 +1,11 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
 diesel::table! {
     answers (id) {
         id -> Int4,
@@ -7,6 +13,35 @@ diesel::table! {
         is_correct -> Bool,
         question_id -> Int4,
         created_at -> Timestamp,
+        question_version_id -> Int4,
+    }
+}
+
+diesel::table! {
+    api_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Text,
+        prefix -> Text,
+        token_hash -> Text,
+        scopes -> Array<Text>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    audit_events (id) {
+        id -> Int8,
+        actor_id -> Nullable<Int4>,
+        action -> Text,
+        entity_type -> Text,
+        entity_id -> Text,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        created_at -> Timestamp,
     }
 }
 
@@ -17,6 +52,17 @@ diesel::table! {
         url -> Text,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        image_etag -> Nullable<Text>,
+        url_blocked -> Bool,
+    }
+}
+
+diesel::table! {
+    class_staff (class_id, user_id) {
+        class_id -> Int4,
+        user_id -> Int4,
+        role -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -37,11 +83,31 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    email_verification_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    exam_monitors (exam_id, user_id) {
+        exam_id -> Int4,
+        user_id -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     exam_questions (exam_id, question_id) {
         exam_id -> Int4,
         question_id -> Int4,
         created_at -> Timestamp,
+        question_version_id -> Int4,
     }
 }
 
@@ -53,6 +119,104 @@ diesel::table! {
         end_date -> Timestamp,
         class_id -> Int4,
         created_at -> Timestamp,
+        grades_released_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    impersonations (id) {
+        id -> Int4,
+        jti -> Text,
+        actor_id -> Int4,
+        user_id -> Int4,
+        reason -> Text,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    login_attempts (key) {
+        key -> Text,
+        failures -> Int4,
+        last_failure_at -> Timestamp,
+        blocked_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    mfa_challenges (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        failures -> Int4,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    mfa_recovery_codes (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code_hash -> Text,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    oidc_login_states (id) {
+        id -> Int4,
+        state_hash -> Text,
+        provider -> Text,
+        code_verifier -> Text,
+        nonce -> Text,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        link_user_id -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    password_reset_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    permissions (name) {
+        name -> Text,
+        description -> Text,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
+    question_search (question_id) {
+        question_id -> Int4,
+        document -> Tsvector,
+    }
+}
+
+diesel::table! {
+    question_versions (id) {
+        id -> Int4,
+        question_id -> Int4,
+        version -> Int4,
+        question -> Varchar,
+        question_type -> Text,
+        settings -> Jsonb,
+        created_by -> Nullable<Int4>,
+        created_at -> Timestamp,
     }
 }
 
@@ -61,6 +225,22 @@ diesel::table! {
         id -> Int4,
         question -> Varchar,
         created_at -> Timestamp,
+        author_id -> Nullable<Int4>,
+        visibility -> Text,
+        question_type -> Text,
+        settings -> Jsonb,
+        version -> Int4,
+        tags -> Array<Text>,
+        topic_id -> Nullable<Int4>,
+        difficulty -> Nullable<Text>,
+        metadata -> Jsonb,
+    }
+}
+
+diesel::table! {
+    role_permissions (role_name, permission_name) {
+        role_name -> Text,
+        permission_name -> Text,
     }
 }
 
@@ -68,17 +248,40 @@ diesel::table! {
     roles (name) {
         name -> Text,
         created_at -> Timestamp,
+        description -> Text,
+        built_in -> Bool,
     }
 }
 
 diesel::table! {
-    student_answers (id) {
+    sessions (id) {
         id -> Int4,
+        jti -> Text,
         user_id -> Int4,
+        refresh_token_hash -> Text,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        mfa_verified -> Bool,
+        previous_refresh_token_hash -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    student_answers (id) {
+        id -> Int4,
+        user_id -> Nullable<Int4>,
         exam_id -> Int4,
         question_id -> Int4,
-        answer_id -> Int4,
+        answer_id -> Nullable<Int4>,
         created_at -> Timestamp,
+        response -> Nullable<Jsonb>,
+        manual_score -> Nullable<Float4>,
+        feedback -> Nullable<Text>,
+        graded_by -> Nullable<Int4>,
+        graded_at -> Nullable<Timestamp>,
+        question_version_id -> Int4,
     }
 }
 
@@ -89,6 +292,39 @@ diesel::table! {
         email -> Text,
         password -> Text,
         created_at -> Timestamp,
+        locked_until -> Nullable<Timestamp>,
+        email_verified_at -> Nullable<Timestamp>,
+        token_version -> Int4,
+        active -> Bool,
+    }
+}
+
+diesel::table! {
+    topics (id) {
+        id -> Int4,
+        name -> Text,
+        parent_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    totp_credentials (user_id) {
+        user_id -> Int4,
+        secret -> Text,
+        confirmed_at -> Nullable<Timestamp>,
+        last_used_step -> Nullable<Int8>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    user_identities (id) {
+        id -> Int4,
+        user_id -> Int4,
+        provider -> Text,
+        subject -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -99,31 +335,72 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(answers -> question_versions (question_version_id));
 diesel::joinable!(answers -> questions (question_id));
+diesel::joinable!(api_tokens -> users (user_id));
 diesel::joinable!(avatars -> users (user_id));
+diesel::joinable!(class_staff -> classes (class_id));
+diesel::joinable!(class_staff -> users (user_id));
 diesel::joinable!(classes -> users (user_id));
 diesel::joinable!(classes_students -> classes (class_id));
 diesel::joinable!(classes_students -> users (student_id));
+diesel::joinable!(email_verification_tokens -> users (user_id));
+diesel::joinable!(exam_monitors -> exams (exam_id));
+diesel::joinable!(exam_monitors -> users (user_id));
 diesel::joinable!(exam_questions -> exams (exam_id));
+diesel::joinable!(exam_questions -> question_versions (question_version_id));
 diesel::joinable!(exam_questions -> questions (question_id));
 diesel::joinable!(exams -> classes (class_id));
+diesel::joinable!(mfa_challenges -> users (user_id));
+diesel::joinable!(mfa_recovery_codes -> users (user_id));
+diesel::joinable!(oidc_login_states -> users (link_user_id));
+diesel::joinable!(password_reset_tokens -> users (user_id));
+diesel::joinable!(question_search -> questions (question_id));
+diesel::joinable!(question_versions -> questions (question_id));
+diesel::joinable!(question_versions -> users (created_by));
+diesel::joinable!(questions -> topics (topic_id));
+diesel::joinable!(questions -> users (author_id));
+diesel::joinable!(role_permissions -> permissions (permission_name));
+diesel::joinable!(role_permissions -> roles (role_name));
+diesel::joinable!(sessions -> users (user_id));
 diesel::joinable!(student_answers -> answers (answer_id));
 diesel::joinable!(student_answers -> exams (exam_id));
+diesel::joinable!(student_answers -> question_versions (question_version_id));
 diesel::joinable!(student_answers -> questions (question_id));
-diesel::joinable!(student_answers -> users (user_id));
+diesel::joinable!(totp_credentials -> users (user_id));
+diesel::joinable!(user_identities -> users (user_id));
 diesel::joinable!(users_roles -> roles (role_name));
 diesel::joinable!(users_roles -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     answers,
+    api_tokens,
+    audit_events,
     avatars,
+    class_staff,
     classes,
     classes_students,
+    email_verification_tokens,
+    exam_monitors,
     exam_questions,
     exams,
+    impersonations,
+    login_attempts,
+    mfa_challenges,
+    mfa_recovery_codes,
+    oidc_login_states,
+    password_reset_tokens,
+    permissions,
+    question_search,
+    question_versions,
     questions,
+    role_permissions,
     roles,
+    sessions,
     student_answers,
+    topics,
+    totp_credentials,
+    user_identities,
     users,
     users_roles,
 );
@@ -0,0 +1,23 @@
+use actix_web::{web, HttpResponse, Responder};
+
+use crate::errors::ServiceError;
+
+use super::{dto::CreateTopicInputDto, service};
+
+pub async fn list_topics() -> impl Responder {
+    match service::list_topics() {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(topics) => HttpResponse::Ok().json(topics),
+    }
+}
+
+pub async fn create_topic(input: web::Json<CreateTopicInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    match service::create_topic(input.into_inner()) {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(topic) => HttpResponse::Created().json(topic),
+    }
+}
@@ -0,0 +1,23 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTopicInputDto {
+    pub name: String,
+    pub parent_id: Option<i32>,
+}
+
+impl CreateTopicInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        let name = self.name.trim();
+
+        if name.is_empty() {
+            return Err("Name is required".to_string());
+        }
+
+        if name.chars().count() > 100 {
+            return Err("Name can't be longer than 100 characters".to_string());
+        }
+
+        Ok(())
+    }
+}
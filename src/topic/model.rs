@@ -0,0 +1,20 @@
+use diesel::{deserialize::Queryable, prelude::Insertable};
+use serde::Serialize;
+
+use crate::schema::topics;
+
+#[derive(Debug, Serialize, Queryable, Clone)]
+pub struct Topic {
+    pub id: i32,
+    pub name: String,
+    /// Topics nest, `None` for the top level ones.
+    pub parent_id: Option<i32>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = topics)]
+pub struct NewTopic<'a> {
+    pub name: &'a str,
+    pub parent_id: Option<i32>,
+}
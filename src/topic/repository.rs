@@ -0,0 +1,56 @@
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl};
+
+use crate::{db::DB_MANAGER, errors::ServiceError, schema::topics};
+
+use super::model::{NewTopic, Topic};
+
+pub fn list_topics() -> Result<Vec<Topic>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    topics::table
+        .order((topics::name, topics::id))
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn get_topic(topic_id: i32) -> Result<Option<Topic>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    topics::table
+        .find(topic_id)
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+/// Topics with the same parent and name, case aside.
+pub fn find_sibling(parent_id: Option<i32>, name: &str) -> Result<Option<Topic>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let mut query = topics::table
+        .filter(
+            diesel::dsl::sql::<diesel::sql_types::Bool>("LOWER(name) = LOWER(")
+                .bind::<diesel::sql_types::Text, _>(name.to_string())
+                .sql(")"),
+        )
+        .into_boxed();
+
+    query = match parent_id {
+        Some(parent_id) => query.filter(topics::parent_id.eq(parent_id)),
+        None => query.filter(topics::parent_id.is_null()),
+    };
+
+    query
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn create_topic(new_topic: NewTopic) -> Result<Topic, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::insert_into(topics::table)
+        .values(&new_topic)
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)
+}
@@ -0,0 +1,91 @@
+use crate::errors::ServiceError;
+
+use super::{
+    dto::CreateTopicInputDto,
+    model::{NewTopic, Topic},
+    repository,
+};
+
+pub fn list_topics() -> Result<Vec<Topic>, ServiceError> {
+    repository::list_topics()
+}
+
+pub fn get_topic(topic_id: i32) -> Result<Option<Topic>, ServiceError> {
+    repository::get_topic(topic_id)
+}
+
+pub fn create_topic(new_topic: CreateTopicInputDto) -> Result<Topic, ServiceError> {
+    let name = new_topic.name.trim();
+
+    if let Some(parent_id) = new_topic.parent_id {
+        if repository::get_topic(parent_id)?.is_none() {
+            return Err(ServiceError::BadRequest(
+                "Parent topic not found".to_string(),
+            ));
+        }
+    }
+
+    if repository::find_sibling(new_topic.parent_id, name)?.is_some() {
+        return Err(ServiceError::BadRequest("Topic already exists".to_string()));
+    }
+
+    repository::create_topic(NewTopic {
+        name,
+        parent_id: new_topic.parent_id,
+    })
+}
+
+/// The topic and every topic nested under it, at any depth.
+pub fn subtree_ids(topic_id: i32) -> Result<Vec<i32>, ServiceError> {
+    Ok(subtree(&repository::list_topics()?, topic_id))
+}
+
+fn subtree(topics: &[Topic], topic_id: i32) -> Vec<i32> {
+    let mut ids = vec![topic_id];
+    let mut i = 0;
+
+    // Parents can't be changed, so there are no cycles to guard against.
+    while let Some(parent_id) = ids.get(i).copied() {
+        ids.extend(
+            topics
+                .iter()
+                .filter(|topic| topic.parent_id == Some(parent_id))
+                .map(|topic| topic.id),
+        );
+        i += 1;
+    }
+
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(id: i32, parent_id: Option<i32>) -> Topic {
+        Topic {
+            id,
+            name: format!("topic {}", id),
+            parent_id,
+            created_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn subtrees_include_every_level_below() {
+        // 1 > 2 > 4, 1 > 3, 5
+        let topics = [
+            topic(1, None),
+            topic(2, Some(1)),
+            topic(3, Some(1)),
+            topic(4, Some(2)),
+            topic(5, None),
+        ];
+
+        let mut ids = subtree(&topics, 1);
+        ids.sort_unstable();
+
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        assert_eq!(subtree(&topics, 4), vec![4]);
+    }
+}
@@ -1,9 +1,15 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder};
 
 use crate::{
+    auth::models::LoggedUser,
     errors::ServiceError,
-    role::enm::RoleEnum,
-    user::{dto::CreateUserInputDto, service},
+    user::{
+        dto::{
+            ChangePasswordInputDto, CreateUserInputDto, DeleteAccountInputDto, ImportUsersQueryDto,
+            ListUsersQueryDto, UpdateProfileInputDto, VerifyEmailInputDto,
+        },
+        service,
+    },
 };
 
 pub async fn create_user(new_user: web::Json<CreateUserInputDto>) -> impl Responder {
@@ -19,16 +25,146 @@ pub async fn create_user(new_user: web::Json<CreateUserInputDto>) -> impl Respon
     HttpResponse::Ok().json(user).into()
 }
 
-pub async fn set_user_roles(path: web::Path<i32>, roles: web::Json<Vec<String>>) -> impl Responder {
+pub async fn set_user_roles(
+    req: HttpRequest,
+    path: web::Path<i32>,
+    roles: web::Json<Vec<String>>,
+) -> impl Responder {
     let user_id = path.into_inner();
-    let roles: Vec<RoleEnum> = roles.into_inner().iter().map(|role| role.into()).collect();
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
 
-    if roles.contains(&RoleEnum::INVALID) {
-        return HttpResponse::from_error(ServiceError::BadRequest("Invalid role".into()));
+    match service::set_user_roles(admin, user_id, roles.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().into(),
+        Err(e) => HttpResponse::from_error(e),
     }
+}
 
-    match service::set_user_roles(user_id, roles) {
-        Ok(_) => HttpResponse::NoContent().into(),
+pub async fn verify_email(input: web::Json<VerifyEmailInputDto>) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    match service::verify_email(input.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn resend_verification_email(req: HttpRequest) -> impl Responder {
+    let ext = req.extensions();
+    let user = match ext.get::<LoggedUser>() {
+        Some(user) => user,
+        None => return HttpResponse::from_error(ServiceError::Unauthorized),
+    };
+
+    match service::resend_verification_email(user.id) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn get_me(req: HttpRequest) -> impl Responder {
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::get_profile(user.id) {
+        Ok(profile) => HttpResponse::Ok().json(profile),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn update_me(
+    req: HttpRequest,
+    input: web::Json<UpdateProfileInputDto>,
+) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::update_profile(user.id, input.into_inner()) {
+        Ok(profile) => HttpResponse::Ok().json(profile),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn change_password(
+    req: HttpRequest,
+    input: web::Json<ChangePasswordInputDto>,
+) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let ext = req.extensions();
+    let user = ext.get::<LoggedUser>().unwrap();
+
+    match service::change_password(user.id, &user.jti, input.into_inner()) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn delete_me(
+    req: HttpRequest,
+    input: web::Json<DeleteAccountInputDto>,
+) -> impl Responder {
+    if let Err(e) = input.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    let user_id = req.extensions().get::<LoggedUser>().unwrap().id;
+
+    match service::delete_account(user_id, input.into_inner()).await {
+        Err(e) => HttpResponse::from_error(e),
+        Ok(_) => HttpResponse::NoContent().finish(),
+    }
+}
+
+pub async fn list_users(query: web::Query<ListUsersQueryDto>) -> impl Responder {
+    if let Err(e) = query.validate() {
+        return HttpResponse::from_error(ServiceError::BadRequest(e));
+    }
+
+    match service::list_users(query.into_inner()) {
+        Ok(page) => HttpResponse::Ok().json(page),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn deactivate_user(req: HttpRequest, path: web::Path<i32>) -> impl Responder {
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
+
+    match service::set_user_active(admin.id, path.into_inner(), false) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn reactivate_user(req: HttpRequest, path: web::Path<i32>) -> impl Responder {
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
+
+    match service::set_user_active(admin.id, path.into_inner(), true) {
+        Ok(_) => HttpResponse::NoContent().finish(),
+        Err(e) => HttpResponse::from_error(e),
+    }
+}
+
+pub async fn import_users(
+    req: HttpRequest,
+    query: web::Query<ImportUsersQueryDto>,
+    csv: String,
+) -> impl Responder {
+    let ext = req.extensions();
+    let admin = ext.get::<LoggedUser>().unwrap();
+
+    match service::import_users(admin, &csv, query.dry_run) {
+        Ok(report) => HttpResponse::Ok().json(report),
         Err(e) => HttpResponse::from_error(e),
     }
 }
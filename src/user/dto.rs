@@ -1,9 +1,38 @@
 use serde::{Deserialize, Serialize};
 
-use crate::role::enm::RoleEnum;
-
 use super::model::User;
 
+const MIN_PASSWORD_LENGTH: usize = 8;
+// Bcrypt ignores anything past 72 bytes.
+const MAX_PASSWORD_BYTES: usize = 72;
+
+/// Rules every new password follows, whether chosen at sign up, on a reset or on a change.
+pub fn validate_password_strength(password: &str) -> Result<(), String> {
+    if password.is_empty() {
+        return Err("Password is required".to_string());
+    }
+
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "Password must have at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+
+    if password.len() > MAX_PASSWORD_BYTES {
+        return Err(format!(
+            "Password must have at most {} bytes",
+            MAX_PASSWORD_BYTES
+        ));
+    }
+
+    if !password.chars().any(char::is_alphabetic) || !password.chars().any(|c| c.is_ascii_digit()) {
+        return Err("Password must have letters and numbers".to_string());
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateUserInputDto {
     pub name: String,
@@ -21,11 +50,7 @@ impl CreateUserInputDto {
             return Err("Email is required".to_string());
         }
 
-        if self.password.is_empty() {
-            return Err("Password is required".to_string());
-        }
-
-        Ok(())
+        validate_password_strength(&self.password)
     }
 }
 
@@ -51,5 +76,179 @@ pub struct UserWithRolesOutputDto {
     pub id: i32,
     pub name: String,
     pub email: String,
-    pub roles: Vec<RoleEnum>,
+    pub roles: Vec<String>,
+    pub email_verified_at: Option<chrono::NaiveDateTime>,
+    pub avatar_url: Option<String>,
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListUsersQueryDto {
+    pub role: Option<String>,
+    pub q: Option<String>,
+    pub created_from: Option<chrono::NaiveDateTime>,
+    pub created_to: Option<chrono::NaiveDateTime>,
+    pub cursor: Option<i32>,
+    pub limit: Option<i64>,
+}
+
+impl ListUsersQueryDto {
+    pub const DEFAULT_LIMIT: i64 = 50;
+    pub const MAX_LIMIT: i64 = 200;
+
+    pub fn validate(&self) -> Result<(), String> {
+        if let (Some(from), Some(to)) = (self.created_from, self.created_to) {
+            if from > to {
+                return Err("created_from must be before created_to".to_string());
+            }
+        }
+
+        if self
+            .limit
+            .is_some_and(|limit| !(1..=Self::MAX_LIMIT).contains(&limit))
+        {
+            return Err(format!("Limit must be between 1 and {}", Self::MAX_LIMIT));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUserOutputDto {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+    pub roles: Vec<String>,
+    pub active: bool,
+    pub email_verified_at: Option<chrono::NaiveDateTime>,
+    pub locked_until: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// A page of users, newest first. `next_cursor` is passed back as `cursor` to get the
+/// next page and is missing on the last one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserPageOutputDto {
+    pub users: Vec<AdminUserOutputDto>,
+    pub next_cursor: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateProfileInputDto {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub current_password: Option<String>,
+}
+
+impl UpdateProfileInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.as_ref().is_some_and(|name| name.is_empty()) {
+            return Err("Name can't be empty".to_string());
+        }
+
+        if let Some(email) = &self.email {
+            if email.is_empty() {
+                return Err("Email can't be empty".to_string());
+            }
+
+            if self.current_password.as_ref().is_none_or(|p| p.is_empty()) {
+                return Err("Current password is required to change the email".to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordInputDto {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+impl ChangePasswordInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.current_password.is_empty() {
+            return Err("Current password is required".to_string());
+        }
+
+        validate_password_strength(&self.new_password)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteAccountInputDto {
+    pub password: String,
+}
+
+impl DeleteAccountInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.password.is_empty() {
+            return Err("Password is required".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportUsersQueryDto {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportRowStatus {
+    Valid,
+    Invalid,
+    Created,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportRowReportDto {
+    pub row: usize,
+    pub email: String,
+    pub status: ImportRowStatus,
+    pub errors: Vec<String>,
+    pub user_id: Option<i32>,
+}
+
+/// Nothing is created unless every row is valid, so a file can be fixed and sent again.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportReportOutputDto {
+    pub dry_run: bool,
+    pub created: usize,
+    pub rows: Vec<ImportRowReportDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyEmailInputDto {
+    pub token: String,
+}
+
+impl VerifyEmailInputDto {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.token.is_empty() {
+            return Err("Token is required".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_need_length_letters_and_numbers() {
+        assert!(validate_password_strength("").is_err());
+        assert!(validate_password_strength("abc123").is_err());
+        assert!(validate_password_strength("abcdefghij").is_err());
+        assert!(validate_password_strength("1234567890").is_err());
+        assert!(validate_password_strength(&format!("a1{}", "b".repeat(80))).is_err());
+        assert!(validate_password_strength("correct horse 42").is_ok());
+    }
 }
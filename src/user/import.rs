@@ -0,0 +1,163 @@
+/// A data row of a provisioning CSV. `row` counts data rows from 1, the header excluded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvUserRow {
+    pub row: usize,
+    pub name: String,
+    pub email: String,
+    pub roles: Vec<String>,
+    pub class_codes: Vec<String>,
+}
+
+/// Splits CSV text (RFC 4180) into records. Fields may be quoted, with `""` for a quote
+/// inside them, and quoted fields may span lines.
+fn parse_records(input: &str) -> Result<Vec<Vec<String>>, String> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(c),
+        }
+    }
+
+    if in_quotes {
+        return Err("Unterminated quoted field".to_string());
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    // Blank lines, usually a trailing one, carry no user.
+    records.retain(|record| record.iter().any(|field| !field.trim().is_empty()));
+
+    Ok(records)
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Reads the rows of a CSV with a `name,email,roles,class_codes` header, in any order.
+/// Only `name` and `email` are required. Roles and class codes are separated by `;`.
+pub fn parse_users_csv(input: &str) -> Result<Vec<CsvUserRow>, String> {
+    let mut records = parse_records(input)?.into_iter();
+
+    let header: Vec<String> = match records.next() {
+        Some(header) => header.iter().map(|h| h.trim().to_lowercase()).collect(),
+        None => return Err("The file is empty".to_string()),
+    };
+
+    let column = |name: &str| header.iter().position(|h| h == name);
+
+    let (name, email) = match (column("name"), column("email")) {
+        (Some(name), Some(email)) => (name, email),
+        _ => return Err("The header must have name and email columns".to_string()),
+    };
+    let roles = column("roles");
+    let class_codes = column("class_codes");
+
+    Ok(records
+        .enumerate()
+        .map(|(index, record)| {
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|column| record.get(column))
+                    .map(|value| value.trim().to_string())
+                    .unwrap_or_default()
+            };
+
+            CsvUserRow {
+                row: index + 1,
+                name: field(Some(name)),
+                email: field(Some(email)),
+                roles: split_list(&field(roles)),
+                class_codes: split_list(&field(class_codes)),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_rows_in_header_order() {
+        let csv = "\u{feff}email,Name,class_codes,roles\r\n\
+                   ana@school.test,Ana,MATH1;PHYS1,STUDENT\r\n\
+                   bob@school.test,Bob,,TEACHER;MONITOR\r\n\
+                   \r\n";
+
+        let rows = parse_users_csv(csv).unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                CsvUserRow {
+                    row: 1,
+                    name: "Ana".to_string(),
+                    email: "ana@school.test".to_string(),
+                    roles: vec!["STUDENT".to_string()],
+                    class_codes: vec!["MATH1".to_string(), "PHYS1".to_string()],
+                },
+                CsvUserRow {
+                    row: 2,
+                    name: "Bob".to_string(),
+                    email: "bob@school.test".to_string(),
+                    roles: vec!["TEACHER".to_string(), "MONITOR".to_string()],
+                    class_codes: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_quoted_fields() {
+        let csv = "name,email\n\"Silva, \"\"Ana\"\"\nMaria\",ana@school.test\n";
+
+        let rows = parse_users_csv(csv).unwrap();
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].name, "Silva, \"Ana\"\nMaria");
+        assert_eq!(rows[0].email, "ana@school.test");
+        assert!(rows[0].roles.is_empty());
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert!(parse_users_csv("").is_err());
+        assert!(parse_users_csv("name,roles\nAna,STUDENT\n").is_err());
+        assert!(parse_users_csv("name,email\n\"Ana,ana@school.test\n").is_err());
+    }
+}
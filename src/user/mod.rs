@@ -1,5 +1,6 @@
 pub mod controller;
 pub mod dto;
+mod import;
 pub mod model;
 pub mod repository;
 pub mod service;
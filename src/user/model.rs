@@ -2,10 +2,13 @@ use crate::{role::model::Role, schema::*};
 use diesel::{
     associations::{Associations, Identifiable},
     deserialize::Queryable,
+    query_builder::AsChangeset,
     Insertable, Selectable,
 };
 use serde::{Deserialize, Serialize};
 
+use super::dto::CreateUserInputDto;
+
 #[derive(Debug, Serialize, Deserialize, Selectable, Queryable)]
 pub struct User {
     pub id: i32,
@@ -13,6 +16,10 @@ pub struct User {
     pub email: String,
     pub password: String,
     pub created_at: chrono::NaiveDateTime,
+    pub locked_until: Option<chrono::NaiveDateTime>,
+    pub email_verified_at: Option<chrono::NaiveDateTime>,
+    pub token_version: i32,
+    pub active: bool,
 }
 
 #[derive(Insertable, Debug)]
@@ -24,6 +31,22 @@ pub struct NewUser<'a> {
     pub created_at: chrono::NaiveDateTime,
 }
 
+/// A user created by a bulk import, with the classes they are enrolled in.
+#[derive(Debug)]
+pub struct ProvisionedUser {
+    pub user: CreateUserInputDto,
+    pub roles: Vec<String>,
+    pub class_ids: Vec<i32>,
+}
+
+#[derive(AsChangeset, Debug, Default)]
+#[diesel(table_name = users)]
+pub struct UpdateUser<'a> {
+    pub name: Option<&'a str>,
+    pub email: Option<&'a str>,
+    pub email_verified_at: Option<Option<chrono::NaiveDateTime>>,
+}
+
 #[derive(Identifiable, Selectable, Queryable, Associations, Debug, Insertable)]
 #[diesel(belongs_to(Role, foreign_key = role_name))]
 #[diesel(belongs_to(User))]
@@ -33,3 +56,22 @@ pub struct UsersRole {
     pub role_name: String,
     pub user_id: i32,
 }
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = email_verification_tokens)]
+pub struct EmailVerificationToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub expires_at: chrono::NaiveDateTime,
+    pub used_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = email_verification_tokens)]
+pub struct NewEmailVerificationToken<'a> {
+    pub user_id: i32,
+    pub token_hash: &'a str,
+    pub expires_at: chrono::NaiveDateTime,
+}
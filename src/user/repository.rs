@@ -4,17 +4,27 @@ use diesel::associations::HasTable;
 use diesel::prelude::*;
 use diesel::{QueryDsl, RunQueryDsl, SelectableHelper, Table};
 
-use super::dto::{CreateUserInputDto, UserWithRolesOutputDto};
-use super::model::{NewUser, User, UsersRole};
+use super::dto::{
+    AdminUserOutputDto, CreateUserInputDto, ListUsersQueryDto, UserWithRolesOutputDto,
+};
+use super::model::{
+    EmailVerificationToken, NewEmailVerificationToken, NewUser, ProvisionedUser, UpdateUser, User,
+    UsersRole,
+};
 
+use crate::audit::{self, model::NewAuditEvent};
 use crate::db::DB_MANAGER;
 use crate::errors::ServiceError;
-use crate::role::enm::RoleEnum;
 use crate::role::model::Role;
 use crate::schema::users::dsl::*;
-use crate::schema::{roles, users_roles};
+use crate::schema::{
+    avatars, classes_students, email_verification_tokens, password_reset_tokens, roles,
+    student_answers, users_roles,
+};
 
-pub fn create_user(user: CreateUserInputDto, roles: Vec<RoleEnum>) -> Result<User, ServiceError> {
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
+pub fn create_user(user: CreateUserInputDto, roles: Vec<String>) -> Result<User, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
     let result: Result<User, Box<dyn Error>> = conn.transaction(|tx| {
@@ -36,7 +46,7 @@ pub fn create_user(user: CreateUserInputDto, roles: Vec<RoleEnum>) -> Result<Use
                     .into_iter()
                     .map(|role| UsersRole {
                         user_id: user.id,
-                        role_name: role.into(),
+                        role_name: role,
                     })
                     .collect::<Vec<UsersRole>>(),
             )
@@ -50,6 +60,83 @@ pub fn create_user(user: CreateUserInputDto, roles: Vec<RoleEnum>) -> Result<Use
     Ok(user)
 }
 
+/// Creates all the users with their roles and enrollments, or none of them.
+pub fn create_provisioned_users(
+    actor_id: i32,
+    provisioned: Vec<ProvisionedUser>,
+) -> Result<Vec<User>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<Vec<User>, Box<dyn Error>> = conn.transaction(|tx| {
+        let mut created = Vec::with_capacity(provisioned.len());
+
+        for provisioned_user in provisioned {
+            let user: User = diesel::insert_into(users::table())
+                .values(&NewUser {
+                    name: &provisioned_user.user.name,
+                    email: &provisioned_user.user.email,
+                    password: &provisioned_user.user.password,
+                    created_at: chrono::Local::now().naive_local(),
+                })
+                .returning(User::as_returning())
+                .get_result(tx)?;
+
+            audit::repository::record(
+                tx,
+                NewAuditEvent::new(
+                    actor_id,
+                    "import",
+                    "user",
+                    user.id,
+                    None,
+                    Some(serde_json::json!({
+                        "name": user.name,
+                        "email": user.email,
+                        "roles": provisioned_user.roles,
+                        "class_ids": provisioned_user.class_ids,
+                    })),
+                ),
+            )?;
+
+            diesel::insert_into(users_roles::table)
+                .values(
+                    provisioned_user
+                        .roles
+                        .into_iter()
+                        .map(|role| UsersRole {
+                            user_id: user.id,
+                            role_name: role,
+                        })
+                        .collect::<Vec<UsersRole>>(),
+                )
+                .execute(tx)?;
+
+            if !provisioned_user.class_ids.is_empty() {
+                diesel::insert_into(classes_students::table)
+                    .values(
+                        provisioned_user
+                            .class_ids
+                            .iter()
+                            .map(|class_id| {
+                                (
+                                    classes_students::class_id.eq(class_id),
+                                    classes_students::student_id.eq(user.id),
+                                )
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                    .execute(tx)?;
+            }
+
+            created.push(user);
+        }
+
+        Ok(created)
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
 pub fn get_user_by_email(user_email: &str) -> Result<Option<User>, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
@@ -63,19 +150,124 @@ pub fn get_user_by_email(user_email: &str) -> Result<Option<User>, ServiceError>
     Ok(user)
 }
 
+/// Whether someone already has the address, however it is capitalized.
+pub fn email_exists_ignoring_case(user_email: &str) -> Result<bool, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::select(diesel::dsl::exists(
+        users.filter(lower(email).eq(lower(user_email))),
+    ))
+    .get_result(&mut conn)
+    .map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn get_user_by_id(user_id: i32) -> Result<Option<User>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let user = users
+        .filter(id.eq(user_id))
+        .select(User::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(user)
+}
+
+pub fn update_password(user_id: i32, new_password: &str) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(users.filter(id.eq(user_id)))
+        .set(password.eq(new_password))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
+
+/// Applies the changes and, when the email changes, bumps the token version and drops the
+/// verification tokens sent to the previous address.
+pub fn update_user(user_id: i32, changes: UpdateUser) -> Result<User, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let email_changed = changes.email.is_some();
+
+    let result: Result<User, Box<dyn Error>> = conn.transaction(|tx| {
+        if email_changed {
+            diesel::delete(
+                email_verification_tokens::table
+                    .filter(email_verification_tokens::user_id.eq(user_id)),
+            )
+            .execute(tx)?;
+
+            // Reset links went to the old address, which may no longer be the user's.
+            diesel::update(
+                password_reset_tokens::table
+                    .filter(password_reset_tokens::user_id.eq(user_id))
+                    .filter(password_reset_tokens::used_at.is_null()),
+            )
+            .set(password_reset_tokens::used_at.eq(chrono::Utc::now().naive_utc()))
+            .execute(tx)?;
+
+            diesel::update(users.filter(id.eq(user_id)))
+                .set(token_version.eq(token_version + 1))
+                .execute(tx)?;
+        }
+
+        let user = diesel::update(users.filter(id.eq(user_id)))
+            .set(&changes)
+            .returning(User::as_returning())
+            .get_result(tx)?;
+
+        Ok(user)
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+/// Deletes the user. Their answers are kept for the exam statistics, without the user.
+pub fn delete_user(user_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        diesel::update(student_answers::table.filter(student_answers::user_id.eq(user_id)))
+            .set(student_answers::user_id.eq(None::<i32>))
+            .execute(tx)?;
+
+        diesel::delete(users.filter(id.eq(user_id))).execute(tx)?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn get_token_version(user_id: i32) -> Result<Option<i32>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let version = users
+        .filter(id.eq(user_id))
+        .select(token_version)
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(version)
+}
+
 pub fn get_user_with_roles_by_id(
     id_to_find: i32,
 ) -> Result<Option<UserWithRolesOutputDto>, ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
-    let user = users
+    let user: Option<(User, Option<String>)> = users
+        .left_join(avatars::table.on(avatars::user_id.eq(id).and(avatars::url_blocked.eq(false))))
         .filter(id.eq(id_to_find))
-        .select(User::as_select())
+        .select((User::as_select(), avatars::url.nullable()))
         .first(&mut conn)
         .optional()
         .map_err(|_| ServiceError::InternalServerError)?;
 
-    let user = match user {
+    let (user, avatar_url) = match user {
         Some(user) => user,
         None => return Ok(None),
     };
@@ -91,23 +283,152 @@ pub fn get_user_with_roles_by_id(
         id: user.id,
         name: user.name,
         email: user.email,
-        roles: roles.into_iter().map(|role| role.into()).collect(),
+        roles: roles.into_iter().map(|role| role.name).collect(),
+        email_verified_at: user.email_verified_at,
+        avatar_url,
+        active: user.active,
     }))
 }
 
-pub fn set_user_roles(user_id: i32, roles: Vec<RoleEnum>) -> Result<(), ServiceError> {
+/// Newest users first, `limit` of them after the `cursor` id.
+pub fn list_users(
+    filter: &ListUsersQueryDto,
+    limit: i64,
+) -> Result<Vec<AdminUserOutputDto>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let mut query = users.select(User::as_select()).into_boxed();
+
+    if let Some(role) = &filter.role {
+        query = query.filter(
+            id.eq_any(
+                users_roles::table
+                    .filter(users_roles::role_name.eq(role))
+                    .select(users_roles::user_id),
+            ),
+        );
+    }
+
+    if let Some(search) = filter.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        let escaped = search
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let pattern = format!("%{}%", escaped);
+        query = query.filter(name.ilike(pattern.clone()).or(email.ilike(pattern)));
+    }
+
+    if let Some(created_from) = filter.created_from {
+        query = query.filter(created_at.ge(created_from));
+    }
+
+    if let Some(created_to) = filter.created_to {
+        query = query.filter(created_at.lt(created_to));
+    }
+
+    if let Some(cursor) = filter.cursor {
+        query = query.filter(id.lt(cursor));
+    }
+
+    let page: Vec<User> = query
+        .order(id.desc())
+        .limit(limit)
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    let user_roles: Vec<UsersRole> = users_roles::table
+        .filter(users_roles::user_id.eq_any(page.iter().map(|user| user.id)))
+        .select(UsersRole::as_select())
+        .load(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(page
+        .into_iter()
+        .map(|user| AdminUserOutputDto {
+            roles: user_roles
+                .iter()
+                .filter(|user_role| user_role.user_id == user.id)
+                .map(|user_role| user_role.role_name.clone())
+                .collect(),
+            id: user.id,
+            name: user.name,
+            email: user.email,
+            active: user.active,
+            email_verified_at: user.email_verified_at,
+            locked_until: user.locked_until,
+            created_at: user.created_at,
+        })
+        .collect())
+}
+
+/// Also bumps the token version, so tokens with rich claims notice the change.
+pub fn set_user_active(actor_id: i32, user_id: i32, is_active: bool) -> Result<(), ServiceError> {
     let mut conn = DB_MANAGER.lock().unwrap().get_database();
 
     let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let was_active: bool = users.find(user_id).select(active).first(tx)?;
+
+        diesel::update(users.filter(id.eq(user_id)))
+            .set((active.eq(is_active), token_version.eq(token_version + 1)))
+            .execute(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                if is_active {
+                    "reactivate"
+                } else {
+                    "deactivate"
+                },
+                "user",
+                user_id,
+                Some(serde_json::json!({ "active": was_active })),
+                Some(serde_json::json!({ "active": is_active })),
+            ),
+        )?;
+
+        Ok(())
+    });
+
+    result.map_err(|_| ServiceError::InternalServerError)
+}
+
+pub fn set_user_roles(actor_id: i32, user_id: i32, roles: Vec<String>) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let result: Result<(), Box<dyn Error>> = conn.transaction(|tx| {
+        let previous_roles: Vec<String> = users_roles::table
+            .filter(users_roles::user_id.eq(user_id))
+            .order(users_roles::role_name)
+            .select(users_roles::role_name)
+            .load(tx)?;
+
+        audit::repository::record(
+            tx,
+            NewAuditEvent::new(
+                actor_id,
+                "set_roles",
+                "user",
+                user_id,
+                Some(serde_json::json!({ "roles": previous_roles })),
+                Some(serde_json::json!({ "roles": roles })),
+            ),
+        )?;
+
         diesel::delete(users_roles::table.filter(users_roles::user_id.eq(user_id))).execute(tx)?;
 
+        diesel::update(users.filter(id.eq(user_id)))
+            .set(token_version.eq(token_version + 1))
+            .execute(tx)?;
+
         diesel::insert_into(users_roles::table)
             .values(
                 roles
                     .into_iter()
                     .map(|role| UsersRole {
                         user_id,
-                        role_name: role.into(),
+                        role_name: role,
                     })
                     .collect::<Vec<UsersRole>>(),
             )
@@ -120,3 +441,76 @@ pub fn set_user_roles(user_id: i32, roles: Vec<RoleEnum>) -> Result<(), ServiceE
 
     Ok(())
 }
+
+pub fn create_email_verification_token(
+    new_token: NewEmailVerificationToken,
+) -> Result<EmailVerificationToken, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let token = diesel::insert_into(email_verification_tokens::table)
+        .values(&new_token)
+        .returning(EmailVerificationToken::as_returning())
+        .get_result(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(token)
+}
+
+pub fn get_email_verification_token_by_hash(
+    hash: &str,
+) -> Result<Option<EmailVerificationToken>, ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    let token = email_verification_tokens::table
+        .filter(email_verification_tokens::token_hash.eq(hash))
+        .select(EmailVerificationToken::as_select())
+        .first(&mut conn)
+        .optional()
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(token)
+}
+
+pub fn verify_email(token_id: i32, user_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+    let now = chrono::Utc::now().naive_utc();
+
+    let result: Result<bool, Box<dyn Error>> = conn.transaction(|tx| {
+        let consumed = diesel::update(
+            email_verification_tokens::table
+                .find(token_id)
+                .filter(email_verification_tokens::used_at.is_null()),
+        )
+        .set(email_verification_tokens::used_at.eq(now))
+        .execute(tx)?;
+
+        if consumed == 0 {
+            return Ok(false);
+        }
+
+        diesel::update(users.filter(id.eq(user_id)))
+            .set(email_verified_at.eq(now))
+            .execute(tx)?;
+
+        Ok(true)
+    });
+
+    match result {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ServiceError::BadRequest(
+            "Invalid or expired verification token".into(),
+        )),
+        Err(_) => Err(ServiceError::InternalServerError),
+    }
+}
+
+pub fn set_email_verified(user_id: i32) -> Result<(), ServiceError> {
+    let mut conn = DB_MANAGER.lock().unwrap().get_database();
+
+    diesel::update(users.filter(id.eq(user_id)))
+        .set(email_verified_at.eq(chrono::Utc::now().naive_utc()))
+        .execute(&mut conn)
+        .map_err(|_| ServiceError::InternalServerError)?;
+
+    Ok(())
+}
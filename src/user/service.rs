@@ -1,8 +1,31 @@
-use std::vec;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+    vec,
+};
 
-use crate::{auth::crypto, errors::ServiceError, role::enm::RoleEnum, user::repository};
+use lazy_static::lazy_static;
 
-use super::dto::{CreateUserInputDto, CreateUserOutputDto, UserWithRolesOutputDto};
+use crate::{
+    auth::{self, crypto, models::LoggedUser},
+    avatar,
+    cache::TtlCache,
+    class,
+    errors::ServiceError,
+    mailer::{Mail, MAILER},
+    role,
+    user::repository,
+};
+
+use super::{
+    dto::{
+        ChangePasswordInputDto, CreateUserInputDto, CreateUserOutputDto, DeleteAccountInputDto,
+        ImportReportOutputDto, ImportRowReportDto, ImportRowStatus, ListUsersQueryDto,
+        UpdateProfileInputDto, UserPageOutputDto, UserWithRolesOutputDto, VerifyEmailInputDto,
+    },
+    import,
+    model::{NewEmailVerificationToken, ProvisionedUser, UpdateUser, User},
+};
 
 pub fn create_user(user: CreateUserInputDto) -> Result<CreateUserOutputDto, ServiceError> {
     let existing_user = repository::get_user_by_email(&user.email)?;
@@ -16,12 +39,182 @@ pub fn create_user(user: CreateUserInputDto) -> Result<CreateUserOutputDto, Serv
             password: hashed_password,
             ..user
         },
-        vec![RoleEnum::STUDENT],
+        vec![role::service::DEFAULT_ROLE.to_string()],
     )?;
 
+    if send_verification_email(&user).is_err() {
+        log::warn!("could not send the verification email to user {}", user.id);
+    }
+
     Ok(user.into())
 }
 
+/// Creates a student signing in through an identity provider. They get a random password
+/// they don't know, so they can only sign in through the provider or after a reset.
+pub fn create_external_user(
+    name: &str,
+    email: &str,
+    email_verified: bool,
+) -> Result<User, ServiceError> {
+    let hashed_password = crypto::encrypt_password(&crypto::generate_opaque_token(32))?;
+    let user = repository::create_user(
+        CreateUserInputDto {
+            name: name.to_string(),
+            email: email.to_string(),
+            password: hashed_password,
+        },
+        vec![role::service::DEFAULT_ROLE.to_string()],
+    )?;
+
+    if email_verified {
+        repository::set_email_verified(user.id)?;
+    } else if send_verification_email(&user).is_err() {
+        log::warn!("could not send the verification email to user {}", user.id);
+    }
+
+    Ok(user)
+}
+
+/// Creates the users of a CSV export, see `import::parse_users_csv` for the format. Users
+/// without roles get the default one. They get no password, an invitation lets them choose it.
+pub fn import_users(
+    admin: &LoggedUser,
+    csv: &str,
+    dry_run: bool,
+) -> Result<ImportReportOutputDto, ServiceError> {
+    let rows = import::parse_users_csv(csv).map_err(ServiceError::BadRequest)?;
+
+    if rows.is_empty() {
+        return Err(ServiceError::BadRequest("The file has no users".into()));
+    }
+
+    // Nobody knows this password and it is thrown away, so one hash serves every user.
+    let unusable_password = crypto::encrypt_password(&crypto::generate_opaque_token(32))?;
+
+    let known_roles = role::service::list_role_names()?;
+    let mut seen_emails = HashSet::new();
+    let mut class_ids: HashMap<String, Option<i32>> = HashMap::new();
+    let mut reports = Vec::with_capacity(rows.len());
+    let mut provisioned = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        let user = CreateUserInputDto {
+            name: row.name,
+            email: row.email,
+            password: unusable_password.clone(),
+        };
+        let mut errors = Vec::new();
+
+        if let Err(e) = user.validate() {
+            errors.push(e);
+        }
+
+        if !user.email.is_empty() {
+            if !seen_emails.insert(user.email.to_lowercase()) {
+                errors.push("Email appears more than once in the file".to_string());
+            } else if repository::email_exists_ignoring_case(&user.email)? {
+                errors.push("Email already exists".to_string());
+            }
+        }
+
+        let mut roles: Vec<String> = Vec::new();
+        for role in &row.roles {
+            if !known_roles.contains(role) {
+                errors.push(format!("Invalid role {}", role));
+            } else if !roles.contains(role) {
+                roles.push(role.clone());
+            }
+        }
+
+        if row.roles.is_empty() {
+            roles.push(role::service::DEFAULT_ROLE.to_string());
+        }
+
+        match role::service::check_can_assign(admin, &roles) {
+            Ok(_) => (),
+            Err(ServiceError::Forbidden) => {
+                errors.push("Not allowed to assign these roles".to_string())
+            }
+            Err(e) => return Err(e),
+        }
+
+        let mut enrollments = Vec::new();
+        for code in &row.class_codes {
+            let class_id = match class_ids.get(code) {
+                Some(class_id) => *class_id,
+                None => {
+                    let class_id = class::service::get_class_by_code(code)?.map(|c| c.id);
+                    class_ids.insert(code.clone(), class_id);
+                    class_id
+                }
+            };
+
+            match class_id {
+                Some(class_id) if !enrollments.contains(&class_id) => enrollments.push(class_id),
+                Some(_) => (),
+                None => errors.push(format!("Class {} not found", code)),
+            }
+        }
+
+        if !enrollments.is_empty()
+            && !role::service::permissions_of(&roles)?.contains(&"classes:attend".to_string())
+        {
+            errors.push("Only users who attend classes can be enrolled in them".to_string());
+        }
+
+        reports.push(ImportRowReportDto {
+            row: row.row,
+            email: user.email.clone(),
+            status: if errors.is_empty() {
+                ImportRowStatus::Valid
+            } else {
+                ImportRowStatus::Invalid
+            },
+            errors,
+            user_id: None,
+        });
+
+        provisioned.push(ProvisionedUser {
+            user,
+            roles,
+            class_ids: enrollments,
+        });
+    }
+
+    let is_valid = reports
+        .iter()
+        .all(|report| report.status == ImportRowStatus::Valid);
+
+    if dry_run || !is_valid {
+        return Ok(ImportReportOutputDto {
+            dry_run,
+            created: 0,
+            rows: reports,
+        });
+    }
+
+    let users = repository::create_provisioned_users(admin.id, provisioned)?;
+
+    for (report, user) in reports.iter_mut().zip(&users) {
+        report.status = ImportRowStatus::Created;
+        report.user_id = Some(user.id);
+
+        if auth::service::send_invitation(user).is_err() {
+            log::warn!("could not send the invitation to user {}", user.id);
+        }
+    }
+
+    Ok(ImportReportOutputDto {
+        dry_run,
+        created: users.len(),
+        rows: reports,
+    })
+}
+
+pub fn get_user_by_email(email: &str) -> Result<Option<User>, ServiceError> {
+    repository::get_user_by_email(email)
+}
+
 pub fn get_user_with_roles_by_id(
     user_id: i32,
 ) -> Result<Option<UserWithRolesOutputDto>, ServiceError> {
@@ -29,7 +222,291 @@ pub fn get_user_with_roles_by_id(
     Ok(user.into())
 }
 
-pub fn set_user_roles(user_id: i32, roles: Vec<RoleEnum>) -> Result<(), ServiceError> {
-    repository::set_user_roles(user_id, roles)?;
+pub fn get_profile(user_id: i32) -> Result<UserWithRolesOutputDto, ServiceError> {
+    match repository::get_user_with_roles_by_id(user_id)? {
+        Some(user) => Ok(user),
+        None => Err(ServiceError::BadRequest("User not found".into())),
+    }
+}
+
+fn get_user_checking_password(user_id: i32, password: &str) -> Result<User, ServiceError> {
+    let user = match repository::get_user_by_id(user_id)? {
+        Some(user) => user,
+        None => return Err(ServiceError::BadRequest("User not found".into())),
+    };
+
+    if !crypto::verify_password(password, &user.password)? {
+        return Err(ServiceError::BadRequest("Wrong password".into()));
+    }
+
+    Ok(user)
+}
+
+/// Changing the email needs the current password and takes the account back to
+/// unverified until the new address is confirmed.
+pub fn update_profile(
+    user_id: i32,
+    input: UpdateProfileInputDto,
+) -> Result<UserWithRolesOutputDto, ServiceError> {
+    let user = match repository::get_user_by_id(user_id)? {
+        Some(user) => user,
+        None => return Err(ServiceError::BadRequest("User not found".into())),
+    };
+
+    let new_email = input.email.as_deref().filter(|email| *email != user.email);
+
+    if let Some(new_email) = new_email {
+        get_user_checking_password(user_id, input.current_password.as_deref().unwrap_or(""))?;
+
+        if repository::get_user_by_email(new_email)?.is_some() {
+            return Err(ServiceError::BadRequest("Email already exists".into()));
+        }
+    }
+
+    let changes = UpdateUser {
+        name: input.name.as_deref(),
+        email: new_email,
+        email_verified_at: new_email.map(|_| None),
+    };
+
+    if changes.name.is_none() && changes.email.is_none() {
+        return get_profile(user_id);
+    }
+
+    let user = repository::update_user(user_id, changes)?;
+
+    if new_email.is_some() {
+        TOKEN_VERSIONS.remove(&user_id);
+
+        if send_verification_email(&user).is_err() {
+            log::warn!("could not send the verification email to user {}", user.id);
+        }
+    }
+
+    get_profile(user_id)
+}
+
+/// Other sessions are signed out, the one changing the password is kept.
+pub fn change_password(
+    user_id: i32,
+    jti: &str,
+    input: ChangePasswordInputDto,
+) -> Result<(), ServiceError> {
+    get_user_checking_password(user_id, &input.current_password)?;
+
+    let hashed_password = crypto::encrypt_password(&input.new_password)?;
+    repository::update_password(user_id, &hashed_password)?;
+    auth::service::logout_others(user_id, jti)?;
+
     Ok(())
 }
+
+pub async fn delete_account(
+    user_id: i32,
+    input: DeleteAccountInputDto,
+) -> Result<(), ServiceError> {
+    get_user_checking_password(user_id, &input.password)?;
+
+    if !class::service::list_classes_by_owner(user_id)?.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Delete your classes before deleting the account".into(),
+        ));
+    }
+
+    auth::service::logout_all(user_id)?;
+    repository::delete_user(user_id)?;
+    TOKEN_VERSIONS.remove(&user_id);
+
+    // The avatar row went with the account, its stored images have to go separately.
+    if avatar::service::delete_images(user_id).await.is_err() {
+        log::warn!(
+            "images of deleted user {} were left in the blob store",
+            user_id
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether the user only attends classes: no staff seat anywhere and no permission beyond
+/// the ones of the default student role.
+pub fn is_plain_student(user_id: i32) -> Result<bool, ServiceError> {
+    let user = match repository::get_user_with_roles_by_id(user_id)? {
+        Some(user) => user,
+        None => return Ok(false),
+    };
+
+    let student_permissions =
+        role::service::permissions_of(&[role::service::DEFAULT_ROLE.to_string()])?;
+    let has_more = role::service::permissions_of(&user.roles)?
+        .iter()
+        .any(|permission| !student_permissions.contains(permission));
+
+    Ok(!has_more && !class::service::is_staff_anywhere(user_id)?)
+}
+
+pub fn list_users(filter: ListUsersQueryDto) -> Result<UserPageOutputDto, ServiceError> {
+    let limit = filter.limit.unwrap_or(ListUsersQueryDto::DEFAULT_LIMIT);
+
+    // One extra row tells whether there is a next page.
+    let mut users = repository::list_users(&filter, limit + 1)?;
+    let next_cursor = if users.len() as i64 > limit {
+        users.truncate(limit as usize);
+        users.last().map(|user| user.id)
+    } else {
+        None
+    };
+
+    Ok(UserPageOutputDto { users, next_cursor })
+}
+
+/// Deactivated users can't sign in and their sessions and tokens stop working right away.
+pub fn set_user_active(admin_id: i32, user_id: i32, active: bool) -> Result<(), ServiceError> {
+    if admin_id == user_id && !active {
+        return Err(ServiceError::BadRequest(
+            "You can't deactivate yourself".into(),
+        ));
+    }
+
+    if repository::get_user_by_id(user_id)?.is_none() {
+        return Err(ServiceError::BadRequest("User not found".into()));
+    }
+
+    repository::set_user_active(admin_id, user_id, active)?;
+    TOKEN_VERSIONS.remove(&user_id);
+
+    if !active {
+        auth::service::logout_all(user_id)?;
+    }
+
+    Ok(())
+}
+
+pub fn is_active(user_id: i32) -> Result<bool, ServiceError> {
+    let user = repository::get_user_by_id(user_id)?;
+    Ok(user.is_some_and(|user| user.active))
+}
+
+pub fn set_user_roles(
+    admin: &LoggedUser,
+    user_id: i32,
+    mut roles: Vec<String>,
+) -> Result<(), ServiceError> {
+    roles.sort();
+    roles.dedup();
+    role::service::check_roles_exist(&roles)?;
+    role::service::check_can_assign(admin, &roles)?;
+
+    if repository::get_user_by_id(user_id)?.is_none() {
+        return Err(ServiceError::BadRequest("User not found".into()));
+    }
+
+    repository::set_user_roles(admin.id, user_id, roles)?;
+    TOKEN_VERSIONS.remove(&user_id);
+    Ok(())
+}
+
+fn token_version_cache_ttl() -> Duration {
+    let seconds = std::env::var("TOKEN_VERSION_CACHE_SECONDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(30);
+
+    Duration::from_secs(seconds)
+}
+
+lazy_static! {
+    static ref TOKEN_VERSIONS: TtlCache<i32, i32> = TtlCache::new(token_version_cache_ttl());
+}
+
+/// Version of the user's authorization data, bumped every time its roles change.
+pub fn get_token_version(user_id: i32) -> Result<Option<i32>, ServiceError> {
+    if let Some(version) = TOKEN_VERSIONS.get(&user_id) {
+        return Ok(Some(version));
+    }
+
+    let version = repository::get_token_version(user_id)?;
+
+    if let Some(version) = version {
+        TOKEN_VERSIONS.insert(user_id, version);
+    }
+
+    Ok(version)
+}
+
+fn email_verification_ttl() -> chrono::Duration {
+    let hours = std::env::var("EMAIL_VERIFICATION_TTL_HOURS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(48);
+
+    chrono::Duration::hours(hours)
+}
+
+/// When set, unverified users can't reach the class, exam and question routes.
+pub fn is_email_verification_required() -> bool {
+    std::env::var("REQUIRE_EMAIL_VERIFICATION")
+        .map(|value| value == "true")
+        .unwrap_or(false)
+}
+
+pub fn send_verification_email(user: &User) -> Result<(), ServiceError> {
+    let token = crypto::generate_opaque_token(64);
+    repository::create_email_verification_token(NewEmailVerificationToken {
+        user_id: user.id,
+        token_hash: &crypto::hash_token(&token),
+        expires_at: (chrono::Utc::now() + email_verification_ttl()).naive_utc(),
+    })?;
+
+    let mut body = format!(
+        "Hi {},\n\nWelcome to miniprova! Use the token below to confirm your email.\n\n{}\n",
+        user.name, token
+    );
+
+    if let Ok(verify_url) = std::env::var("EMAIL_VERIFICATION_URL") {
+        body.push_str(&format!("\nOr open {}?token={}\n", verify_url, token));
+    }
+
+    MAILER.send(&Mail {
+        to: user.email.clone(),
+        subject: "Confirm your miniprova email".to_string(),
+        body,
+    })
+}
+
+pub fn resend_verification_email(user_id: i32) -> Result<(), ServiceError> {
+    let user = repository::get_user_by_id(user_id)?;
+
+    let user = match user {
+        Some(user) => user,
+        None => return Err(ServiceError::BadRequest("User not found".into())),
+    };
+
+    if user.email_verified_at.is_some() {
+        return Err(ServiceError::BadRequest("Email already verified".into()));
+    }
+
+    send_verification_email(&user)
+}
+
+pub fn verify_email(input: VerifyEmailInputDto) -> Result<(), ServiceError> {
+    let token =
+        repository::get_email_verification_token_by_hash(&crypto::hash_token(&input.token))?;
+
+    let token = match token {
+        Some(token) => token,
+        None => {
+            return Err(ServiceError::BadRequest(
+                "Invalid or expired verification token".into(),
+            ))
+        }
+    };
+
+    if token.used_at.is_some() || token.expires_at < chrono::Utc::now().naive_utc() {
+        return Err(ServiceError::BadRequest(
+            "Invalid or expired verification token".into(),
+        ));
+    }
+
+    repository::verify_email(token.id, token.user_id)
+}